@@ -1,23 +1,33 @@
 use std::env;
 use std::io::Write;
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use acp::Agent;
 use agent_client_protocol as acp;
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
-use tokio::io::AsyncBufReadExt;
-use tokio::process::Command;
-use tokio::sync::mpsc;
+use datafusion::arrow::record_batch::RecordBatch;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio::process::{Child, Command};
 use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
 
-use crate::mcp_server::{start_mcp_http_server, FinalQueryResult};
-use crate::sql_executor::SqlExecutor;
+use crate::agent_backend::{start_backend, system_prompt, AgentBackend, AgentTarget, McpEndpoint};
+use crate::cache::{CacheKey, QueryCache};
+use crate::sql_executor::{batches_to_json, SqlExecutor};
+use crate::telemetry::{QueryTrace, TokenUsage, TraceRecorder};
+use crate::template::QueryTemplate;
 
 #[derive(Debug, Clone)]
 pub struct AcpConfig {
+    /// The agent command, `tcp://` or `unix:` socket, or chat endpoint URL.
     pub agent_command: Option<String>,
+    /// Model requested from a chat completions endpoint.
+    pub model: Option<String>,
     pub debug: bool,
     pub show_messages: bool,
     pub show_sql: bool,
@@ -25,12 +35,27 @@ pub struct AcpConfig {
     pub show_datasources: bool,
     pub timeout_secs: u64,
     pub safe_mode: bool,
+    /// Ask the user before agent-generated mutations run.
+    pub confirm_mutations: bool,
+    /// Directory of the query cache; `None` disables caching.
+    pub cache_dir: Option<PathBuf>,
+    /// How many times a final SQL that fails validation is sent back to the
+    /// agent for repair before giving up.
+    pub max_repairs: u32,
+    /// Also run the final SQL with this `LIMIT` during validation; `None`
+    /// only plans it.
+    pub validation_limit: Option<usize>,
+    /// Print the per-question trace after each answer.
+    pub show_stats: bool,
+    /// Append each per-question trace as a JSON line to this file.
+    pub trace_file: Option<PathBuf>,
 }
 
 impl Default for AcpConfig {
     fn default() -> Self {
         Self {
             agent_command: None,
+            model: None,
             debug: false,
             show_messages: false,
             show_sql: false,
@@ -38,15 +63,37 @@ impl Default for AcpConfig {
             show_datasources: false,
             timeout_secs: 300,
             safe_mode: true,
+            confirm_mutations: false,
+            cache_dir: None,
+            max_repairs: 2,
+            validation_limit: None,
+            show_stats: false,
+            trace_file: None,
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AcpResult {
     pub sql: String,
     pub summary: Option<String>,
     pub datasources: Option<String>,
+    /// The template `sql` is the default instance of, when the agent
+    /// answered with named parameters.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<QueryTemplate>,
+    /// Repair prompts needed before the SQL passed validation. Not cached:
+    /// a reused answer needs none.
+    #[serde(skip)]
+    pub repairs: u32,
+}
+
+/// The agent named by `--agent`, `ACP_AGENT` or the default.
+pub fn agent_setting(config: &AcpConfig) -> String {
+    config
+        .agent_command
+        .clone()
+        .unwrap_or_else(|| env::var("ACP_AGENT").unwrap_or_else(|_| "claude-code".to_string()))
 }
 
 fn resolve_agent_command(agent: &str) -> Result<(String, Vec<String>)> {
@@ -77,207 +124,530 @@ fn resolve_agent_command(agent: &str) -> Result<(String, Vec<String>)> {
     ))
 }
 
-fn read_channel_result(rx: &mut mpsc::Receiver<FinalQueryResult>) -> Option<FinalQueryResult> {
-    rx.try_recv().ok()
+/// Maximum number of result rows from the previous turn that are replayed to
+/// the agent as context for a follow-up question.
+pub const PREVIEW_ROWS: usize = 20;
+
+/// Maximum size in bytes of the replayed result preview.
+const PREVIEW_BYTES: usize = 4_000;
+
+/// What the agent answered last time, replayed into the next prompt so
+/// follow-ups like "now only for 2024" can build on it.
+#[derive(Debug, Clone)]
+struct PreviousTurn {
+    question: String,
+    sql: String,
+    result_preview: Option<String>,
 }
 
-pub async fn run_acp(
-    query: &str,
-    executor: Arc<SqlExecutor>,
-    config: &AcpConfig,
-) -> Result<AcpResult> {
-    let default_agent = env::var("ACP_AGENT").unwrap_or_else(|_| "claude-code".to_string());
-    let agent_setting = config.agent_command.clone().unwrap_or(default_agent);
-
-    let (agent_cmd, agent_args) = resolve_agent_command(&agent_setting)?;
-
-    let (port, shutdown_tx, mut final_result_rx) =
-        start_mcp_http_server(executor, config.safe_mode, config.show_sql).await?;
-
-    let acp_future = run_acp_flow(
-        &agent_cmd,
-        &agent_args,
-        query,
-        port,
-        config.debug,
-        config.show_messages,
-        config.safe_mode,
+fn build_prompt_text(query: &str, previous: Option<&PreviousTurn>) -> String {
+    let Some(previous) = previous else {
+        return query.to_string();
+    };
+
+    let mut text = format!(
+        "Context from the previous question in this conversation:\n\
+        Question: {}\n\
+        Final SQL:\n{}\n",
+        previous.question, previous.sql
     );
+    if let Some(preview) = &previous.result_preview {
+        text.push_str(&format!(
+            "Result preview (first {PREVIEW_ROWS} rows as JSON):\n{preview}\n"
+        ));
+    }
+    text.push_str(&format!("\nFollow-up question: {query}"));
+    text
+}
 
-    let sql = tokio::time::timeout(
-        std::time::Duration::from_secs(config.timeout_secs),
-        acp_future,
+fn build_repair_prompt(sql: &str, error: &anyhow::Error) -> String {
+    format!(
+        "The final SQL you submitted failed validation.\n\
+        SQL:\n{sql}\n\
+        Error:\n{error:#}\n\n\
+        Fix the query and call final_query again with the corrected SQL."
     )
-    .await
-    .context("ACP flow timed out")??;
+}
 
-    let final_result = read_channel_result(&mut final_result_rx);
-    let _ = shutdown_tx.send(());
+fn result_preview(batches: &[RecordBatch]) -> Result<String> {
+    let mut remaining = PREVIEW_ROWS;
+    let mut preview = Vec::new();
+    for batch in batches {
+        if remaining == 0 {
+            break;
+        }
+        let rows = batch.num_rows().min(remaining);
+        preview.push(batch.slice(0, rows));
+        remaining -= rows;
+    }
 
-    if config.show_summary {
-        if let Some(fr) = &final_result {
-            if let Some(summary) = &fr.summary {
-                eprintln!("\n[Summary]\n{}", summary);
-                let _ = std::io::stderr().flush();
-            }
+    let mut json = batches_to_json(&preview)?;
+    if json.len() > PREVIEW_BYTES {
+        let mut end = PREVIEW_BYTES;
+        while !json.is_char_boundary(end) {
+            end -= 1;
         }
+        json.truncate(end);
+        json.push_str("... (truncated)");
     }
+    Ok(json)
+}
 
-    if config.show_datasources {
-        if let Some(fr) = &final_result {
-            if let Some(datasources) = &fr.datasources {
-                eprintln!("\n[Datasources]\n{}", datasources);
-                let _ = std::io::stderr().flush();
+/// A long-lived ACP conversation. Every prompt that is not answered from the
+/// query cache is sent on one agent connection, started on first use.
+///
+/// `ClientSideConnection` is `!Send`, so a session must be used from within a
+/// `tokio::task::LocalSet`.
+pub struct AcpSession {
+    executor: Arc<SqlExecutor>,
+    config: AcpConfig,
+    backend: Option<Box<dyn AgentBackend>>,
+    previous_turn: Option<PreviousTurn>,
+    trace: TraceRecorder,
+    last_trace: Option<QueryTrace>,
+}
+
+/// An ACP agent, the embedded MCP server it explores the catalog through and
+/// one ACP session. The agent is either spawned as a subprocess or reached
+/// over a TCP or Unix socket.
+pub struct AcpConnection {
+    conn: acp::ClientSideConnection,
+    session_id: acp::SessionId,
+    child: Option<Child>,
+    endpoint: McpEndpoint,
+    final_sql: Arc<Mutex<Option<String>>>,
+    cancelled: Arc<AtomicBool>,
+    message_buffer: Arc<Mutex<String>>,
+    trace: TraceRecorder,
+}
+
+impl AcpConnection {
+    /// Spawn or connect to the agent and open an ACP session that uses
+    /// `endpoint`.
+    pub async fn start(
+        target: AgentTarget,
+        endpoint: McpEndpoint,
+        config: &AcpConfig,
+        trace: TraceRecorder,
+    ) -> Result<Self> {
+        match target {
+            AgentTarget::Command(agent) => {
+                let (agent_cmd, agent_args) = resolve_agent_command(&agent)?;
+                let mut child = Command::new(&agent_cmd)
+                    .args(&agent_args)
+                    .stdin(std::process::Stdio::piped())
+                    .stdout(std::process::Stdio::piped())
+                    .stderr(std::process::Stdio::piped())
+                    .kill_on_drop(true)
+                    .spawn()
+                    .with_context(|| format!("Failed to spawn agent '{}'", agent_cmd))?;
+
+                let outgoing = child
+                    .stdin
+                    .take()
+                    .ok_or_else(|| anyhow!("Failed to get agent stdin"))?;
+                let incoming = child
+                    .stdout
+                    .take()
+                    .ok_or_else(|| anyhow!("Failed to get agent stdout"))?;
+
+                if config.debug {
+                    if let Some(stderr) = child.stderr.take() {
+                        let mut lines = tokio::io::BufReader::new(stderr).lines();
+                        tokio::spawn(async move {
+                            while let Ok(Some(line)) = lines.next_line().await {
+                                eprintln!("acp agent stderr: {line}");
+                            }
+                        });
+                    }
+                }
+
+                Self::open(outgoing, incoming, Some(child), endpoint, config, trace).await
+            }
+            AgentTarget::Tcp(addr) => {
+                let stream = TcpStream::connect(&addr)
+                    .await
+                    .with_context(|| format!("Failed to connect to agent at tcp://{addr}"))?;
+                let (incoming, outgoing) = stream.into_split();
+                Self::open(outgoing, incoming, None, endpoint, config, trace).await
             }
+            #[cfg(unix)]
+            AgentTarget::Unix(path) => {
+                let stream = tokio::net::UnixStream::connect(&path)
+                    .await
+                    .with_context(|| {
+                        format!("Failed to connect to agent at unix:{}", path.display())
+                    })?;
+                let (incoming, outgoing) = stream.into_split();
+                Self::open(outgoing, incoming, None, endpoint, config, trace).await
+            }
+            #[cfg(not(unix))]
+            AgentTarget::Unix(_) => Err(anyhow!("Unix sockets are not supported on this platform")),
+            AgentTarget::Chat(url) => Err(anyhow!("'{url}' is a chat endpoint, not an ACP agent")),
         }
     }
 
-    if config.show_sql {
-        eprintln!("\n[Final SQL]\n{}", sql);
-        let _ = std::io::stderr().flush();
+    async fn open<W, R>(
+        outgoing: W,
+        incoming: R,
+        child: Option<Child>,
+        endpoint: McpEndpoint,
+        config: &AcpConfig,
+        trace: TraceRecorder,
+    ) -> Result<Self>
+    where
+        W: AsyncWrite + Unpin + 'static,
+        R: AsyncRead + Unpin + 'static,
+    {
+        let final_sql = Arc::new(Mutex::new(None::<String>));
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let message_buffer = Arc::new(Mutex::new(String::new()));
+
+        let client = AcpClient {
+            final_sql: final_sql.clone(),
+            cancelled: cancelled.clone(),
+            message_buffer: message_buffer.clone(),
+            trace: trace.clone(),
+            debug: config.debug,
+            show_messages: config.show_messages,
+        };
+
+        let (conn, handle_io) = acp::ClientSideConnection::new(
+            client,
+            outgoing.compat_write(),
+            incoming.compat(),
+            |fut| {
+                tokio::task::spawn_local(fut);
+            },
+        );
+
+        tokio::task::spawn_local(handle_io);
+
+        conn.initialize(acp::InitializeRequest::new(acp::ProtocolVersion::LATEST))
+            .await
+            .context("ACP initialize failed")?;
+
+        let mcp_server = acp::McpServerHttp::new("datafusion", &endpoint.url);
+        let mcp_servers = vec![acp::McpServer::Http(mcp_server)];
+
+        let cwd = env::current_dir().unwrap_or_else(|_| std::path::PathBuf::from("/"));
+        let session_meta: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_value(serde_json::json!({
+                "disableBuiltInTools": true,
+                "systemPrompt": {
+                    "append": system_prompt(config.safe_mode)
+                }
+            }))
+            .context("failed to build ACP session metadata")?;
+
+        let new_sess = conn
+            .new_session(
+                acp::NewSessionRequest::new(cwd)
+                    .mcp_servers(mcp_servers)
+                    .meta(session_meta),
+            )
+            .await
+            .context("ACP new_session failed")?;
+
+        Ok(Self {
+            conn,
+            session_id: new_sess.session_id,
+            child,
+            endpoint,
+            final_sql,
+            cancelled,
+            message_buffer,
+            trace,
+        })
     }
+}
+
+#[async_trait(?Send)]
+impl AgentBackend for AcpConnection {
+    async fn prompt(&mut self, text: &str, timeout_secs: u64) -> Result<AcpResult> {
+        *self
+            .final_sql
+            .lock()
+            .map_err(|_| anyhow!("mutex poisoned"))? = None;
+        self.message_buffer
+            .lock()
+            .map_err(|_| anyhow!("mutex poisoned"))?
+            .clear();
+        self.cancelled.store(false, Ordering::SeqCst);
+        self.endpoint.reset();
+
+        let request = acp::PromptRequest::new(self.session_id.clone(), vec![text.into()]);
+
+        let outcome =
+            tokio::time::timeout(Duration::from_secs(timeout_secs), self.conn.prompt(request))
+                .await;
+
+        match outcome {
+            Ok(response) => {
+                let response = response.context("ACP prompt failed")?;
+                if let Some(usage) = response.meta.as_ref().and_then(TokenUsage::from_meta) {
+                    self.trace.add_usage(&usage);
+                }
+            }
+            Err(elapsed) => {
+                self.cancelled.store(true, Ordering::SeqCst);
+                self.conn
+                    .cancel(acp::CancelNotification::new(self.session_id.clone()))
+                    .await
+                    .ok();
+                return Err(elapsed).context("ACP flow timed out");
+            }
+        }
 
-    let sql = final_result.as_ref().map(|r| r.sql.clone()).unwrap_or(sql);
+        let final_result = self.endpoint.final_result();
+        let notified_sql = self
+            .final_sql
+            .lock()
+            .map_err(|_| anyhow!("mutex poisoned"))?
+            .clone();
+
+        let sql = final_result
+            .as_ref()
+            .map(|r| r.sql.clone())
+            .or(notified_sql)
+            .ok_or_else(|| anyhow!("Agent did not call final_query"))?;
+
+        let (summary, datasources, template) = match final_result {
+            Some(r) => (r.summary, r.datasources, r.template),
+            None => (None, None, None),
+        };
+        Ok(AcpResult {
+            sql,
+            summary,
+            datasources,
+            template,
+            repairs: 0,
+        })
+    }
 
-    Ok(AcpResult {
-        sql,
-        summary: final_result.as_ref().and_then(|r| r.summary.clone()),
-        datasources: final_result.and_then(|r| r.datasources),
-    })
+    async fn close(mut self: Box<Self>) {
+        if let Some(child) = self.child.as_mut() {
+            child.kill().await.ok();
+        }
+        self.endpoint.shutdown();
+    }
 }
 
-async fn run_acp_flow(
-    agent_cmd: &str,
-    agent_args: &[String],
-    query: &str,
-    mcp_port: u16,
-    debug: bool,
-    show_messages: bool,
-    safe_mode: bool,
-) -> Result<String> {
-    let final_sql = Arc::new(Mutex::new(None::<String>));
-    let cancelled = Arc::new(AtomicBool::new(false));
-    let message_buffer = Arc::new(Mutex::new(String::new()));
-
-    let client = AcpClient {
-        final_sql: final_sql.clone(),
-        cancelled,
-        message_buffer,
-        debug,
-        show_messages,
-    };
+impl AcpSession {
+    pub fn new(executor: Arc<SqlExecutor>, config: &AcpConfig) -> Self {
+        Self {
+            executor,
+            config: config.clone(),
+            backend: None,
+            previous_turn: None,
+            trace: TraceRecorder::default(),
+            last_trace: None,
+        }
+    }
 
-    let mut child = Command::new(agent_cmd)
-        .args(agent_args)
-        .stdin(std::process::Stdio::piped())
-        .stdout(std::process::Stdio::piped())
-        .stderr(std::process::Stdio::piped())
-        .kill_on_drop(true)
-        .spawn()
-        .with_context(|| format!("Failed to spawn agent '{}'", agent_cmd))?;
-
-    let outgoing = child
-        .stdin
-        .take()
-        .ok_or_else(|| anyhow!("Failed to get agent stdin"))?
-        .compat_write();
-    let incoming = child
-        .stdout
-        .take()
-        .ok_or_else(|| anyhow!("Failed to get agent stdout"))?
-        .compat();
-
-    if debug {
-        if let Some(stderr) = child.stderr.take() {
-            let mut lines = tokio::io::BufReader::new(stderr).lines();
-            tokio::spawn(async move {
-                while let Ok(Some(line)) = lines.next_line().await {
-                    eprintln!("acp agent stderr: {line}");
-                }
-            });
+    /// Ask `query` as the next question of this conversation.
+    ///
+    /// The first question of a conversation is looked up in the query cache
+    /// when one is configured; follow-ups depend on the earlier turns and
+    /// always go to the agent.
+    pub async fn prompt(&mut self, query: &str) -> Result<AcpResult> {
+        self.trace.begin(query, &agent_setting(&self.config));
+        let answer = self.answer(query).await;
+
+        let trace = match &answer {
+            Ok((result, cached)) => {
+                self.trace
+                    .finish(Some(&result.sql), None, result.repairs, *cached)
+            }
+            Err(e) => self.trace.finish(None, Some(e), 0, false),
+        };
+        if self.config.show_stats {
+            eprintln!("\n[Stats]\n{}", trace.render());
+            let _ = std::io::stderr().flush();
         }
+        if let Some(path) = &self.config.trace_file {
+            if let Err(e) = trace.append_to(path) {
+                eprintln!("Warning: {e:#}");
+            }
+        }
+        self.last_trace = Some(trace);
+
+        let (result, _) = answer?;
+        self.report(&result);
+        self.previous_turn = Some(PreviousTurn {
+            question: query.to_string(),
+            sql: result.sql.clone(),
+            result_preview: None,
+        });
+        Ok(result)
     }
 
-    let local = tokio::task::LocalSet::new();
-    let query_owned = query.to_string();
+    /// Answer `query` from the cache or the agent. Returns whether the answer
+    /// came from the cache.
+    async fn answer(&mut self, query: &str) -> Result<(AcpResult, bool)> {
+        let cache = match (&self.config.cache_dir, &self.previous_turn) {
+            (Some(dir), None) => Some(QueryCache::new(dir)),
+            _ => None,
+        };
+        let cache_key = match cache {
+            Some(_) => Some(CacheKey::new(query, &self.executor, self.config.safe_mode).await?),
+            None => None,
+        };
+        let cached = cache
+            .as_ref()
+            .zip(cache_key.as_ref())
+            .and_then(|(cache, key)| cache.get(key));
+
+        match cached {
+            Some(result) => {
+                eprintln!("[Cache] Reusing the cached answer for this question (--no-cache to ask the agent again)");
+                Ok((result, true))
+            }
+            None => {
+                let text = build_prompt_text(query, self.previous_turn.as_ref());
+                let backend = match self.backend.as_mut() {
+                    Some(backend) => backend,
+                    None => self.backend.insert(
+                        start_backend(
+                            &agent_setting(&self.config),
+                            self.executor.clone(),
+                            &self.config,
+                            self.trace.clone(),
+                        )
+                        .await?,
+                    ),
+                };
+                let mut result = backend.prompt(&text, self.config.timeout_secs).await?;
+                while let Err(e) = self
+                    .executor
+                    .validate_sql(
+                        &result.sql,
+                        self.config.safe_mode,
+                        self.config.validation_limit,
+                    )
+                    .await
+                {
+                    if result.repairs == self.config.max_repairs {
+                        return Err(match result.repairs {
+                            0 => e.context("Final SQL failed validation"),
+                            n => e.context(format!(
+                                "Final SQL still failed validation after {n} repair attempts"
+                            )),
+                        });
+                    }
+                    let repairs = result.repairs + 1;
+                    eprintln!(
+                        "[Repair {repairs}/{}] Final SQL failed validation, asking the agent to fix it: {e:#}",
+                        self.config.max_repairs
+                    );
+                    let text = build_repair_prompt(&result.sql, &e);
+                    result = backend.prompt(&text, self.config.timeout_secs).await?;
+                    result.repairs = repairs;
+                }
+                if let Some((cache, key)) = cache.as_ref().zip(cache_key.as_ref()) {
+                    if let Err(e) = cache.put(key, query, &result) {
+                        if self.config.debug {
+                            eprintln!("acp: failed to cache answer: {e:#}");
+                        }
+                    }
+                }
+                Ok((result, false))
+            }
+        }
+    }
 
-    let prompt_result = local
-        .run_until(async move {
-            let (conn, handle_io) = acp::ClientSideConnection::new(
-                client,
-                outgoing,
-                incoming,
-                |fut| {
-                    tokio::task::spawn_local(fut);
-                },
+    fn report(&self, result: &AcpResult) {
+        if result.repairs > 0 {
+            eprintln!(
+                "\n[Repairs] The final SQL passed validation after {} repair attempt(s)",
+                result.repairs
             );
+            let _ = std::io::stderr().flush();
+        }
 
-            tokio::task::spawn_local(handle_io);
+        if self.config.show_summary {
+            if let Some(summary) = &result.summary {
+                eprintln!("\n[Summary]\n{}", summary);
+                let _ = std::io::stderr().flush();
+            }
+        }
 
-            conn.initialize(acp::InitializeRequest::new(acp::ProtocolVersion::LATEST))
-                .await
-                .context("ACP initialize failed")?;
+        if self.config.show_datasources {
+            if let Some(datasources) = &result.datasources {
+                eprintln!("\n[Datasources]\n{}", datasources);
+                let _ = std::io::stderr().flush();
+            }
+        }
 
-            let mcp_url = format!("http://127.0.0.1:{}/mcp", mcp_port);
-            let mcp_server = acp::McpServerHttp::new("datafusion", &mcp_url);
-            let mcp_servers = vec![acp::McpServer::Http(mcp_server)];
+        if self.config.show_sql {
+            eprintln!("\n[Final SQL]\n{}", result.sql);
+            let _ = std::io::stderr().flush();
+        }
+    }
 
-            let cwd = env::current_dir().unwrap_or_else(|_| std::path::PathBuf::from("/"));
-            let mode_rules = if safe_mode {
-                "- Generate ONLY read-only SELECT queries. Never INSERT, UPDATE, DELETE, DROP, CREATE, etc.\n"
-            } else {
-                "- You may use any SQL statements including INSERT, UPDATE, DELETE, CREATE, etc.\n"
-            };
+    /// Continue a conversation from an earlier session whose last answer
+    /// was `sql` for `question`, so follow-ups can refer to it.
+    pub fn resume(&mut self, question: &str, sql: &str) {
+        self.previous_turn = Some(PreviousTurn {
+            question: question.to_string(),
+            sql: sql.to_string(),
+            result_preview: None,
+        });
+    }
 
-            let system_prompt = format!(
-                "You are operating as a SQL generation assistant within DataFusion. {}Always call final_query with your answer.",
-                mode_rules
-            );
+    /// Attach the rows produced by the last answer so the next prompt can
+    /// show them to the agent.
+    pub fn record_result(&mut self, batches: &[RecordBatch]) -> Result<()> {
+        if let Some(turn) = self.previous_turn.as_mut() {
+            turn.result_preview = Some(result_preview(batches)?);
+        }
+        Ok(())
+    }
 
-            let session_meta: serde_json::Map<String, serde_json::Value> =
-                serde_json::from_value(serde_json::json!({
-                    "disableBuiltInTools": true,
-                    "systemPrompt": {
-                        "append": system_prompt
-                    }
-                }))
-                .context("failed to build ACP session metadata")?;
-
-            let new_sess = conn
-                .new_session(
-                    acp::NewSessionRequest::new(cwd)
-                        .mcp_servers(mcp_servers)
-                        .meta(session_meta),
-                )
-                .await
-                .context("ACP new_session failed")?;
-
-            conn.prompt(acp::PromptRequest::new(
-                new_sess.session_id,
-                vec![query_owned.into()],
-            ))
-            .await
-            .context("ACP prompt failed")?;
+    /// The trace of the last question asked, answered or not.
+    pub fn last_trace(&self) -> Option<&QueryTrace> {
+        self.last_trace.as_ref()
+    }
 
-            Ok::<(), anyhow::Error>(())
-        })
-        .await;
+    /// Stop the agent and the MCP server, if they were started.
+    pub async fn close(self) {
+        if let Some(backend) = self.backend {
+            backend.close().await;
+        }
+    }
+}
 
-    child.kill().await.ok();
-    prompt_result?;
+pub async fn run_acp(
+    query: &str,
+    executor: Arc<SqlExecutor>,
+    config: &AcpConfig,
+) -> Result<AcpResult> {
+    run_acp_traced(query, executor, config).await.0
+}
 
-    let maybe_sql = final_sql
-        .lock()
-        .map_err(|_| anyhow!("mutex poisoned"))?
-        .clone()
-        .ok_or_else(|| anyhow!("Agent did not call final_query"));
-    maybe_sql
+/// Like [`run_acp`], also returning the trace of the question.
+pub async fn run_acp_traced(
+    query: &str,
+    executor: Arc<SqlExecutor>,
+    config: &AcpConfig,
+) -> (Result<AcpResult>, Option<QueryTrace>) {
+    let local = tokio::task::LocalSet::new();
+    local
+        .run_until(async move {
+            let mut session = AcpSession::new(executor, config);
+            let result = session.prompt(query).await;
+            let trace = session.last_trace().cloned();
+            session.close().await;
+            (result, trace)
+        })
+        .await
 }
 
 struct AcpClient {
     final_sql: Arc<Mutex<Option<String>>>,
     cancelled: Arc<AtomicBool>,
     message_buffer: Arc<Mutex<String>>,
+    trace: TraceRecorder,
     debug: bool,
     show_messages: bool,
 }
@@ -371,6 +741,10 @@ impl acp::Client for AcpClient {
             return Ok(());
         }
 
+        if let Some(usage) = args.meta.as_ref().and_then(TokenUsage::from_meta) {
+            self.trace.add_usage(&usage);
+        }
+
         match args.update {
             SU::AgentMessageChunk(chunk) => {
                 let text = match chunk.content {
@@ -407,6 +781,11 @@ impl acp::Client for AcpClient {
                         tc.tool_call_id.0, tc.title, tc.status
                     );
                 }
+                self.trace.record_tool_call(
+                    &tc.tool_call_id.0,
+                    &tc.title,
+                    &status_name(&tc.status),
+                );
                 if tc.title.contains("final_query") {
                     if let Some(raw_input) = tc.raw_input {
                         if let Some(sql) = raw_input.get("sql").and_then(|v| v.as_str()) {
@@ -418,10 +797,61 @@ impl acp::Client for AcpClient {
                     }
                 }
             }
-            SU::ToolCallUpdate(_) => {}
+            SU::ToolCallUpdate(update) => {
+                self.trace.update_tool_call(
+                    &update.tool_call_id.0,
+                    update.fields.title.as_deref(),
+                    update.fields.status.as_ref().map(status_name).as_deref(),
+                );
+            }
             _ => {}
         }
 
         Ok(())
     }
 }
+
+/// The wire name of a tool call status, e.g. `in_progress`.
+fn status_name(status: &acp::ToolCallStatus) -> String {
+    serde_json::to_value(status)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_else(|| format!("{status:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use datafusion::arrow::array::Int64Array;
+    use datafusion::arrow::datatypes::{DataType, Field, Schema};
+
+    #[test]
+    fn test_first_prompt_is_the_question() {
+        assert_eq!(build_prompt_text("list products", None), "list products");
+    }
+
+    #[test]
+    fn test_follow_up_prompt_includes_previous_turn() {
+        let previous = PreviousTurn {
+            question: "total sales per year".to_string(),
+            sql: "SELECT year, SUM(amount) FROM sales GROUP BY year".to_string(),
+            result_preview: Some(r#"[{"year":2024,"total":10.0}]"#.to_string()),
+        };
+        let text = build_prompt_text("now only for 2024", Some(&previous));
+        assert!(text.contains("Question: total sales per year"));
+        assert!(text.contains("SELECT year, SUM(amount) FROM sales GROUP BY year"));
+        assert!(text.contains(r#"[{"year":2024,"total":10.0}]"#));
+        assert!(text.ends_with("Follow-up question: now only for 2024"));
+    }
+
+    #[test]
+    fn test_result_preview_limits_rows() {
+        let schema = Arc::new(Schema::new(vec![Field::new("n", DataType::Int64, false)]));
+        let batch =
+            RecordBatch::try_new(schema, vec![Arc::new(Int64Array::from_iter_values(0..100))])
+                .unwrap();
+        let preview = result_preview(&[batch.clone(), batch]).unwrap();
+        let rows: Vec<serde_json::Value> = serde_json::from_str(&preview).unwrap();
+        assert_eq!(rows.len(), PREVIEW_ROWS);
+    }
+}
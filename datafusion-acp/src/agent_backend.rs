@@ -0,0 +1,174 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::acp_client::{AcpConfig, AcpConnection, AcpResult};
+use crate::chat_backend::ChatBackend;
+use crate::mcp_server::{start_mcp_http_server, FinalQueryResult};
+use crate::sql_executor::SqlExecutor;
+use crate::telemetry::TraceRecorder;
+
+/// Something that turns prompts into a final SQL answer, exploring the
+/// catalog through the embedded MCP server's `run_sql`, `list_tables`,
+/// `describe_table` and `final_query` tools.
+///
+/// A backend holds one conversation: each prompt follows the previous ones.
+/// Backends may be `!Send`, so they are used from within a
+/// `tokio::task::LocalSet`.
+#[async_trait(?Send)]
+pub trait AgentBackend {
+    /// Send `text` as the next prompt and wait for the agent to call
+    /// `final_query`.
+    async fn prompt(&mut self, text: &str, timeout_secs: u64) -> Result<AcpResult>;
+
+    /// Stop the agent and the MCP server.
+    async fn close(self: Box<Self>);
+}
+
+/// Where `--agent` points.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AgentTarget {
+    /// An ACP agent spawned as a subprocess, speaking over stdio.
+    Command(String),
+    /// An ACP agent already listening on a TCP socket (`tcp://host:port`).
+    Tcp(String),
+    /// An ACP agent listening on a Unix socket (`unix:/path`).
+    Unix(PathBuf),
+    /// An OpenAI-compatible chat completions endpoint (`http://host/v1`),
+    /// driven with tool calling.
+    Chat(String),
+}
+
+impl AgentTarget {
+    pub fn parse(agent: &str) -> Self {
+        if let Some(addr) = agent.strip_prefix("tcp://") {
+            AgentTarget::Tcp(addr.to_string())
+        } else if let Some(path) = agent
+            .strip_prefix("unix://")
+            .or_else(|| agent.strip_prefix("unix:"))
+        {
+            AgentTarget::Unix(PathBuf::from(path))
+        } else if agent.starts_with("http://") || agent.starts_with("https://") {
+            AgentTarget::Chat(agent.trim_end_matches('/').to_string())
+        } else {
+            AgentTarget::Command(agent.to_string())
+        }
+    }
+}
+
+/// Start the embedded MCP server and the backend `--agent` points to.
+pub async fn start_backend(
+    agent: &str,
+    executor: Arc<SqlExecutor>,
+    config: &AcpConfig,
+    trace: TraceRecorder,
+) -> Result<Box<dyn AgentBackend>> {
+    let start = async {
+        let endpoint = McpEndpoint::start(executor, config, trace.clone()).await?;
+        let backend: Box<dyn AgentBackend> = match AgentTarget::parse(agent) {
+            AgentTarget::Chat(url) => {
+                Box::new(ChatBackend::start(url, endpoint, config, trace).await?)
+            }
+            target => Box::new(AcpConnection::start(target, endpoint, config, trace).await?),
+        };
+        Ok(backend)
+    };
+    tokio::time::timeout(Duration::from_secs(config.timeout_secs), start)
+        .await
+        .context("Agent session start timed out")?
+}
+
+/// The system prompt every backend is given.
+pub fn system_prompt(safe_mode: bool) -> String {
+    let mode_rules = if safe_mode {
+        "- Generate ONLY read-only SELECT queries. Never INSERT, UPDATE, DELETE, DROP, CREATE, etc.\n"
+    } else {
+        "- You may use any SQL statements including INSERT, UPDATE, DELETE, CREATE, etc.\n"
+    };
+    format!(
+        "You are operating as a SQL generation assistant within DataFusion. {}Always call final_query with your answer.",
+        mode_rules
+    )
+}
+
+/// The embedded MCP HTTP server a backend answers through.
+pub struct McpEndpoint {
+    pub url: String,
+    final_result_rx: mpsc::Receiver<FinalQueryResult>,
+    shutdown_tx: Option<oneshot::Sender<()>>,
+}
+
+impl McpEndpoint {
+    async fn start(
+        executor: Arc<SqlExecutor>,
+        config: &AcpConfig,
+        trace: TraceRecorder,
+    ) -> Result<Self> {
+        let (port, shutdown_tx, final_result_rx) = start_mcp_http_server(
+            executor,
+            config.safe_mode,
+            config.confirm_mutations,
+            config.show_sql,
+            trace,
+        )
+        .await?;
+        Ok(Self {
+            url: format!("http://127.0.0.1:{port}/mcp"),
+            final_result_rx,
+            shutdown_tx: Some(shutdown_tx),
+        })
+    }
+
+    /// Forget a `final_query` left over from an earlier prompt.
+    pub fn reset(&mut self) {
+        while self.final_result_rx.try_recv().is_ok() {}
+    }
+
+    /// The `final_query` call made since the last [`reset`](Self::reset).
+    pub fn final_result(&mut self) -> Option<FinalQueryResult> {
+        self.final_result_rx.try_recv().ok()
+    }
+
+    pub fn shutdown(&mut self) {
+        if let Some(tx) = self.shutdown_tx.take() {
+            let _ = tx.send(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_agent_target_parse() {
+        assert_eq!(
+            AgentTarget::parse("claude-code"),
+            AgentTarget::Command("claude-code".to_string())
+        );
+        assert_eq!(
+            AgentTarget::parse("./bin/agent"),
+            AgentTarget::Command("./bin/agent".to_string())
+        );
+        assert_eq!(
+            AgentTarget::parse("tcp://127.0.0.1:4000"),
+            AgentTarget::Tcp("127.0.0.1:4000".to_string())
+        );
+        assert_eq!(
+            AgentTarget::parse("unix:/tmp/agent.sock"),
+            AgentTarget::Unix(PathBuf::from("/tmp/agent.sock"))
+        );
+        assert_eq!(
+            AgentTarget::parse("unix:///tmp/agent.sock"),
+            AgentTarget::Unix(PathBuf::from("/tmp/agent.sock"))
+        );
+        assert_eq!(
+            AgentTarget::parse("http://localhost:11434/v1/"),
+            AgentTarget::Chat("http://localhost:11434/v1".to_string())
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use datafusion::prelude::SessionContext;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::acp_client::AcpResult;
+use crate::sql_executor::SqlExecutor;
+
+/// On-disk cache of agent answers, one JSON file per key.
+///
+/// An answer is only reused for the same normalised question, against tables
+/// with the same schemas and under the same safe-mode setting.
+pub struct QueryCache {
+    dir: PathBuf,
+}
+
+/// Digest of everything an agent answer depends on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CacheKey(String);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheEntry {
+    question: String,
+    #[serde(flatten)]
+    result: AcpResult,
+}
+
+impl CacheKey {
+    pub async fn new(question: &str, executor: &SqlExecutor, safe_mode: bool) -> Result<Self> {
+        let fingerprint = catalog_fingerprint(&executor.ctx).await?;
+        Ok(Self::from_parts(question, &fingerprint, safe_mode))
+    }
+
+    fn from_parts(question: &str, catalog_fingerprint: &str, safe_mode: bool) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(normalize_question(question));
+        hasher.update([0]);
+        hasher.update(catalog_fingerprint);
+        hasher.update([0, u8::from(safe_mode)]);
+        Self(hex(&hasher.finalize()))
+    }
+}
+
+impl QueryCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// `$XDG_CACHE_HOME/datafusion-acp`, falling back to `~/.cache/datafusion-acp`.
+    pub fn default_dir() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CACHE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+        Some(base.join("datafusion-acp"))
+    }
+
+    fn entry_path(&self, key: &CacheKey) -> PathBuf {
+        self.dir.join(format!("{}.json", key.0))
+    }
+
+    /// The cached answer for `key`. Unreadable entries count as misses.
+    pub fn get(&self, key: &CacheKey) -> Option<AcpResult> {
+        let text = std::fs::read_to_string(self.entry_path(key)).ok()?;
+        let entry: CacheEntry = serde_json::from_str(&text).ok()?;
+        Some(entry.result)
+    }
+
+    pub fn put(&self, key: &CacheKey, question: &str, result: &AcpResult) -> Result<()> {
+        std::fs::create_dir_all(&self.dir).with_context(|| {
+            format!("Failed to create cache directory '{}'", self.dir.display())
+        })?;
+        let entry = CacheEntry {
+            question: question.to_string(),
+            result: result.clone(),
+        };
+        let path = self.entry_path(key);
+        // Write then rename so a concurrent reader never sees a partial entry.
+        let tmp = path.with_extension(format!("json.{}.tmp", std::process::id()));
+        std::fs::write(&tmp, serde_json::to_vec_pretty(&entry)?)
+            .with_context(|| format!("Failed to write cache entry '{}'", tmp.display()))?;
+        std::fs::rename(&tmp, &path)
+            .with_context(|| format!("Failed to write cache entry '{}'", path.display()))?;
+        Ok(())
+    }
+
+    /// Remove every cached answer and return how many there were.
+    pub fn clear(&self) -> Result<usize> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!("Failed to read cache directory '{}'", self.dir.display())
+                })
+            }
+        };
+
+        let mut removed = 0;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                std::fs::remove_file(&path)
+                    .with_context(|| format!("Failed to remove '{}'", path.display()))?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+/// Lower-case the question, collapse whitespace and drop trailing punctuation.
+fn normalize_question(question: &str) -> String {
+    question
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+        .trim_end_matches(['?', '.', '!'])
+        .trim_end()
+        .to_string()
+}
+
+/// Digest of the name, column names and column types of every registered
+/// table, excluding `information_schema`.
+pub async fn catalog_fingerprint(ctx: &SessionContext) -> Result<String> {
+    let mut tables = Vec::new();
+    for catalog_name in ctx.catalog_names() {
+        let Some(catalog) = ctx.catalog(&catalog_name) else {
+            continue;
+        };
+        for schema_name in catalog.schema_names() {
+            if schema_name == "information_schema" {
+                continue;
+            }
+            let Some(schema) = catalog.schema(&schema_name) else {
+                continue;
+            };
+            for table_name in schema.table_names() {
+                let Some(table) = schema.table(&table_name).await? else {
+                    continue;
+                };
+                let columns = table
+                    .schema()
+                    .fields()
+                    .iter()
+                    .map(|f| format!("{}:{}:{}", f.name(), f.data_type(), f.is_nullable()))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                tables.push(format!(
+                    "{catalog_name}.{schema_name}.{table_name}({columns})"
+                ));
+            }
+        }
+    }
+    tables.sort();
+
+    let mut hasher = Sha256::new();
+    for table in &tables {
+        hasher.update(table);
+        hasher.update([b'\n']);
+    }
+    Ok(hex(&hasher.finalize()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_data_path;
+
+    fn temp_cache(name: &str) -> QueryCache {
+        let dir = std::env::temp_dir().join(format!(
+            "datafusion_acp_cache_{name}_{}",
+            std::process::id()
+        ));
+        std::fs::remove_dir_all(&dir).ok();
+        QueryCache::new(dir)
+    }
+
+    fn answer(sql: &str) -> AcpResult {
+        AcpResult {
+            sql: sql.to_string(),
+            summary: Some("summary".to_string()),
+            datasources: None,
+            template: None,
+            repairs: 0,
+        }
+    }
+
+    #[test]
+    fn test_normalize_question() {
+        assert_eq!(
+            normalize_question("  What are the   CHEAPEST products?? "),
+            "what are the cheapest products"
+        );
+    }
+
+    #[test]
+    fn test_key_depends_on_question_catalog_and_safe_mode() {
+        let key = CacheKey::from_parts("list products", "abc", true);
+        assert_eq!(key, CacheKey::from_parts("List  products?", "abc", true));
+        assert_ne!(key, CacheKey::from_parts("list orders", "abc", true));
+        assert_ne!(key, CacheKey::from_parts("list products", "def", true));
+        assert_ne!(key, CacheKey::from_parts("list products", "abc", false));
+    }
+
+    #[test]
+    fn test_put_get_clear() {
+        let cache = temp_cache("roundtrip");
+        let key = CacheKey::from_parts("list products", "abc", true);
+        assert_eq!(cache.get(&key), None);
+
+        cache
+            .put(&key, "list products", &answer("SELECT * FROM products"))
+            .unwrap();
+        assert_eq!(cache.get(&key), Some(answer("SELECT * FROM products")));
+
+        assert_eq!(cache.clear().unwrap(), 1);
+        assert_eq!(cache.get(&key), None);
+        assert_eq!(cache.clear().unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_catalog_fingerprint_tracks_schemas() {
+        let exec = SqlExecutor::new().await.unwrap();
+        let empty = catalog_fingerprint(&exec.ctx).await.unwrap();
+
+        exec.register_csv("products", &test_data_path("products.csv"))
+            .await
+            .unwrap();
+        let with_products = catalog_fingerprint(&exec.ctx).await.unwrap();
+        assert_ne!(empty, with_products);
+        assert_eq!(with_products, catalog_fingerprint(&exec.ctx).await.unwrap());
+
+        let other = SqlExecutor::new().await.unwrap();
+        other
+            .register_csv("products", &test_data_path("products_unicode.csv"))
+            .await
+            .unwrap();
+        assert_ne!(
+            with_products,
+            catalog_fingerprint(&other.ctx).await.unwrap()
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::sql_executor::RegisterOptions;
+
+/// A `--catalog` file declaring many tables at once.
+///
+/// ```toml
+/// [[table]]
+/// name = "events"
+/// path = "events/"          # file, directory or glob
+/// format = "parquet"        # optional, detected from the extension
+///
+/// [[table]]
+/// name = "products"
+/// path = "products.tsv"
+/// format = "csv"
+/// delimiter = "\t"
+/// has_header = false
+///
+/// [table.schema]
+/// price = "Float64"
+/// ```
+#[derive(Debug, Deserialize)]
+struct CatalogFile {
+    #[serde(default, rename = "table")]
+    tables: Vec<CatalogTable>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CatalogTable {
+    pub name: String,
+    pub path: String,
+    #[serde(flatten)]
+    pub options: RegisterOptions,
+}
+
+/// Load the table declarations from a catalog file.
+///
+/// Relative table paths are resolved against the catalog file's directory.
+pub fn load_catalog(path: &str) -> Result<Vec<CatalogTable>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read catalog file '{path}'"))?;
+    let catalog: CatalogFile =
+        toml::from_str(&text).with_context(|| format!("Invalid catalog file '{path}'"))?;
+
+    let base = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+    Ok(catalog
+        .tables
+        .into_iter()
+        .map(|mut table| {
+            if Path::new(&table.path).is_relative() {
+                table.path = base.join(&table.path).to_string_lossy().into_owned();
+            }
+            table
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql_executor::{DataFormat, SqlExecutor};
+    use crate::test_util::test_data_path;
+
+    #[test]
+    fn test_load_catalog_resolves_relative_paths() {
+        let tables = load_catalog(&test_data_path("catalog.toml")).unwrap();
+        let names = tables.iter().map(|t| t.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["orders", "suppliers"]);
+        assert_eq!(tables[0].path, test_data_path("orders/"));
+        assert_eq!(tables[1].options.format, Some(DataFormat::Csv));
+        assert_eq!(tables[1].options.delimiter, Some(';'));
+        assert_eq!(tables[1].options.has_header, Some(false));
+    }
+
+    #[test]
+    fn test_load_catalog_missing_file() {
+        assert!(load_catalog("/nonexistent/catalog.toml").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_catalog_tables_register() {
+        let exec = SqlExecutor::new().await.unwrap();
+        for table in load_catalog(&test_data_path("catalog.toml")).unwrap() {
+            exec.register_with_options(&table.name, &table.path, &table.options)
+                .await
+                .unwrap();
+        }
+        let json = exec
+            .execute_sql_json(
+                "SELECT s.column_2 AS supplier, COUNT(*) AS n \
+                 FROM orders o JOIN suppliers s ON o.supplier_id = s.column_1 \
+                 GROUP BY s.column_2 ORDER BY s.column_2",
+            )
+            .await
+            .unwrap();
+        let rows: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["supplier"], "Acme");
+    }
+}
@@ -0,0 +1,397 @@
+use std::io::Write;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::acp_client::{AcpConfig, AcpResult};
+use crate::agent_backend::{system_prompt, AgentBackend, McpEndpoint};
+use crate::telemetry::{TokenUsage, TraceRecorder};
+
+/// Most model turns spent on one prompt before giving up on `final_query`.
+const MAX_TOOL_ROUNDS: usize = 25;
+
+/// Longest wait for one table's profile when building the startup context.
+const TABLE_PROFILE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An OpenAI-compatible chat completions endpoint, driven with tool calling.
+///
+/// The tools are the embedded MCP server's: their definitions come from
+/// `tools/list` and every call the model makes is forwarded to `tools/call`.
+pub struct ChatBackend {
+    http: reqwest::Client,
+    completions_url: String,
+    model: String,
+    api_key: Option<String>,
+    endpoint: McpEndpoint,
+    tools: Vec<Value>,
+    messages: Vec<Value>,
+    next_request_id: u64,
+    trace: TraceRecorder,
+    show_messages: bool,
+}
+
+impl ChatBackend {
+    /// Prepare a conversation with the endpoint at `base_url` (e.g.
+    /// `http://localhost:11434/v1`). The API key, if any, is read from
+    /// `OPENAI_API_KEY`.
+    pub async fn start(
+        base_url: String,
+        endpoint: McpEndpoint,
+        config: &AcpConfig,
+        trace: TraceRecorder,
+    ) -> Result<Self> {
+        let model = config
+            .model
+            .clone()
+            .ok_or_else(|| anyhow!("--model is required for chat endpoint agents"))?;
+
+        let mut backend = Self {
+            http: reqwest::Client::new(),
+            completions_url: format!("{base_url}/chat/completions"),
+            model,
+            api_key: std::env::var("OPENAI_API_KEY").ok(),
+            endpoint,
+            tools: Vec::new(),
+            messages: vec![json!({
+                "role": "system",
+                "content": system_prompt(config.safe_mode),
+            })],
+            next_request_id: 1,
+            trace,
+            show_messages: config.show_messages,
+        };
+
+        let listed = backend.mcp_request("tools/list", json!({})).await?;
+        backend.tools = listed["tools"]
+            .as_array()
+            .ok_or_else(|| anyhow!("unexpected tools/list response: {listed}"))?
+            .iter()
+            .map(|tool| {
+                json!({
+                    "type": "function",
+                    "function": {
+                        "name": tool["name"],
+                        "description": tool["description"],
+                        "parameters": tool["inputSchema"],
+                    }
+                })
+            })
+            .collect();
+
+        // Hand the model every table resource up front, so it can usually
+        // write the query without exploring the catalog first. Tables that
+        // cannot be profiled are left for the model to explore.
+        let tables = backend.table_resources().await?;
+        if !tables.is_empty() {
+            let system = &mut backend.messages[0]["content"];
+            *system = json!(format!(
+                "{}\n\nTables:\n{}",
+                system.as_str().unwrap_or_default(),
+                tables.join("\n")
+            ));
+        }
+        Ok(backend)
+    }
+
+    /// The contents of every table resource the MCP server lists. A table
+    /// whose profile fails or takes longer than `TABLE_PROFILE_TIMEOUT` is
+    /// skipped with a warning rather than failing startup.
+    async fn table_resources(&mut self) -> Result<Vec<String>> {
+        let listed = self.mcp_request("resources/list", json!({})).await?;
+        let uris: Vec<String> = listed["resources"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|resource| resource["uri"].as_str().map(str::to_string))
+            .collect();
+        let mut tables = Vec::new();
+        for uri in uris {
+            let read = tokio::time::timeout(
+                TABLE_PROFILE_TIMEOUT,
+                self.mcp_request("resources/read", json!({ "uri": uri })),
+            )
+            .await;
+            match read {
+                Ok(Ok(read)) => {
+                    if let Some(text) = read["contents"][0]["text"].as_str() {
+                        tables.push(text.to_string());
+                    }
+                }
+                Ok(Err(e)) => eprintln!("Warning: skipping {uri}: {e:#}"),
+                Err(_) => eprintln!(
+                    "Warning: skipping {uri}: no profile after {}s",
+                    TABLE_PROFILE_TIMEOUT.as_secs()
+                ),
+            }
+        }
+        Ok(tables)
+    }
+
+    async fn run(&mut self, text: &str) -> Result<AcpResult> {
+        self.endpoint.reset();
+        self.messages
+            .push(json!({ "role": "user", "content": text }));
+
+        for _ in 0..MAX_TOOL_ROUNDS {
+            let message = self.complete().await?;
+            self.messages.push(message.clone());
+
+            if let Some(content) = message["content"].as_str() {
+                if self.show_messages && !content.trim().is_empty() {
+                    eprint!("\n[Agent] {content}");
+                    let _ = std::io::stderr().flush();
+                }
+            }
+
+            let calls = message["tool_calls"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default();
+            if calls.is_empty() {
+                break;
+            }
+            for call in &calls {
+                let id = call["id"].as_str().unwrap_or_default();
+                let name = call["function"]["name"].as_str().unwrap_or_default();
+                let output = self
+                    .call_tool(id, name, &call["function"]["arguments"])
+                    .await;
+                self.messages.push(json!({
+                    "role": "tool",
+                    "tool_call_id": id,
+                    "content": output,
+                }));
+            }
+
+            if let Some(result) = self.endpoint.final_result() {
+                return Ok(AcpResult {
+                    sql: result.sql,
+                    summary: result.summary,
+                    datasources: result.datasources,
+                    template: result.template,
+                    repairs: 0,
+                });
+            }
+        }
+        bail!("Agent did not call final_query")
+    }
+
+    /// One chat completion over the conversation so far; returns the
+    /// assistant message.
+    async fn complete(&mut self) -> Result<Value> {
+        let mut request = self.http.post(&self.completions_url).json(&json!({
+            "model": self.model,
+            "messages": self.messages,
+            "tools": self.tools,
+        }));
+        if let Some(key) = &self.api_key {
+            request = request.bearer_auth(key);
+        }
+        let response: Value = request
+            .send()
+            .await
+            .context("Chat completion request failed")?
+            .error_for_status()
+            .context("Chat completion request failed")?
+            .json()
+            .await
+            .context("Invalid chat completion response")?;
+
+        if let Some(usage) = response.get("usage") {
+            self.trace.add_usage(&TokenUsage {
+                input_tokens: usage["prompt_tokens"].as_u64().unwrap_or(0),
+                output_tokens: usage["completion_tokens"].as_u64().unwrap_or(0),
+                ..Default::default()
+            });
+        }
+        response["choices"][0]
+            .get("message")
+            .cloned()
+            .ok_or_else(|| anyhow!("Chat completion has no message: {response}"))
+    }
+
+    /// Forward a tool call to the MCP server and return what the model is
+    /// told: the tool's output or the error.
+    async fn call_tool(&mut self, id: &str, name: &str, arguments: &Value) -> String {
+        self.trace.record_tool_call(id, name, "in_progress");
+        // Arguments arrive as a JSON-encoded string.
+        let arguments = match arguments {
+            Value::String(text) => serde_json::from_str(text).unwrap_or(Value::Null),
+            other => other.clone(),
+        };
+        let outcome = self
+            .mcp_request(
+                "tools/call",
+                json!({ "name": name, "arguments": arguments }),
+            )
+            .await;
+        let (status, output) = match outcome {
+            Ok(result) => {
+                let text = result["content"][0]["text"]
+                    .as_str()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| result.to_string());
+                let failed = result["isError"].as_bool().unwrap_or(false);
+                (if failed { "failed" } else { "completed" }, text)
+            }
+            Err(e) => ("failed", json!({ "error": format!("{e:#}") }).to_string()),
+        };
+        self.trace.update_tool_call(id, None, Some(status));
+        output
+    }
+
+    /// A JSON-RPC request to the MCP server; returns its `result`.
+    async fn mcp_request(&mut self, method: &str, params: Value) -> Result<Value> {
+        let id = self.next_request_id;
+        self.next_request_id += 1;
+        let body = self
+            .http
+            .post(&self.endpoint.url)
+            .header("accept", "application/json, text/event-stream")
+            .json(&json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }))
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        // Responses are sent as a server-sent event.
+        let data = body
+            .lines()
+            .find_map(|line| line.strip_prefix("data:"))
+            .unwrap_or(&body);
+        let response: Value = serde_json::from_str(data.trim())
+            .with_context(|| format!("Invalid MCP response: {body}"))?;
+        if let Some(error) = response.get("error") {
+            bail!("MCP {method} failed: {error}");
+        }
+        Ok(response["result"].clone())
+    }
+}
+
+#[async_trait(?Send)]
+impl AgentBackend for ChatBackend {
+    async fn prompt(&mut self, text: &str, timeout_secs: u64) -> Result<AcpResult> {
+        tokio::time::timeout(Duration::from_secs(timeout_secs), self.run(text))
+            .await
+            .context("Chat agent timed out")?
+    }
+
+    async fn close(mut self: Box<Self>) {
+        self.endpoint.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use axum::extract::State;
+    use axum::routing::post;
+    use axum::{Json, Router};
+
+    use super::*;
+    use crate::agent_backend::start_backend;
+    use crate::test_util::products_executor;
+
+    /// A chat endpoint that replays `replies` and records the requests.
+    #[derive(Clone, Default)]
+    struct Stub {
+        replies: Arc<Mutex<Vec<Value>>>,
+        requests: Arc<Mutex<Vec<Value>>>,
+    }
+
+    async fn completions(State(stub): State<Stub>, Json(request): Json<Value>) -> Json<Value> {
+        stub.requests.lock().unwrap().push(request);
+        let message = stub.replies.lock().unwrap().remove(0);
+        Json(json!({
+            "choices": [{ "message": message }],
+            "usage": { "prompt_tokens": 100, "completion_tokens": 10 },
+        }))
+    }
+
+    fn tool_call(id: &str, name: &str, arguments: Value) -> Value {
+        json!({
+            "role": "assistant",
+            "content": null,
+            "tool_calls": [{
+                "id": id,
+                "type": "function",
+                "function": { "name": name, "arguments": arguments.to_string() },
+            }],
+        })
+    }
+
+    #[tokio::test]
+    async fn test_chat_backend_uses_mcp_tools() {
+        let stub = Stub::default();
+        *stub.replies.lock().unwrap() = vec![
+            tool_call(
+                "c1",
+                "run_sql",
+                json!({ "sql": "SELECT COUNT(*) AS n FROM products" }),
+            ),
+            tool_call(
+                "c2",
+                "final_query",
+                json!({ "sql": "SELECT name FROM products", "summary": "All names" }),
+            ),
+        ];
+        let app = Router::new()
+            .route("/v1/chat/completions", post(completions))
+            .with_state(stub.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}/v1", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let executor = products_executor().await;
+        // Fails when profiled, which should not stop the backend starting.
+        executor
+            .ctx
+            .sql("CREATE VIEW broken AS SELECT CAST(name AS INT) AS n FROM products")
+            .await
+            .unwrap();
+        let executor = Arc::new(executor);
+        let config = AcpConfig {
+            model: Some("stub-model".to_string()),
+            ..Default::default()
+        };
+        let trace = TraceRecorder::default();
+        trace.begin("names", &base_url);
+
+        let mut backend = start_backend(&base_url, executor, &config, trace.clone())
+            .await
+            .unwrap();
+        let result = backend.prompt("list product names", 30).await.unwrap();
+        backend.close().await;
+
+        assert_eq!(result.sql, "SELECT name FROM products");
+        assert_eq!(result.summary.as_deref(), Some("All names"));
+
+        let requests = stub.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0]["model"], "stub-model");
+        let tools: Vec<&str> = requests[0]["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tool| tool["function"]["name"].as_str().unwrap())
+            .collect();
+        assert!(tools.contains(&"run_sql") && tools.contains(&"final_query"));
+        let system = requests[0]["messages"][0]["content"].as_str().unwrap();
+        assert!(system.contains("\"table\":\"products\""), "{system}");
+        assert!(!system.contains("\"table\":\"broken\""), "{system}");
+        // The run_sql result is sent back as the tool message.
+        let tool_reply = &requests[1]["messages"][3];
+        assert_eq!(tool_reply["tool_call_id"], "c1");
+        assert!(tool_reply["content"].as_str().unwrap().contains("\"n\":3"));
+
+        let trace = trace.finish(Some(&result.sql), None, 0, false);
+        assert_eq!(trace.run_sql_calls, 1);
+        assert_eq!(trace.tool_calls.len(), 2);
+        assert_eq!(trace.tool_calls[0].status, "completed");
+        assert_eq!(trace.usage.unwrap().input_tokens, 200);
+    }
+}
@@ -1,19 +1,31 @@
-use std::sync::Arc;
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
 
 use anyhow::{anyhow, bail, Context, Result};
 use async_trait::async_trait;
-use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
-use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::arrow::datatypes::{DataType, Schema, SchemaRef};
 use datafusion::catalog::{Session, TableFunctionImpl, TableProvider};
-use datafusion::common::{DataFusionError, Result as DataFusionResult, ScalarValue};
-use datafusion::datasource::MemTable;
+use datafusion::common::{Column, DFSchema, DataFusionError, Result as DataFusionResult};
 use datafusion::datasource::TableType;
-use datafusion::logical_expr::Expr;
-use datafusion::physical_plan::ExecutionPlan;
-use datafusion::prelude::SessionContext;
+use datafusion::execution::context::ExecutionProps;
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::logical_expr::simplify::SimplifyContext;
+use datafusion::logical_expr::{Expr, ExprSchemable};
+use datafusion::optimizer::simplify_expressions::ExprSimplifier;
+use datafusion::physical_expr::EquivalenceProperties;
+use datafusion::physical_plan::execution_plan::{Boundedness, EmissionType};
+use datafusion::physical_plan::{
+    DisplayAs, DisplayFormatType, ExecutionPlan, Partitioning, PlanProperties,
+};
+use datafusion::prelude::{DataFrame, SessionContext};
 use datafusion::sql::parser::{DFParser, Statement};
 
 use crate::acp_client::{run_acp, AcpConfig};
+use crate::claude_udf::{
+    extract_condition, filter_columns, filter_prompt, nl_calls, question_literal,
+    ClaudeScalarFunction, NlFunction,
+};
+use crate::confirm::confirm_mutation;
 use crate::sql_executor::SqlExecutor;
 
 pub struct ClaudeParser<'a> {
@@ -49,6 +61,13 @@ impl<'a> ClaudeParser<'a> {
             bail!("No SQL statement found")
         }
 
+        if statements.len() > 1 {
+            bail!(
+                "Expected a single SQL statement, found {}. Use split_statements for scripts.",
+                statements.len()
+            )
+        }
+
         let stmt = statements
             .pop_front()
             .ok_or_else(|| anyhow!("No SQL statement found"))?;
@@ -56,19 +75,302 @@ impl<'a> ClaudeParser<'a> {
     }
 }
 
-pub fn register_claude_table_function(
+/// Split a script into statements at `;`, ignoring semicolons inside string
+/// literals, quoted identifiers and comments.
+///
+/// A statement starting with `CLAUDE` runs to the end of its line instead, so
+/// questions may contain apostrophes and semicolons. Empty and comment-only
+/// statements are dropped.
+pub fn split_statements(script: &str) -> Vec<String> {
+    scan_script(script).0
+}
+
+/// Whether `script` ends inside a string literal, quoted identifier or block
+/// comment, so more input is needed before it can be split.
+pub fn is_incomplete(script: &str) -> bool {
+    scan_script(script).1
+}
+
+fn scan_script(script: &str) -> (Vec<String>, bool) {
+    let mut statements = Vec::new();
+    let mut chars = script.char_indices().peekable();
+    let mut start = 0;
+    let mut has_content = false;
+    let mut open = false;
+
+    // Consume characters up to (not including) byte offset `end`.
+    macro_rules! skip_to {
+        ($end:expr) => {{
+            let end = $end;
+            while chars.next_if(|(j, _)| *j < end).is_some() {}
+        }};
+    }
+
+    while let Some((i, c)) = chars.next() {
+        let rest = &script[i..];
+        match c {
+            c if c.is_whitespace() => {}
+            '-' if rest.starts_with("--") => {
+                skip_to!(rest.find('\n').map_or(script.len(), |n| i + n));
+            }
+            '/' if rest.starts_with("/*") => match rest[2..].find("*/") {
+                Some(n) => skip_to!(i + 2 + n + 2),
+                None => {
+                    open = true;
+                    skip_to!(script.len());
+                }
+            },
+            '\'' | '"' | '`' => {
+                has_content = true;
+                // A doubled quote closes and reopens the literal, which
+                // scans the same as an escape.
+                match rest[1..].find(c) {
+                    Some(n) => skip_to!(i + 1 + n + 1),
+                    None => {
+                        open = true;
+                        skip_to!(script.len());
+                    }
+                }
+            }
+            ';' => {
+                if has_content {
+                    statements.push(script[start..i].trim().to_string());
+                }
+                start = i + 1;
+                has_content = false;
+            }
+            _ if !has_content && starts_with_claude(rest) => {
+                let end = rest.find('\n').map_or(script.len(), |n| i + n);
+                let statement = script[i..end].trim().trim_end_matches(';').trim_end();
+                statements.push(statement.to_string());
+                skip_to!(end);
+                start = end;
+            }
+            _ => has_content = true,
+        }
+    }
+
+    if has_content {
+        statements.push(script[start..].trim().to_string());
+    }
+    (statements, open)
+}
+
+fn starts_with_claude(text: &str) -> bool {
+    text.get(..6)
+        .is_some_and(|word| word.eq_ignore_ascii_case("CLAUDE"))
+        && text[6..].chars().next().is_none_or(char::is_whitespace)
+}
+
+/// Register the `claude('<question>')` table function and the
+/// `claude_sql()` and `claude_filter()` scalar functions on `ctx`.
+///
+/// The returned [`ClaudeAnswers`] must [`prepare`](ClaudeAnswers::prepare)
+/// each statement before it is planned for execution: that is where the agent
+/// is asked, so planning itself never blocks on it.
+pub fn register_claude_functions(
     ctx: &SessionContext,
     executor: Arc<SqlExecutor>,
     config: Arc<AcpConfig>,
-) -> Result<()> {
-    let function = ClaudeTableFunction { executor, config };
-    ctx.register_udtf("claude", Arc::new(function));
-    Ok(())
+) -> Result<ClaudeAnswers> {
+    let answers = ClaudeAnswers {
+        executor,
+        config,
+        state: Arc::default(),
+    };
+    ctx.register_udtf(
+        "claude",
+        Arc::new(ClaudeTableFunction {
+            answers: answers.clone(),
+        }),
+    );
+    for function in [NlFunction::Sql, NlFunction::Filter] {
+        ctx.register_udf(ClaudeScalarFunction::new(function, answers.clone()).into());
+    }
+    Ok(answers)
 }
 
-struct ClaudeTableFunction {
+/// The agent's answers to the natural-language functions used in this
+/// session.
+#[derive(Clone)]
+pub struct ClaudeAnswers {
     executor: Arc<SqlExecutor>,
     config: Arc<AcpConfig>,
+    state: Arc<Mutex<AnswerState>>,
+}
+
+#[derive(Default)]
+struct AnswerState {
+    answered: HashMap<String, Answer>,
+    /// Questions met while planning that have no answer yet.
+    pending: BTreeSet<String>,
+    /// `claude_sql()` answers.
+    generated_sql: HashMap<String, String>,
+    /// `claude_filter()` predicates, with the columns they were compiled
+    /// against.
+    filters: HashMap<String, (Vec<String>, Expr)>,
+}
+
+#[derive(Debug, Clone)]
+struct Answer {
+    sql: String,
+    schema: SchemaRef,
+}
+
+impl ClaudeAnswers {
+    /// Ask the agent the `claude()` questions in `sql` that have no answer
+    /// yet, on the current runtime.
+    ///
+    /// Only the agent's SQL and its schema are kept; the SQL runs when the
+    /// table is scanned. Plain `EXPLAIN` never asks the agent, while
+    /// `EXPLAIN ANALYZE` does since it executes the query.
+    pub async fn prepare(&self, sql: &str) -> Result<()> {
+        // Unparsable SQL fails with a better error once it is run.
+        let Ok(statements) = DFParser::parse_sql(sql) else {
+            return Ok(());
+        };
+        if statements
+            .iter()
+            .any(|stmt| matches!(stmt, Statement::Explain(explain) if !explain.analyze))
+        {
+            return Ok(());
+        }
+
+        // Planning records every unanswered question. Planning errors caused
+        // by the placeholder schema are expected and ignored here.
+        self.lock().pending.clear();
+        let plan = self.executor.ctx.state().create_logical_plan(sql).await;
+        let pending = std::mem::take(&mut self.lock().pending);
+        let replan = !pending.is_empty();
+
+        for question in pending {
+            let answer = self
+                .ask(&question)
+                .await
+                .with_context(|| format!("claude('{question}') failed"))?;
+            self.lock().answered.insert(question, answer);
+        }
+
+        // Scalar calls can only be found once the tables they read from are
+        // known, so a statement with new claude() tables is planned again.
+        let plan = match plan {
+            Ok(plan) if !replan => plan,
+            _ => match self.executor.ctx.state().create_logical_plan(sql).await {
+                Ok(plan) => plan,
+                Err(_) => return Ok(()),
+            },
+        };
+        for call in nl_calls(&plan)? {
+            match call.function {
+                NlFunction::Sql => {
+                    if self.generated_sql(&call.question).is_some() {
+                        continue;
+                    }
+                    let result = run_acp(&call.question, self.executor.clone(), &self.config)
+                        .await
+                        .with_context(|| format!("claude_sql('{}') failed", call.question))?;
+                    self.lock().generated_sql.insert(call.question, result.sql);
+                }
+                NlFunction::Filter => {
+                    let columns = filter_columns(&call.schema);
+                    if self
+                        .lock()
+                        .filters
+                        .get(&call.question)
+                        .is_some_and(|(compiled_for, _)| *compiled_for == columns)
+                    {
+                        continue;
+                    }
+                    let predicate = self
+                        .compile_filter(&call.question, &call.schema)
+                        .await
+                        .with_context(|| format!("claude_filter('{}') failed", call.question))?;
+                    self.lock()
+                        .filters
+                        .insert(call.question, (columns, predicate));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Have the agent turn `condition` into a boolean expression over
+    /// `schema`.
+    async fn compile_filter(&self, condition: &str, schema: &DFSchema) -> Result<Expr> {
+        let prompt = filter_prompt(condition, schema)?;
+        let acp_result = run_acp(&prompt, self.executor.clone(), &self.config)
+            .await
+            .context("ACP query generation failed")?;
+        let sql = extract_condition(&acp_result.sql)?;
+        let predicate = self
+            .executor
+            .ctx
+            .parse_sql_expr(&sql, schema)
+            .with_context(|| format!("Failed to plan the generated condition: {sql}"))?;
+        // Simplification runs after type coercion, so coerce the predicate
+        // here before it replaces the call.
+        let props = ExecutionProps::new();
+        let predicate =
+            ExprSimplifier::new(SimplifyContext::new(&props)).coerce(predicate, schema)?;
+        let data_type = predicate.get_type(schema)?;
+        if data_type != DataType::Boolean {
+            bail!("The generated condition is {data_type}, not a boolean: {sql}");
+        }
+        Ok(predicate)
+    }
+
+    pub(crate) fn generated_sql(&self, question: &str) -> Option<String> {
+        self.lock().generated_sql.get(question).cloned()
+    }
+
+    pub(crate) fn filter(&self, condition: &str) -> Option<Expr> {
+        self.lock()
+            .filters
+            .get(condition)
+            .map(|(_, predicate)| predicate.clone())
+    }
+
+    pub(crate) fn same_session(&self, other: &ClaudeAnswers) -> bool {
+        Arc::ptr_eq(&self.state, &other.state)
+    }
+
+    async fn ask(&self, question: &str) -> Result<Answer> {
+        let acp_result = run_acp(question, self.executor.clone(), &self.config)
+            .await
+            .context("ACP query generation failed")?;
+        if self.config.safe_mode {
+            self.executor.ensure_read_only(&acp_result.sql).await?;
+        }
+        if self.config.confirm_mutations {
+            confirm_mutation(&self.executor, &acp_result.sql).await?;
+        }
+        let plan = self
+            .executor
+            .ctx
+            .state()
+            .create_logical_plan(&acp_result.sql)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to plan SQL generated by claude(): {}",
+                    acp_result.sql
+                )
+            })?;
+        Ok(Answer {
+            sql: acp_result.sql,
+            schema: Arc::new(plan.schema().as_arrow().clone()),
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, AnswerState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+struct ClaudeTableFunction {
+    answers: ClaudeAnswers,
 }
 
 impl std::fmt::Debug for ClaudeTableFunction {
@@ -80,149 +382,158 @@ impl std::fmt::Debug for ClaudeTableFunction {
 
 impl TableFunctionImpl for ClaudeTableFunction {
     fn call(&self, args: &[Expr]) -> DataFusionResult<Arc<dyn TableProvider>> {
-        if args.len() != 1 {
-            return Err(DataFusionError::Plan(
-                "claude() expects exactly 1 argument".to_string(),
-            ));
-        }
+        let question = question_literal("claude", args)?;
 
-        let query = match &args[0] {
-            Expr::Literal(ScalarValue::Utf8(Some(query)), _)
-            | Expr::Literal(ScalarValue::LargeUtf8(Some(query)), _) => query.clone(),
-            _ => {
-                return Err(DataFusionError::Plan(
-                    "claude() argument must be a string literal".to_string(),
-                ))
-            }
-        };
-
-        let executor = self.executor.clone();
-        let config = self.config.clone();
-        match materialize_claude_query(query, executor, config) {
-            Ok((schema, batches)) => Ok(Arc::new(ClaudeMaterializedTableProvider {
-                schema,
-                batches,
-            })),
-            Err(e) => {
-                let schema = Arc::new(Schema::new(vec![Field::new(
-                    "result",
-                    DataType::Utf8,
-                    true,
-                )]));
-                Ok(Arc::new(ClaudeErrorTableProvider {
-                    schema,
-                    error: e.to_string(),
-                }))
-            }
+        let mut state = self.answers.lock();
+        let answer = state.answered.get(&question).cloned();
+        if answer.is_none() {
+            state.pending.insert(question.clone());
         }
+        Ok(Arc::new(ClaudeTableProvider {
+            executor: self.answers.executor.clone(),
+            question,
+            answer,
+        }))
     }
 }
 
-fn materialize_claude_query(
-    query: String,
+/// The result of a `claude()` question. The agent's SQL is planned and run
+/// only when the table is scanned, with the scan's projection and limit
+/// applied to it. An unanswered question has no columns and cannot be
+/// scanned.
+struct ClaudeTableProvider {
     executor: Arc<SqlExecutor>,
-    config: Arc<AcpConfig>,
-) -> Result<(SchemaRef, Vec<RecordBatch>)> {
-    let join_result = std::thread::spawn(move || -> Result<(SchemaRef, Vec<RecordBatch>)> {
-        let rt = tokio::runtime::Builder::new_multi_thread()
-            .enable_all()
-            .build()
-            .context("Failed to build Tokio runtime for claude() table function")?;
-        rt.block_on(async move {
-            let acp_result = run_acp(&query, executor.clone(), &config)
-                .await
-                .context("ACP query generation failed")?;
-            let df = executor
-                .execute_sql(&acp_result.sql)
-                .await
-                .with_context(|| {
-                    format!(
-                        "Failed to execute SQL generated by claude(): {}",
-                        acp_result.sql
-                    )
-                })?;
-            let schema = Arc::new(df.schema().as_arrow().clone());
-            let batches = df
-                .collect()
-                .await
-                .context("Failed collecting SQL results")?;
-            Ok((schema, batches))
-        })
-    })
-    .join();
-
-    match join_result {
-        Ok(result) => result,
-        Err(_) => bail!("claude() worker thread panicked"),
-    }
+    question: String,
+    answer: Option<Answer>,
 }
 
-#[derive(Debug)]
-struct ClaudeMaterializedTableProvider {
-    schema: SchemaRef,
-    batches: Vec<RecordBatch>,
+impl std::fmt::Debug for ClaudeTableProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClaudeTableProvider")
+            .field("question", &self.question)
+            .field("answer", &self.answer)
+            .finish_non_exhaustive()
+    }
 }
 
 #[async_trait]
-impl TableProvider for ClaudeMaterializedTableProvider {
+impl TableProvider for ClaudeTableProvider {
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
 
     fn schema(&self) -> SchemaRef {
-        Arc::clone(&self.schema)
+        match &self.answer {
+            Some(answer) => answer.schema.clone(),
+            None => Arc::new(Schema::empty()),
+        }
     }
 
     fn table_type(&self) -> TableType {
-        TableType::Base
+        TableType::View
+    }
+
+    fn get_table_definition(&self) -> Option<&str> {
+        self.answer.as_ref().map(|answer| answer.sql.as_str())
     }
 
     async fn scan(
         &self,
-        state: &dyn Session,
+        _state: &dyn Session,
         projection: Option<&Vec<usize>>,
-        filters: &[Expr],
+        _filters: &[Expr],
         limit: Option<usize>,
     ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
-        let memtable = MemTable::try_new(self.schema.clone(), vec![self.batches.clone()])?;
-        memtable.scan(state, projection, filters, limit).await
+        let Some(answer) = &self.answer else {
+            return Ok(Arc::new(UnansweredExec::new(self.question.clone())));
+        };
+
+        let state = self.executor.ctx.state();
+        let plan = state.create_logical_plan(&answer.sql).await?;
+        let mut df = DataFrame::new(state, plan);
+        if let Some(projection) = projection {
+            let columns = projection
+                .iter()
+                .map(|&i| Expr::Column(Column::from(df.schema().qualified_field(i))))
+                .collect::<Vec<_>>();
+            df = df.select(columns)?;
+        }
+        if limit.is_some() {
+            df = df.limit(0, limit)?;
+        }
+        df.create_physical_plan().await
     }
 }
 
+/// Stands in for an unanswered question so `EXPLAIN` can show the plan;
+/// executing it fails.
 #[derive(Debug)]
-struct ClaudeErrorTableProvider {
-    schema: SchemaRef,
-    error: String,
+struct UnansweredExec {
+    question: String,
+    properties: PlanProperties,
 }
 
-#[async_trait]
-impl TableProvider for ClaudeErrorTableProvider {
+impl UnansweredExec {
+    fn new(question: String) -> Self {
+        let properties = PlanProperties::new(
+            EquivalenceProperties::new(Arc::new(Schema::empty())),
+            Partitioning::UnknownPartitioning(1),
+            EmissionType::Incremental,
+            Boundedness::Bounded,
+        );
+        Self {
+            question,
+            properties,
+        }
+    }
+}
+
+impl DisplayAs for UnansweredExec {
+    fn fmt_as(&self, _t: DisplayFormatType, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "ClaudeExec: question={:?}, unanswered", self.question)
+    }
+}
+
+impl ExecutionPlan for UnansweredExec {
+    fn name(&self) -> &'static str {
+        "ClaudeExec"
+    }
+
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
 
-    fn schema(&self) -> SchemaRef {
-        Arc::clone(&self.schema)
+    fn properties(&self) -> &PlanProperties {
+        &self.properties
     }
 
-    fn table_type(&self) -> TableType {
-        TableType::Base
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![]
     }
 
-    async fn scan(
-        &self,
-        _state: &dyn Session,
-        _projection: Option<&Vec<usize>>,
-        _filters: &[Expr],
-        _limit: Option<usize>,
+    fn with_new_children(
+        self: Arc<Self>,
+        _: Vec<Arc<dyn ExecutionPlan>>,
     ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
-        Err(DataFusionError::Execution(self.error.clone()))
+        Ok(self)
+    }
+
+    fn execute(
+        &self,
+        _partition: usize,
+        _context: Arc<TaskContext>,
+    ) -> DataFusionResult<SendableRecordBatchStream> {
+        Err(DataFusionError::Execution(format!(
+            "claude('{}') was not answered before the query was planned",
+            self.question
+        )))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::products_executor;
 
     #[test]
     fn test_claude_parser_recognises_claude() {
@@ -272,13 +583,115 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_claude_parser_rejects_multiple_statements() {
+        let mut parser = ClaudeParser::new("SELECT 1; SELECT 2").unwrap();
+        assert!(parser.parse_statement().is_err());
+    }
+
+    #[test]
+    fn test_split_statements() {
+        let script = "
+            -- setup; not a statement
+            CREATE TABLE t AS VALUES (1, 'a;b'), (2, 'it''s');
+            /* block; comment */ SELECT \"odd;name\" FROM t;
+            CLAUDE what's the total; per category?
+            claude
+            SELECT 2
+        ";
+        assert_eq!(
+            split_statements(script),
+            [
+                "-- setup; not a statement\n            \
+                 CREATE TABLE t AS VALUES (1, 'a;b'), (2, 'it''s')",
+                "/* block; comment */ SELECT \"odd;name\" FROM t",
+                "CLAUDE what's the total; per category?",
+                "claude",
+                "SELECT 2",
+            ]
+        );
+        assert!(split_statements(" ; -- nothing\n;").is_empty());
+        assert_eq!(
+            split_statements("SELECT claude_x FROM t"),
+            ["SELECT claude_x FROM t"]
+        );
+    }
+
+    #[test]
+    fn test_incomplete_scripts() {
+        assert!(!is_incomplete("SELECT 'a'"));
+        assert!(is_incomplete("SELECT 'a"));
+        assert!(is_incomplete("SELECT 1 /* comment"));
+        assert!(!is_incomplete("CLAUDE what's up"));
+        assert!(!is_incomplete("SELECT 1 -- it's fine"));
+    }
+
     #[tokio::test]
     async fn test_claude_table_function_registers_for_planning() {
         let executor = Arc::new(SqlExecutor::new().await.unwrap());
         let config = Arc::new(AcpConfig::default());
-        register_claude_table_function(&executor.ctx, executor.clone(), config).unwrap();
+        let answers = register_claude_functions(&executor.ctx, executor.clone(), config).unwrap();
 
         let df = executor.ctx.sql("SELECT * FROM claude('test')").await;
         assert!(df.is_ok());
+
+        // Planning does not ask the agent, so nothing can be scanned yet.
+        let err = df.unwrap().collect().await.unwrap_err();
+        assert!(err.to_string().contains("was not answered"), "{err}");
+
+        // Neither does EXPLAIN.
+        answers
+            .prepare("EXPLAIN SELECT * FROM claude('test')")
+            .await
+            .unwrap();
+        assert!(answers.lock().answered.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_answered_question_runs_lazily_with_limit() {
+        let executor = Arc::new(products_executor().await);
+        let config = Arc::new(AcpConfig::default());
+        let answers = register_claude_functions(&executor.ctx, executor.clone(), config).unwrap();
+
+        let sql = "SELECT id, name FROM products ORDER BY id";
+        let plan = executor.ctx.state().create_logical_plan(sql).await.unwrap();
+        answers.lock().answered.insert(
+            "all products".to_string(),
+            Answer {
+                sql: sql.to_string(),
+                schema: Arc::new(plan.schema().as_arrow().clone()),
+            },
+        );
+
+        // Already answered, so preparing does not ask the agent again.
+        let query = "SELECT name FROM claude('all products') LIMIT 2";
+        answers.prepare(query).await.unwrap();
+        let batches = executor
+            .ctx
+            .sql(query)
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+        assert_eq!(rows, 2);
+        assert_eq!(batches[0].schema().field(0).name(), "name");
+
+        let batches = executor
+            .ctx
+            .sql("SELECT COUNT(*) AS n FROM claude('all products')")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let n = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<datafusion::arrow::array::Int64Array>()
+            .unwrap()
+            .value(0);
+        assert_eq!(n, 3);
     }
 }
@@ -0,0 +1,305 @@
+use std::any::Any;
+use std::hash::{Hash, Hasher};
+
+use anyhow::{bail, Context, Result};
+use datafusion::arrow::datatypes::DataType;
+use datafusion::common::tree_node::{TreeNode, TreeNodeRecursion};
+use datafusion::common::{DFSchema, DataFusionError, Result as DataFusionResult, ScalarValue};
+use datafusion::logical_expr::simplify::{ExprSimplifyResult, SimplifyInfo};
+use datafusion::logical_expr::{
+    ColumnarValue, Expr, LogicalPlan, ScalarFunctionArgs, ScalarUDFImpl, Signature, Volatility,
+};
+use datafusion::sql::parser::{DFParser, Statement};
+use datafusion::sql::sqlparser::ast::{SetExpr, Statement as SqlStatement};
+
+use crate::claude_statement::ClaudeAnswers;
+
+/// The natural-language scalar functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NlFunction {
+    /// `claude_sql('question')`: the SQL the agent writes for the question,
+    /// as text. It is never run.
+    Sql,
+    /// `claude_filter('condition')`: a predicate the agent compiles against
+    /// the columns in scope where it is used.
+    Filter,
+}
+
+impl NlFunction {
+    pub fn name(self) -> &'static str {
+        match self {
+            NlFunction::Sql => "claude_sql",
+            NlFunction::Filter => "claude_filter",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        [NlFunction::Sql, NlFunction::Filter]
+            .into_iter()
+            .find(|function| function.name() == name)
+    }
+}
+
+/// A `claude_sql()` or `claude_filter()` call found in a plan.
+#[derive(Debug)]
+pub struct NlCall {
+    pub function: NlFunction,
+    pub question: String,
+    /// The columns in scope at the call, which a filter may refer to.
+    pub schema: DFSchema,
+}
+
+/// Collect the natural-language function calls in `plan`, subqueries
+/// included.
+pub fn nl_calls(plan: &LogicalPlan) -> DataFusionResult<Vec<NlCall>> {
+    let mut calls = Vec::new();
+    plan.apply_with_subqueries(|node| {
+        let inputs = node.inputs();
+        let schema = match inputs.as_slice() {
+            [input] => input.schema(),
+            _ => node.schema(),
+        };
+        node.apply_expressions(|expr| {
+            expr.apply(|expr| {
+                if let Expr::ScalarFunction(call) = expr {
+                    if let Some(function) = NlFunction::from_name(call.name()) {
+                        calls.push(NlCall {
+                            function,
+                            question: question_literal(function.name(), &call.args)?,
+                            schema: schema.as_ref().clone(),
+                        });
+                    }
+                }
+                Ok(TreeNodeRecursion::Continue)
+            })
+        })
+    })?;
+    Ok(calls)
+}
+
+/// The string literal a natural-language function was called with.
+pub fn question_literal(name: &str, args: &[Expr]) -> DataFusionResult<String> {
+    match args {
+        [Expr::Literal(
+            ScalarValue::Utf8(Some(question))
+            | ScalarValue::LargeUtf8(Some(question))
+            | ScalarValue::Utf8View(Some(question)),
+            _,
+        )] => Ok(question.clone()),
+        [_] => Err(DataFusionError::Plan(format!(
+            "{name}() argument must be a string literal"
+        ))),
+        _ => Err(DataFusionError::Plan(format!(
+            "{name}() expects exactly 1 argument"
+        ))),
+    }
+}
+
+/// Column references a filter may use, e.g. `orders.placed_at (Timestamp)`.
+pub fn filter_columns(schema: &DFSchema) -> Vec<String> {
+    schema
+        .iter()
+        .map(|(qualifier, field)| match qualifier {
+            Some(qualifier) => format!("{qualifier}.{} ({})", field.name(), field.data_type()),
+            None => format!("{} ({})", field.name(), field.data_type()),
+        })
+        .collect()
+}
+
+/// The prompt asking the agent to compile `condition` into a predicate over
+/// `schema`. The agent answers with a whole query through `final_query` so
+/// the usual validation applies; only its `WHERE` clause is kept.
+pub fn filter_prompt(condition: &str, schema: &DFSchema) -> Result<String> {
+    let mut relations = Vec::new();
+    for (qualifier, _) in schema.iter() {
+        if let Some(qualifier) = qualifier.map(|q| q.to_string()) {
+            if !relations.contains(&qualifier) {
+                relations.push(qualifier);
+            }
+        }
+    }
+    if relations.is_empty() {
+        bail!("claude_filter() needs named tables in scope");
+    }
+
+    Ok(format!(
+        "Write a SQL condition that keeps only the rows matching: {condition}\n\n\
+         The condition may only use these columns:\n{}\n\n\
+         Call final_query with `SELECT * FROM {} WHERE <condition>`. Only the \
+         condition is used, so do not add joins, aliases, GROUP BY or ORDER BY.",
+        filter_columns(schema)
+            .iter()
+            .map(|column| format!("- {column}"))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        relations.join(", "),
+    ))
+}
+
+/// The `WHERE` condition of the agent's `SELECT ... WHERE ...` query.
+pub fn extract_condition(sql: &str) -> Result<String> {
+    let mut statements = DFParser::parse_sql(sql)?;
+    let Some(Statement::Statement(statement)) = statements.pop_front() else {
+        bail!("Expected a SELECT statement, got: {sql}");
+    };
+    let SqlStatement::Query(query) = *statement else {
+        bail!("Expected a SELECT statement, got: {sql}");
+    };
+    let SetExpr::Select(select) = *query.body else {
+        bail!("Expected a plain SELECT statement, got: {sql}");
+    };
+    select
+        .selection
+        .map(|condition| condition.to_string())
+        .with_context(|| format!("The agent's query has no WHERE clause: {sql}"))
+}
+
+/// `claude_sql()` and `claude_filter()`. Their answers are looked up when
+/// the plan is simplified, so calls must be answered by
+/// [`ClaudeAnswers::prepare`] first; evaluating an unanswered call fails.
+#[derive(Clone)]
+pub struct ClaudeScalarFunction {
+    function: NlFunction,
+    signature: Signature,
+    answers: ClaudeAnswers,
+}
+
+impl ClaudeScalarFunction {
+    pub fn new(function: NlFunction, answers: ClaudeAnswers) -> Self {
+        Self {
+            function,
+            // Volatile keeps constant folding from evaluating the call.
+            signature: Signature::exact(vec![DataType::Utf8], Volatility::Volatile),
+            answers,
+        }
+    }
+}
+
+impl std::fmt::Debug for ClaudeScalarFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ClaudeScalarFunction")
+            .field("function", &self.function)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PartialEq for ClaudeScalarFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.function == other.function && self.answers.same_session(&other.answers)
+    }
+}
+
+impl Eq for ClaudeScalarFunction {}
+
+impl Hash for ClaudeScalarFunction {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.function.hash(state);
+    }
+}
+
+impl ScalarUDFImpl for ClaudeScalarFunction {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn name(&self) -> &str {
+        self.function.name()
+    }
+
+    fn signature(&self) -> &Signature {
+        &self.signature
+    }
+
+    fn return_type(&self, _arg_types: &[DataType]) -> DataFusionResult<DataType> {
+        Ok(match self.function {
+            NlFunction::Sql => DataType::Utf8,
+            NlFunction::Filter => DataType::Boolean,
+        })
+    }
+
+    fn simplify(
+        &self,
+        args: Vec<Expr>,
+        _info: &dyn SimplifyInfo,
+    ) -> DataFusionResult<ExprSimplifyResult> {
+        let question = question_literal(self.name(), &args)?;
+        let answer = match self.function {
+            NlFunction::Sql => self
+                .answers
+                .generated_sql(&question)
+                .map(|sql| Expr::Literal(ScalarValue::Utf8(Some(sql)), None)),
+            NlFunction::Filter => self.answers.filter(&question),
+        };
+        Ok(match answer {
+            Some(expr) => ExprSimplifyResult::Simplified(expr),
+            None => ExprSimplifyResult::Original(args),
+        })
+    }
+
+    fn invoke_with_args(&self, args: ScalarFunctionArgs) -> DataFusionResult<ColumnarValue> {
+        let question = match args.args.first() {
+            Some(ColumnarValue::Scalar(ScalarValue::Utf8(Some(question)))) => question.clone(),
+            _ => String::new(),
+        };
+        Err(DataFusionError::Execution(format!(
+            "{}('{question}') was not answered before the query was planned",
+            self.name()
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::acp_client::AcpConfig;
+    use crate::claude_statement::register_claude_functions;
+    use crate::sql_executor::SqlExecutor;
+
+    #[tokio::test]
+    async fn test_nl_calls_carry_the_schema_in_scope() {
+        let executor = Arc::new(SqlExecutor::new().await.unwrap());
+        executor
+            .ctx
+            .sql("CREATE TABLE orders (id INT, placed_at TIMESTAMP)")
+            .await
+            .unwrap();
+        let config = Arc::new(AcpConfig::default());
+        register_claude_functions(&executor.ctx, executor.clone(), config).unwrap();
+
+        let plan = executor
+            .ctx
+            .state()
+            .create_logical_plan(
+                "SELECT claude_sql('count orders') FROM orders \
+                 WHERE claude_filter('placed on weekends')",
+            )
+            .await
+            .unwrap();
+        let mut calls = nl_calls(&plan).unwrap();
+        calls.sort_by_key(|call| call.question.clone());
+
+        assert_eq!(calls[0].function, NlFunction::Sql);
+        assert_eq!(calls[0].question, "count orders");
+        assert_eq!(calls[1].function, NlFunction::Filter);
+        assert_eq!(calls[1].question, "placed on weekends");
+        assert_eq!(
+            filter_columns(&calls[1].schema),
+            ["orders.id (Int32)", "orders.placed_at (Timestamp(ns))"]
+        );
+        let prompt = filter_prompt(&calls[1].question, &calls[1].schema).unwrap();
+        assert!(prompt.contains("SELECT * FROM orders WHERE <condition>"));
+    }
+
+    #[test]
+    fn test_extract_condition() {
+        assert_eq!(
+            extract_condition("SELECT * FROM orders WHERE date_part('dow', placed_at) IN (0, 6)")
+                .unwrap(),
+            "date_part('dow', placed_at) IN (0, 6)"
+        );
+        assert!(extract_condition("SELECT * FROM orders").is_err());
+        assert!(extract_condition("DELETE FROM orders WHERE id = 1").is_err());
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use datafusion::prelude::SessionContext;
+use datafusion::sql::sqlparser::keywords::ALL_KEYWORDS;
+use rustyline::completion::{Completer, FilenameCompleter, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Context, Helper};
+
+use crate::claude_statement::is_incomplete;
+
+/// REPL meta-commands, offered when a line starts with `\`.
+const META_COMMANDS: &[&str] = &[
+    "\\cache clear",
+    "\\d",
+    "\\explain",
+    "\\format",
+    "\\history",
+    "\\load",
+    "\\o",
+    "\\reset",
+    "\\save",
+    "\\timing",
+];
+
+const OUTPUT_FORMATS: &[&str] = &[
+    "arrow", "csv", "json", "markdown", "ndjson", "table", "vertical",
+];
+
+const EXPLAIN_MODES: &[&str] = &["analyze", "off", "plan"];
+
+/// Names offered by tab completion, taken from the live `SessionContext`.
+#[derive(Debug, Default)]
+pub struct CompletionCatalog {
+    /// Columns of every user table, keyed by the shortest name that refers
+    /// to it: `table` in the default schema, `schema.table` elsewhere.
+    tables: BTreeMap<String, Vec<String>>,
+    /// Scalar, aggregate, window and table functions, including UDFs such
+    /// as `claude`.
+    functions: BTreeSet<String>,
+}
+
+impl CompletionCatalog {
+    /// Snapshot the tables, columns and functions registered in `ctx`.
+    pub async fn load(ctx: &SessionContext) -> Self {
+        let options = ctx.state().config().options().catalog.clone();
+        let mut tables = BTreeMap::new();
+        for catalog_name in ctx.catalog_names() {
+            let Some(catalog) = ctx.catalog(&catalog_name) else {
+                continue;
+            };
+            for schema_name in catalog.schema_names() {
+                if schema_name == "information_schema" {
+                    continue;
+                }
+                let Some(schema) = catalog.schema(&schema_name) else {
+                    continue;
+                };
+                for table_name in schema.table_names() {
+                    let columns = match schema.table(&table_name).await {
+                        Ok(Some(table)) => table
+                            .schema()
+                            .fields()
+                            .iter()
+                            .map(|f| f.name().clone())
+                            .collect(),
+                        _ => Vec::new(),
+                    };
+                    let name = if catalog_name != options.default_catalog {
+                        format!("{catalog_name}.{schema_name}.{table_name}")
+                    } else if schema_name != options.default_schema {
+                        format!("{schema_name}.{table_name}")
+                    } else {
+                        table_name
+                    };
+                    tables.insert(name, columns);
+                }
+            }
+        }
+
+        let state = ctx.state();
+        let functions = state
+            .scalar_functions()
+            .keys()
+            .chain(state.aggregate_functions().keys())
+            .chain(state.window_functions().keys())
+            .chain(state.table_functions().keys())
+            .cloned()
+            .collect();
+
+        Self { tables, functions }
+    }
+
+    /// Completions for the SQL word ending at the end of `word`.
+    fn complete_word(&self, word: &str) -> Vec<String> {
+        if let Some((qualifier, prefix)) = word.rsplit_once('.') {
+            // `schema.tab` completes a table name, `table.col` a column.
+            let tables = self
+                .tables
+                .keys()
+                .filter(|table| starts_with_ignore_case(table, word))
+                .cloned();
+            let columns = self
+                .tables
+                .iter()
+                .filter(|(table, _)| table.eq_ignore_ascii_case(qualifier))
+                .flat_map(|(_, columns)| columns)
+                .filter(|column| starts_with_ignore_case(column, prefix))
+                .map(|column| format!("{qualifier}.{column}"));
+            return tables.chain(columns).collect();
+        }
+
+        let keyword_case = |keyword: &str| {
+            if word.chars().any(|c| c.is_ascii_uppercase()) {
+                keyword.to_string()
+            } else {
+                keyword.to_ascii_lowercase()
+            }
+        };
+
+        let names = self
+            .tables
+            .keys()
+            .chain(self.tables.values().flatten())
+            .chain(&self.functions)
+            .filter(|name| starts_with_ignore_case(name, word))
+            .cloned();
+        let keywords = ALL_KEYWORDS
+            .iter()
+            .chain(&["CLAUDE"])
+            .filter(|keyword| starts_with_ignore_case(keyword, word))
+            .map(|keyword| keyword_case(keyword));
+
+        let mut seen = BTreeSet::new();
+        names
+            .chain(keywords)
+            .filter(|candidate| seen.insert(candidate.clone()))
+            .collect()
+    }
+}
+
+/// Rustyline helper that completes SQL and meta-commands against the
+/// current catalog.
+pub struct ReplHelper {
+    catalog: CompletionCatalog,
+    files: FilenameCompleter,
+}
+
+impl ReplHelper {
+    pub fn new() -> Self {
+        Self {
+            catalog: CompletionCatalog::default(),
+            files: FilenameCompleter::new(),
+        }
+    }
+
+    pub fn set_catalog(&mut self, catalog: CompletionCatalog) {
+        self.catalog = catalog;
+    }
+
+    /// Completions for everything but file paths: returns the start of the
+    /// replaced text and the candidates.
+    fn complete_line(&self, line: &str) -> (usize, Vec<String>) {
+        for (command, choices) in [("\\format ", OUTPUT_FORMATS), ("\\explain ", EXPLAIN_MODES)] {
+            if let Some(arg) = line.strip_prefix(command) {
+                let start = line.len() - arg.len();
+                let matches = choices
+                    .iter()
+                    .filter(|choice| choice.starts_with(arg))
+                    .map(|choice| choice.to_string())
+                    .collect();
+                return (start, matches);
+            }
+        }
+
+        if let Some(arg) = line.strip_prefix("\\d ") {
+            let start = line.len() - arg.len();
+            let tables = self
+                .catalog
+                .tables
+                .keys()
+                .filter(|table| starts_with_ignore_case(table, arg))
+                .cloned()
+                .collect();
+            return (start, tables);
+        }
+
+        if line.starts_with('\\') {
+            let commands = META_COMMANDS
+                .iter()
+                .filter(|command| command.starts_with(line))
+                .map(|command| command.to_string())
+                .collect();
+            return (0, commands);
+        }
+
+        let start = line
+            .char_indices()
+            .rev()
+            .find(|(_, c)| !(c.is_alphanumeric() || *c == '_' || *c == '.'))
+            .map(|(i, c)| i + c.len_utf8())
+            .unwrap_or(0);
+        let word = &line[start..];
+        if word.is_empty() {
+            return (start, Vec::new());
+        }
+        (start, self.catalog.complete_word(word))
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        if line.starts_with("\\load ") || line.starts_with("\\o ") {
+            return self.files.complete(line, pos, ctx);
+        }
+
+        let (start, candidates) = self.complete_line(&line[..pos]);
+        let pairs = candidates
+            .into_iter()
+            .map(|candidate| Pair {
+                display: candidate.clone(),
+                replacement: candidate,
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+
+/// Keep reading lines while a string literal or block comment is open, so
+/// pasted multi-line statements arrive in one piece.
+impl Validator for ReplHelper {
+    fn validate(&self, ctx: &mut ValidationContext<'_>) -> rustyline::Result<ValidationResult> {
+        if is_incomplete(ctx.input()) {
+            Ok(ValidationResult::Incomplete)
+        } else {
+            Ok(ValidationResult::Valid(None))
+        }
+    }
+}
+
+impl Helper for ReplHelper {}
+
+fn starts_with_ignore_case(name: &str, prefix: &str) -> bool {
+    name.len() >= prefix.len()
+        && name.is_char_boundary(prefix.len())
+        && name[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::products_executor;
+
+    async fn helper() -> ReplHelper {
+        let executor = products_executor().await;
+        let mut helper = ReplHelper::new();
+        helper.set_catalog(CompletionCatalog::load(&executor.ctx).await);
+        helper
+    }
+
+    #[tokio::test]
+    async fn test_completes_tables_columns_and_functions() {
+        let helper = helper().await;
+
+        let (start, candidates) = helper.complete_line("SELECT na");
+        assert_eq!(start, 7);
+        assert!(candidates.contains(&"name".to_string()));
+
+        let (_, candidates) = helper.complete_line("SELECT * FROM prod");
+        assert_eq!(candidates, ["products"]);
+
+        let (start, candidates) = helper.complete_line("SELECT products.pr");
+        assert_eq!(start, 7);
+        assert_eq!(candidates, ["products.price"]);
+
+        let (_, candidates) = helper.complete_line("SELECT uppe");
+        assert!(candidates.contains(&"upper".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_completes_schema_qualified_tables() {
+        let ctx = SessionContext::new();
+        for sql in [
+            "CREATE SCHEMA staging",
+            "CREATE TABLE staging.orders (order_id BIGINT, total DOUBLE)",
+        ] {
+            ctx.sql(sql).await.unwrap().collect().await.unwrap();
+        }
+        let mut helper = ReplHelper::new();
+        helper.set_catalog(CompletionCatalog::load(&ctx).await);
+
+        let (start, candidates) = helper.complete_line("SELECT * FROM staging.or");
+        assert_eq!(start, 14);
+        assert_eq!(candidates, ["staging.orders"]);
+        let (_, candidates) = helper.complete_line("SELECT staging.orders.to");
+        assert_eq!(candidates, ["staging.orders.total"]);
+        assert_eq!(
+            helper.complete_line("\\d sta"),
+            (3, vec!["staging.orders".to_string()])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_keywords_follow_the_typed_case() {
+        let helper = helper().await;
+        let (_, candidates) = helper.complete_line("sele");
+        assert!(candidates.contains(&"select".to_string()));
+        let (_, candidates) = helper.complete_line("SELE");
+        assert!(candidates.contains(&"SELECT".to_string()));
+        let (_, candidates) = helper.complete_line("CLA");
+        assert!(candidates.contains(&"CLAUDE".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_completes_meta_commands() {
+        let helper = helper().await;
+        assert_eq!(
+            helper.complete_line("\\fo"),
+            (0, vec!["\\format".to_string()])
+        );
+        assert_eq!(
+            helper.complete_line("\\format j"),
+            (8, vec!["json".to_string()])
+        );
+        assert_eq!(
+            helper.complete_line("\\explain p"),
+            (9, vec!["plan".to_string()])
+        );
+        assert_eq!(
+            helper.complete_line("\\d pro"),
+            (3, vec!["products".to_string()])
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use std::io::Write;
+
+use anyhow::Result;
+use tokio::sync::Mutex;
+
+use crate::sql_executor::{SafeModeViolation, SqlExecutor};
+
+/// Only one approval prompt is shown at a time, even when the agent issues
+/// several `run_sql` calls concurrently.
+static PROMPT_LOCK: Mutex<()> = Mutex::const_new(());
+
+/// A mutation the user declined to run under `--confirm-mutations`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MutationRejected {
+    /// The rejected operation, e.g. `INSERT` or `CREATE TABLE`.
+    pub operation: String,
+}
+
+impl std::fmt::Display for MutationRejected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The user rejected this {} statement.", self.operation)
+    }
+}
+
+impl std::error::Error for MutationRejected {}
+
+/// Ask the user on the terminal before `sql` is allowed to mutate anything.
+///
+/// Read-only queries pass without a prompt, as do statements that do not
+/// parse or plan: running them reports the error. Otherwise the statement and
+/// its logical plan are shown and a declined prompt returns
+/// [`MutationRejected`].
+pub async fn confirm_mutation(executor: &SqlExecutor, sql: &str) -> Result<()> {
+    let operation = match executor.ensure_read_only(sql).await {
+        Ok(()) => return Ok(()),
+        Err(e) => match e.downcast::<SafeModeViolation>() {
+            Ok(violation) => violation.operation,
+            Err(_) => return Ok(()),
+        },
+    };
+
+    let plan = match executor.ctx.state().create_logical_plan(sql).await {
+        Ok(plan) => plan.display_indent().to_string(),
+        Err(e) => format!("(could not be planned: {e})"),
+    };
+
+    let _prompt = PROMPT_LOCK.lock().await;
+    eprintln!("\n[Confirm] The agent wants to run a {operation} statement:\n{sql}\n");
+    eprintln!("Logical plan:\n{plan}\n");
+    eprint!("Run it? [y/N] ");
+    let _ = std::io::stderr().flush();
+
+    let answer = tokio::task::spawn_blocking(|| {
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line).map(|_| line)
+    })
+    .await??;
+
+    if is_approval(&answer) {
+        Ok(())
+    } else {
+        eprintln!("Rejected.");
+        Err(MutationRejected { operation }.into())
+    }
+}
+
+fn is_approval(answer: &str) -> bool {
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_explicit_yes_approves() {
+        assert!(is_approval("y\n"));
+        assert!(is_approval(" YES "));
+        assert!(!is_approval("\n"));
+        assert!(!is_approval("no"));
+        assert!(!is_approval(""));
+    }
+
+    #[tokio::test]
+    async fn test_read_only_queries_need_no_approval() {
+        let exec = SqlExecutor::new().await.unwrap();
+        confirm_mutation(&exec, "SELECT 1").await.unwrap();
+        confirm_mutation(&exec, "SELECT * FROM missing_table")
+            .await
+            .unwrap();
+    }
+}
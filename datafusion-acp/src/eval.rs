@@ -0,0 +1,411 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use datafusion::arrow::datatypes::DataType;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::common::ScalarValue;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::acp_client::{run_acp_traced, AcpConfig};
+use crate::sql_executor::{parse_file_spec, SqlExecutor};
+use crate::telemetry::TokenUsage;
+
+/// Relative tolerance for numbers when a case does not set one.
+const DEFAULT_TOLERANCE: f64 = 1e-6;
+
+/// An `eval` suite file: questions with golden answers.
+///
+/// ```toml
+/// files = ["products.csv"]            # registered for every case
+///
+/// [[case]]
+/// name = "most_expensive"
+/// question = "What is the most expensive product?"
+/// expected_sql = "SELECT name FROM products ORDER BY price DESC LIMIT 1"
+///
+/// [[case]]
+/// name = "average_price"
+/// question = "What is the average product price?"
+/// files = ["sales=sales.parquet"]     # registered for this case only
+/// expected_rows = [[9.99]]
+/// tolerance = 0.001
+/// ```
+///
+/// A `.json` suite has the same shape, with `case` as an array of objects.
+#[derive(Debug, Deserialize)]
+struct SuiteFile {
+    #[serde(default)]
+    files: Vec<String>,
+    #[serde(default, rename = "case")]
+    cases: Vec<EvalCase>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EvalCase {
+    pub name: String,
+    pub question: String,
+    /// File specs (`[table=]path`) to register, after the suite's own.
+    #[serde(default)]
+    pub files: Vec<String>,
+    /// SQL whose result is the expected answer.
+    #[serde(default)]
+    pub expected_sql: Option<String>,
+    /// The expected rows, as lists of values in column order.
+    #[serde(default)]
+    pub expected_rows: Option<Vec<Vec<Value>>>,
+    /// Relative tolerance for comparing numbers.
+    #[serde(default)]
+    pub tolerance: Option<f64>,
+}
+
+/// Load the cases of a suite file.
+///
+/// Relative file paths are resolved against the suite file's directory, and
+/// every case must give exactly one of `expected_sql` and `expected_rows`.
+pub fn load_suite(path: &Path) -> Result<Vec<EvalCase>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read eval suite '{}'", path.display()))?;
+    let suite: SuiteFile = if path.extension().is_some_and(|ext| ext == "json") {
+        serde_json::from_str(&text).map_err(anyhow::Error::from)
+    } else {
+        toml::from_str(&text).map_err(anyhow::Error::from)
+    }
+    .with_context(|| format!("Invalid eval suite '{}'", path.display()))?;
+
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    let resolve = |spec: &String| -> Result<String> {
+        let (name, file) = parse_file_spec(spec)?;
+        if Path::new(&file).is_relative() {
+            Ok(format!("{name}={}", base.join(&file).to_string_lossy()))
+        } else {
+            Ok(format!("{name}={file}"))
+        }
+    };
+    let shared = suite
+        .files
+        .iter()
+        .map(resolve)
+        .collect::<Result<Vec<_>>>()?;
+
+    suite
+        .cases
+        .into_iter()
+        .map(|mut case| {
+            if case.expected_sql.is_some() == case.expected_rows.is_some() {
+                anyhow::bail!(
+                    "Eval case '{}' needs exactly one of expected_sql and expected_rows",
+                    case.name
+                );
+            }
+            let own = case.files.iter().map(resolve).collect::<Result<Vec<_>>>()?;
+            case.files = shared.iter().cloned().chain(own).collect();
+            Ok(case)
+        })
+        .collect()
+}
+
+/// The outcome of one case.
+#[derive(Debug, Clone, Serialize)]
+pub struct CaseResult {
+    pub name: String,
+    pub question: String,
+    pub passed: bool,
+    pub sql: Option<String>,
+    /// Why the case failed: an agent or query error, or how the result
+    /// differed from the expected one.
+    pub error: Option<String>,
+    pub latency_ms: u64,
+    pub tool_calls: usize,
+    pub run_sql_calls: usize,
+    pub repairs: u32,
+    pub usage: Option<TokenUsage>,
+}
+
+/// What `eval` prints: accuracy and averages over the suite, then every case.
+#[derive(Debug, Serialize)]
+pub struct EvalReport {
+    pub suite: String,
+    pub agent: String,
+    pub cases: usize,
+    pub passed: usize,
+    pub accuracy: f64,
+    pub mean_latency_ms: f64,
+    pub mean_tool_calls: f64,
+    pub results: Vec<CaseResult>,
+}
+
+impl EvalReport {
+    pub fn new(suite: &str, agent: &str, results: Vec<CaseResult>) -> Self {
+        let cases = results.len();
+        let passed = results.iter().filter(|r| r.passed).count();
+        let mean = |total: f64| {
+            if cases == 0 {
+                0.0
+            } else {
+                total / cases as f64
+            }
+        };
+        Self {
+            suite: suite.to_string(),
+            agent: agent.to_string(),
+            cases,
+            passed,
+            accuracy: mean(passed as f64),
+            mean_latency_ms: mean(results.iter().map(|r| r.latency_ms as f64).sum()),
+            mean_tool_calls: mean(results.iter().map(|r| r.tool_calls as f64).sum()),
+            results,
+        }
+    }
+}
+
+/// Ask the agent `case.question` against `executor`, with the case's files
+/// registered, and compare the result of its SQL with the expected one.
+pub async fn run_case(
+    case: &EvalCase,
+    executor: Arc<SqlExecutor>,
+    config: &AcpConfig,
+) -> CaseResult {
+    let mut result = CaseResult {
+        name: case.name.clone(),
+        question: case.question.clone(),
+        passed: false,
+        sql: None,
+        error: None,
+        latency_ms: 0,
+        tool_calls: 0,
+        run_sql_calls: 0,
+        repairs: 0,
+        usage: None,
+    };
+    for spec in &case.files {
+        let registered = match parse_file_spec(spec) {
+            Ok((name, path)) => executor.register_file(&name, &path).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = registered {
+            result.error = Some(format!("Failed to load '{spec}': {e:#}"));
+            return result;
+        }
+    }
+
+    let (answer, trace) = run_acp_traced(&case.question, executor.clone(), config).await;
+    if let Some(trace) = trace {
+        result.latency_ms = trace.wall_time_ms;
+        result.tool_calls = trace.tool_calls.len();
+        result.run_sql_calls = trace.run_sql_calls;
+        result.usage = trace.usage;
+    }
+    let answer = match answer {
+        Ok(answer) => answer,
+        Err(e) => {
+            result.error = Some(format!("{e:#}"));
+            return result;
+        }
+    };
+    result.sql = Some(answer.sql.clone());
+    result.repairs = answer.repairs;
+
+    let outcome = async {
+        if config.safe_mode {
+            executor.ensure_read_only(&answer.sql).await?;
+        }
+        let actual = result_rows(&executor, &answer.sql).await?;
+        let expected = match (&case.expected_rows, &case.expected_sql) {
+            (Some(rows), _) => rows.clone(),
+            (None, Some(sql)) => result_rows(&executor, sql)
+                .await
+                .context("The expected SQL failed")?,
+            (None, None) => unreachable!("load_suite checks for an expectation"),
+        };
+        let tolerance = case.tolerance.unwrap_or(DEFAULT_TOLERANCE);
+        compare_rows(&expected, &actual, tolerance).map_err(anyhow::Error::msg)
+    }
+    .await;
+    match outcome {
+        Ok(()) => result.passed = true,
+        Err(e) => result.error = Some(format!("{e:#}")),
+    }
+    result
+}
+
+/// The rows of `sql`, as lists of JSON values in column order. Numbers of
+/// every type become JSON numbers so they compare with tolerance.
+async fn result_rows(executor: &SqlExecutor, sql: &str) -> Result<Vec<Vec<Value>>> {
+    let batches = executor.execute_sql(sql).await?.collect().await?;
+    batch_rows(&batches)
+}
+
+fn batch_rows(batches: &[RecordBatch]) -> Result<Vec<Vec<Value>>> {
+    let mut rows = Vec::new();
+    for batch in batches {
+        for row in 0..batch.num_rows() {
+            let values = batch
+                .columns()
+                .iter()
+                .map(|array| Ok(json_value(ScalarValue::try_from_array(array, row)?)))
+                .collect::<Result<Vec<_>>>()?;
+            rows.push(values);
+        }
+    }
+    Ok(rows)
+}
+
+fn json_value(value: ScalarValue) -> Value {
+    if value.is_null() {
+        return Value::Null;
+    }
+    if let ScalarValue::Boolean(Some(b)) = value {
+        return Value::Bool(b);
+    }
+    if value.data_type().is_numeric() {
+        if let Ok(ScalarValue::Float64(Some(f))) = value.cast_to(&DataType::Float64) {
+            if let Some(number) = serde_json::Number::from_f64(f) {
+                return Value::Number(number);
+            }
+        }
+    }
+    Value::String(value.to_string())
+}
+
+/// Check that `actual` holds the same rows as `expected`, in any order.
+/// Numbers match when they differ by at most `tolerance` relative to the
+/// larger of them (or absolutely, below 1); numeric strings match numbers.
+pub fn compare_rows(
+    expected: &[Vec<Value>],
+    actual: &[Vec<Value>],
+    tolerance: f64,
+) -> std::result::Result<(), String> {
+    if expected.len() != actual.len() {
+        return Err(format!(
+            "Expected {} rows, got {}",
+            expected.len(),
+            actual.len()
+        ));
+    }
+    let mut matched = vec![false; actual.len()];
+    for row in expected {
+        let found = actual.iter().enumerate().position(|(i, candidate)| {
+            !matched[i]
+                && candidate.len() == row.len()
+                && row
+                    .iter()
+                    .zip(candidate)
+                    .all(|(e, a)| values_match(e, a, tolerance))
+        });
+        match found {
+            Some(i) => matched[i] = true,
+            None => {
+                let unmatched = actual
+                    .iter()
+                    .zip(&matched)
+                    .find(|(_, matched)| !**matched)
+                    .map(|(row, _)| Value::from(row.clone()));
+                return Err(format!(
+                    "Expected row {} is not in the result (first unmatched row: {})",
+                    Value::from(row.clone()),
+                    unmatched.unwrap_or_default()
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn values_match(expected: &Value, actual: &Value, tolerance: f64) -> bool {
+    let number = |value: &Value| match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse::<f64>().ok(),
+        _ => None,
+    };
+    match (expected, actual) {
+        (Value::Number(_), _) | (_, Value::Number(_)) => match (number(expected), number(actual)) {
+            (Some(e), Some(a)) => (e - a).abs() <= tolerance * e.abs().max(a.abs()).max(1.0),
+            _ => false,
+        },
+        _ => expected == actual,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn rows(value: Value) -> Vec<Vec<Value>> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_compare_rows_ignores_order_within_tolerance() {
+        let expected = rows(json!([["hardware", 2], ["electronics", 1], [null, 0.3333]]));
+        let actual = rows(json!([
+            [null, 0.33333333],
+            ["electronics", 1.0],
+            ["hardware", "2"]
+        ]));
+        assert!(compare_rows(&expected, &actual, 0.001).is_ok());
+        assert!(compare_rows(&expected, &actual, 1e-9).is_err());
+
+        let err = compare_rows(&expected, &actual[..2], 0.001).unwrap_err();
+        assert_eq!(err, "Expected 3 rows, got 2");
+
+        // Each actual row matches at most one expected row.
+        let err = compare_rows(
+            &rows(json!([["a"], ["a"]])),
+            &rows(json!([["a"], ["b"]])),
+            0.0,
+        )
+        .unwrap_err();
+        assert!(err.contains(r#"first unmatched row: ["b"]"#), "{err}");
+    }
+
+    #[test]
+    fn test_load_suite() {
+        let dir = std::env::temp_dir().join(format!("datafusion_acp_eval_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("suite.toml");
+        std::fs::write(
+            &path,
+            r#"
+files = ["products.csv"]
+
+[[case]]
+name = "count"
+question = "How many products?"
+files = ["s=/data/sales.parquet"]
+expected_rows = [[3]]
+"#,
+        )
+        .unwrap();
+        let cases = load_suite(&path).unwrap();
+        assert_eq!(cases.len(), 1);
+        assert_eq!(
+            cases[0].files,
+            vec![
+                format!("products={}", dir.join("products.csv").display()),
+                "s=/data/sales.parquet".to_string(),
+            ]
+        );
+
+        std::fs::write(&path, "[[case]]\nname = \"x\"\nquestion = \"?\"\n").unwrap();
+        let err = load_suite(&path).unwrap_err();
+        assert!(format!("{err:#}").contains("exactly one of"), "{err:#}");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_batch_rows_normalises_numbers() {
+        let executor = SqlExecutor::new().await.unwrap();
+        let actual = result_rows(
+            &executor,
+            "SELECT 1 AS i, CAST(2.5 AS DECIMAL(5,2)) AS d, 'x' AS s, NULL AS n, true AS b",
+        )
+        .await
+        .unwrap();
+        assert_eq!(actual, rows(json!([[1.0, 2.5, "x", null, true]])));
+    }
+}
@@ -1,26 +1,59 @@
 mod acp_client;
+mod agent_backend;
+mod cache;
+mod catalog;
+mod chat_backend;
 mod claude_statement;
+mod claude_udf;
+mod completion;
+mod confirm;
+mod eval;
 mod mcp_server;
+mod output;
+mod session;
 mod sql_executor;
+mod sqlite;
+mod telemetry;
+mod template;
+#[cfg(test)]
+mod test_util;
 
 use std::collections::HashSet;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use acp_client::{run_acp, AcpConfig};
+use acp_client::{agent_setting, run_acp, AcpConfig, AcpResult, AcpSession};
 use anyhow::{Context, Result};
-use clap::{Parser, ValueEnum};
-use claude_statement::{register_claude_table_function, ClaudeParser, ClaudeStatement};
-use datafusion::arrow::csv;
-use datafusion::arrow::util::pretty::pretty_format_batches;
+use cache::QueryCache;
+use catalog::load_catalog;
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use claude_statement::{
+    is_incomplete, register_claude_functions, split_statements, ClaudeAnswers, ClaudeParser,
+    ClaudeStatement,
+};
+use completion::{CompletionCatalog, ReplHelper};
+use confirm::{confirm_mutation, MutationRejected};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::common::TableReference;
+use eval::{load_suite, run_case, EvalReport};
+use mcp_server::{serve_mcp, ServeTransport};
+use output::{print_result, OutputFormat};
 use rustyline::error::ReadlineError;
-use rustyline::DefaultEditor;
-use sql_executor::{parse_file_spec, SqlExecutor};
+use rustyline::history::DefaultHistory;
+use rustyline::Editor;
+use session::{restore_session, save_session, HistoryEntry};
+use sql_executor::{parse_byte_size, parse_file_spec, ExportFormat, ResourceLimits, SqlExecutor};
+use template::{QueryTemplate, SavedTemplate, TemplateStore};
 
-#[derive(Debug, Clone, ValueEnum)]
-enum OutputFormat {
-    Table,
-    Json,
-    Csv,
+/// How `--explain` shows the agent's final SQL instead of its rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ExplainMode {
+    /// Run the query and report per-operator metrics (EXPLAIN ANALYZE)
+    Analyze,
+    /// Only plan the query (EXPLAIN)
+    Plan,
 }
 
 #[derive(Debug, Parser)]
@@ -30,18 +63,74 @@ enum OutputFormat {
     about = "ACP-backed SQL assistant using DataFusion"
 )]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     #[arg(help = "Natural language query (omit for REPL mode)")]
     query: Option<String>,
 
-    #[arg(short, long = "file", value_name = "PATH", action = clap::ArgAction::Append)]
+    /// Run the SQL and CLAUDE statements in a script file, then exit
+    #[arg(long = "execute-file", value_name = "PATH", conflicts_with = "query")]
+    execute_file: Option<PathBuf>,
+
+    /// Stop at the first failing statement instead of reporting it and moving on
+    #[arg(long = "stop-on-error")]
+    stop_on_error: bool,
+
+    #[arg(short, long = "file", value_name = "PATH", action = clap::ArgAction::Append, global = true)]
     file: Vec<String>,
 
+    #[arg(long = "catalog", value_name = "PATH", global = true)]
+    catalog: Option<String>,
+
+    /// Restore the tables, views and question history saved in this session
+    /// file, and save them back to it when the REPL or script exits
+    #[arg(long = "session", value_name = "PATH")]
+    session: Option<PathBuf>,
+
     #[arg(short = 'o', long = "format", value_enum, default_value_t = OutputFormat::Table)]
     format: OutputFormat,
 
+    /// Fit table output into this many characters, truncating wide columns
+    #[arg(long = "max-width", value_name = "COLS", env = "ACP_MAX_WIDTH")]
+    max_width: Option<usize>,
+
+    #[arg(long = "output-file", value_name = "PATH")]
+    output_file: Option<String>,
+
+    /// Save the agent's answer as a named template to rerun with `run`
+    #[arg(long = "save-template", value_name = "NAME", requires = "query")]
+    save_template: Option<String>,
+
+    /// Where query templates are kept (default: ~/.config/datafusion-acp/templates)
+    #[arg(
+        long = "templates-dir",
+        value_name = "DIR",
+        env = "DATAFUSION_ACP_TEMPLATES",
+        global = true
+    )]
+    templates_dir: Option<PathBuf>,
+
+    /// Print the plans, metrics and a Graphviz graph of the agent's final SQL
+    /// instead of its rows
+    #[arg(
+        long = "explain",
+        value_name = "MODE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "analyze"
+    )]
+    explain: Option<ExplainMode>,
+
+    /// Agent command, tcp://host:port or unix:/path of a running ACP agent,
+    /// or the http(s):// URL of an OpenAI-compatible chat endpoint
     #[arg(short = 'a', long = "agent", env = "ACP_AGENT")]
     agent: Option<String>,
 
+    /// Model to request from a chat endpoint agent
+    #[arg(long = "model", env = "ACP_MODEL")]
+    model: Option<String>,
+
     #[arg(
         short = 't',
         long = "timeout",
@@ -50,19 +139,82 @@ struct Cli {
     )]
     timeout: u64,
 
-    #[arg(long = "safe-mode", env = "ACP_SAFE_MODE", default_value_t = true)]
+    #[arg(
+        long = "safe-mode",
+        env = "ACP_SAFE_MODE",
+        default_value_t = true,
+        global = true
+    )]
     safe_mode: bool,
 
-    #[arg(long = "no-safe-mode", default_value_t = false)]
+    #[arg(long = "no-safe-mode", default_value_t = false, global = true)]
     no_safe_mode: bool,
 
+    #[arg(long = "confirm-mutations", conflicts_with = "no_safe_mode")]
+    confirm_mutations: bool,
+
+    #[arg(long = "no-cache")]
+    no_cache: bool,
+
+    #[arg(
+        long = "max-repairs",
+        env = "ACP_MAX_REPAIRS",
+        value_name = "N",
+        default_value_t = 2
+    )]
+    max_repairs: u32,
+
+    #[arg(long = "validate-limit", value_name = "ROWS")]
+    validate_limit: Option<usize>,
+
+    /// Most rows one run_sql call returns to the agent
+    #[arg(
+        long = "max-rows",
+        env = "ACP_MAX_ROWS",
+        value_name = "N",
+        default_value_t = 1000,
+        global = true
+    )]
+    max_rows: usize,
+
+    /// Largest run_sql result, as JSON, returned to the agent
+    #[arg(
+        long = "max-result-bytes",
+        env = "ACP_MAX_RESULT_BYTES",
+        value_name = "SIZE",
+        default_value = "256K",
+        value_parser = parse_byte_size,
+        global = true
+    )]
+    max_result_bytes: usize,
+
+    /// Time one run_sql query may take
+    #[arg(
+        long = "query-timeout",
+        env = "ACP_QUERY_TIMEOUT",
+        value_name = "SECS",
+        default_value_t = 60,
+        global = true
+    )]
+    query_timeout: u64,
+
+    /// Memory pool size for query execution, e.g. 512M or 4G
+    #[arg(
+        long = "memory-limit",
+        env = "ACP_MEMORY_LIMIT",
+        value_name = "SIZE",
+        value_parser = parse_byte_size,
+        global = true
+    )]
+    memory_limit: Option<usize>,
+
     #[arg(long)]
     debug: bool,
 
     #[arg(long = "show-messages")]
     show_messages: bool,
 
-    #[arg(long = "show-sql")]
+    #[arg(long = "show-sql", global = true)]
     show_sql: bool,
 
     #[arg(long = "show-summary")]
@@ -70,38 +222,153 @@ struct Cli {
 
     #[arg(long = "show-datasources")]
     show_datasources: bool,
+
+    #[arg(long = "show-stats")]
+    show_stats: bool,
+
+    #[arg(long = "trace-file", value_name = "PATH")]
+    trace_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Serve the registered tables to MCP clients (run_sql, list_tables, describe_table)
+    Serve(ServeArgs),
+    /// Score the agent on a suite of questions with golden answers
+    Eval(EvalArgs),
+    /// Rerun a saved query template without the agent, or list the templates
+    Run(RunArgs),
+}
+
+#[derive(Debug, Args)]
+#[command(group(ArgGroup::new("transport").required(true).args(["port", "stdio"])))]
+struct ServeArgs {
+    /// Listen for streamable HTTP connections on this port
+    #[arg(long, value_name = "PORT")]
+    port: Option<u16>,
+
+    /// Address to bind with --port
+    #[arg(long, default_value = "127.0.0.1", requires = "port")]
+    host: IpAddr,
+
+    /// Speak MCP over stdin/stdout instead of HTTP
+    #[arg(long)]
+    stdio: bool,
+}
+
+#[derive(Debug, Args)]
+struct EvalArgs {
+    /// Suite file (TOML or JSON) of questions and expected results
+    #[arg(value_name = "SUITE")]
+    suite: PathBuf,
+
+    /// Exit with an error when fewer than this fraction of cases pass
+    #[arg(long = "min-accuracy", value_name = "FRACTION")]
+    min_accuracy: Option<f64>,
+}
+
+#[derive(Debug, Args)]
+struct RunArgs {
+    /// Template saved with --save-template (omit to list the templates)
+    #[arg(value_name = "NAME")]
+    name: Option<String>,
+
+    /// Value for a template parameter, instead of its default (repeatable)
+    #[arg(
+        short = 'p',
+        long = "param",
+        value_name = "NAME=VALUE",
+        value_parser = parse_param,
+        requires = "name"
+    )]
+    params: Vec<(String, String)>,
+}
+
+fn parse_param(spec: &str) -> Result<(String, String)> {
+    let (name, value) = spec
+        .split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("expected NAME=VALUE, got '{spec}'"))?;
+    Ok((name.trim().to_string(), value.to_string()))
+}
+
+impl Cli {
+    /// Safe mode for one-shot questions, scripts and the REPL, which ask
+    /// before each mutation under `--confirm-mutations`.
+    fn safe_mode(&self) -> bool {
+        !self.no_safe_mode && !self.confirm_mutations && self.safe_mode
+    }
+
+    /// Safe mode for `serve`, `run` and `eval`, which have nobody to ask:
+    /// there `--confirm-mutations` leaves safe mode on.
+    fn unattended_safe_mode(&self, command: &str) -> bool {
+        if self.confirm_mutations {
+            eprintln!("{command} cannot ask before mutations; safe mode stays on.");
+        }
+        !self.no_safe_mode && self.safe_mode
+    }
+}
+
+fn build_resource_limits(cli: &Cli) -> ResourceLimits {
+    ResourceLimits {
+        max_rows: cli.max_rows,
+        max_result_bytes: cli.max_result_bytes,
+        query_timeout: Duration::from_secs(cli.query_timeout),
+        memory_limit: cli.memory_limit,
+    }
 }
 
 fn build_acp_config(cli: &Cli) -> AcpConfig {
     AcpConfig {
         agent_command: cli.agent.clone(),
+        model: cli.model.clone(),
         debug: cli.debug,
         show_messages: cli.show_messages,
         show_sql: cli.show_sql,
         show_summary: cli.show_summary,
         show_datasources: cli.show_datasources,
         timeout_secs: cli.timeout,
-        safe_mode: if cli.no_safe_mode {
-            false
+        safe_mode: cli.safe_mode(),
+        confirm_mutations: cli.confirm_mutations,
+        cache_dir: if cli.no_cache {
+            None
         } else {
-            cli.safe_mode
+            QueryCache::default_dir()
         },
+        max_repairs: cli.max_repairs,
+        validation_limit: cli.validate_limit,
+        show_stats: cli.show_stats,
+        trace_file: cli.trace_file.clone(),
     }
 }
 
-async fn register_files(executor: &SqlExecutor, specs: &[String]) -> Result<()> {
+async fn register_files(executor: &SqlExecutor, cli: &Cli) -> Result<()> {
+    let catalog = match &cli.catalog {
+        Some(path) => load_catalog(path)?,
+        None => Vec::new(),
+    };
+
     let mut names = HashSet::new();
-    for spec in specs {
-        let (name, path) = parse_file_spec(spec)?;
-        if !std::path::Path::new(&path).exists() {
-            anyhow::bail!("File not found: '{}'", path);
-        }
-        if !names.insert(name.clone()) {
+    let mut check_unique = |name: &str| {
+        if !names.insert(name.to_string()) {
             anyhow::bail!(
                 "Duplicate table name '{}'. Use explicit table_name=path to disambiguate.",
                 name
             );
         }
+        Ok(())
+    };
+
+    for table in &catalog {
+        check_unique(&table.name)?;
+        executor
+            .register_with_options(&table.name, &table.path, &table.options)
+            .await
+            .with_context(|| format!("Failed to load '{}={}'", table.name, table.path))?;
+    }
+
+    for spec in &cli.file {
+        let (name, path) = parse_file_spec(spec)?;
+        check_unique(&name)?;
         executor
             .register_file(&name, &path)
             .await
@@ -110,30 +377,82 @@ async fn register_files(executor: &SqlExecutor, specs: &[String]) -> Result<()>
     Ok(())
 }
 
-async fn print_dataframe(executor: &SqlExecutor, sql: &str, format: &OutputFormat) -> Result<()> {
-    match format {
-        OutputFormat::Json => {
-            let json = executor.execute_sql_json(sql).await?;
-            println!("{json}");
-        }
-        OutputFormat::Table => {
-            let df = executor.execute_sql(sql).await?;
-            let batches = df.collect().await?;
-            println!("{}", pretty_format_batches(&batches)?);
-        }
-        OutputFormat::Csv => {
-            let df = executor.execute_sql(sql).await?;
-            let batches = df.collect().await?;
-            let mut out = Vec::new();
-            {
-                let mut writer = csv::Writer::new(&mut out);
-                for batch in &batches {
-                    writer.write(batch)?;
-                }
-            }
-            print!("{}", String::from_utf8_lossy(&out));
-        }
+/// Register the `--file` and `--catalog` tables, then restore `--session`
+/// if its file exists. Returns the session's question history.
+async fn open_session(executor: &SqlExecutor, cli: &Cli) -> Result<Vec<HistoryEntry>> {
+    register_files(executor, cli).await?;
+    match &cli.session {
+        Some(path) if path.exists() => restore_session(executor, path).await,
+        _ => Ok(Vec::new()),
+    }
+}
+
+async fn print_dataframe(
+    executor: &SqlExecutor,
+    sql: &str,
+    format: &OutputFormat,
+    max_width: Option<usize>,
+    safe_mode: bool,
+) -> Result<Vec<RecordBatch>> {
+    if safe_mode {
+        executor.ensure_read_only(sql).await?;
+    }
+    let df = executor.execute_sql(sql).await?;
+    print_result(df, format, max_width).await
+}
+
+/// Print the result of `sql`, or stream it to `output_file` when one is set.
+///
+/// Returns the printed batches; exported results are never held in memory.
+async fn emit_result(
+    executor: &SqlExecutor,
+    sql: &str,
+    format: &OutputFormat,
+    max_width: Option<usize>,
+    output_file: Option<&str>,
+    safe_mode: bool,
+) -> Result<Option<Vec<RecordBatch>>> {
+    let Some(path) = output_file else {
+        return print_dataframe(executor, sql, format, max_width, safe_mode)
+            .await
+            .map(Some);
+    };
+
+    if safe_mode {
+        executor.ensure_read_only(sql).await?;
+    }
+    let rows = executor.write_sql_to_file(sql, path).await?;
+    eprintln!("Wrote {rows} rows to '{path}'");
+    Ok(None)
+}
+
+/// Print the plans of the agent's final SQL next to its summary, running it
+/// first for `ExplainMode::Analyze`.
+async fn print_explanation(
+    executor: &SqlExecutor,
+    result: &AcpResult,
+    mode: ExplainMode,
+    safe_mode: bool,
+) -> Result<()> {
+    if safe_mode {
+        executor.ensure_read_only(&result.sql).await?;
+    }
+    let analyze = mode == ExplainMode::Analyze;
+    let plan = executor.explain_sql(&result.sql, analyze).await?;
+
+    if let Some(summary) = &result.summary {
+        println!("== Summary ==\n{summary}\n");
+    }
+    println!("== SQL ==\n{}\n", result.sql);
+    println!("== Logical Plan ==\n{}", plan.logical);
+    match plan.rows {
+        Some(rows) => println!(
+            "== Physical Plan (analyzed, {rows} rows) ==\n{}",
+            plan.physical
+        ),
+        None => println!("== Physical Plan ==\n{}", plan.physical),
     }
+    println!("== Graphviz ==\n{}", plan.dot.trim());
     Ok(())
 }
 
@@ -143,71 +462,642 @@ async fn run_one_shot(cli: &Cli) -> Result<()> {
         .as_deref()
         .ok_or_else(|| anyhow::anyhow!("query is required in one-shot mode"))?;
 
-    let executor = Arc::new(SqlExecutor::new().await?);
-    register_files(&executor, &cli.file).await?;
+    if let Some(path) = &cli.output_file {
+        ExportFormat::from_path(path)?;
+    }
+
+    let executor = Arc::new(SqlExecutor::with_limits(build_resource_limits(cli)).await?);
+    open_session(&executor, cli).await?;
 
     let config = build_acp_config(cli);
     let result = run_acp(query, executor.clone(), &config).await?;
     let _ = (&result.summary, &result.datasources);
-    print_dataframe(&executor, &result.sql, &cli.format).await
+    if config.confirm_mutations {
+        confirm_mutation(&executor, &result.sql).await?;
+    }
+    if let Some(name) = &cli.save_template {
+        save_template(cli, name, query, &result)?;
+    }
+    if let Some(mode) = cli.explain {
+        return print_explanation(&executor, &result, mode, config.safe_mode).await;
+    }
+    emit_result(
+        &executor,
+        &result.sql,
+        &cli.format,
+        cli.max_width,
+        cli.output_file.as_deref(),
+        config.safe_mode,
+    )
+    .await?;
+    Ok(())
+}
+
+fn template_store(cli: &Cli) -> Result<TemplateStore> {
+    cli.templates_dir
+        .clone()
+        .or_else(TemplateStore::default_dir)
+        .map(TemplateStore::new)
+        .ok_or_else(|| anyhow::anyhow!("No templates directory: set --templates-dir or HOME"))
+}
+
+/// `--save-template NAME`: keep the answer to `question` for `run NAME`. An
+/// answer without parameters is saved as a template that takes none.
+fn save_template(cli: &Cli, name: &str, question: &str, result: &AcpResult) -> Result<()> {
+    let saved = SavedTemplate {
+        question: question.to_string(),
+        template: result.template.clone().unwrap_or_else(|| QueryTemplate {
+            sql: result.sql.clone(),
+            parameters: Vec::new(),
+        }),
+    };
+    template_store(cli)?.save(name, &saved)?;
+    let parameters = saved
+        .template
+        .parameters
+        .iter()
+        .map(|p| p.name.as_str())
+        .collect::<Vec<_>>();
+    if parameters.is_empty() {
+        eprintln!("Saved template '{name}' (no parameters).");
+    } else {
+        eprintln!(
+            "Saved template '{name}' with parameters {}.",
+            parameters.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// `run NAME --param k=v`: run a saved template with its placeholders bound
+/// to the given values or their defaults. `run` alone lists the templates.
+async fn run_template(cli: &Cli, args: &RunArgs) -> Result<()> {
+    let store = template_store(cli)?;
+    let Some(name) = &args.name else {
+        for (name, saved) in store.list()? {
+            let parameters = saved
+                .template
+                .parameters
+                .iter()
+                .map(|p| {
+                    let param_type = serde_json::to_value(p.param_type)?;
+                    Ok(format!(
+                        "{}: {} = {}",
+                        p.name,
+                        param_type.as_str().unwrap_or_default(),
+                        p.default
+                    ))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            println!("{name}({})\n    {}", parameters.join(", "), saved.question);
+        }
+        return Ok(());
+    };
+
+    let saved = store.load(name)?;
+    let template = saved.template;
+    let values = template.values(&args.params)?;
+
+    let executor = Arc::new(SqlExecutor::with_limits(build_resource_limits(cli)).await?);
+    open_session(&executor, cli).await?;
+    if cli.show_sql {
+        eprintln!("\n[Final SQL]\n{}", template.render(&values)?);
+    }
+    if cli.unattended_safe_mode("run") {
+        executor.ensure_read_only(&template.sql).await?;
+    }
+    let df = template.dataframe(&executor, values).await?;
+    match &cli.output_file {
+        Some(path) => {
+            let rows = executor.write_dataframe_to_file(df, path).await?;
+            eprintln!("Wrote {rows} rows to '{path}'");
+        }
+        None => {
+            print_result(df, &cli.format, cli.max_width).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn run_serve(cli: &Cli, args: &ServeArgs) -> Result<()> {
+    let executor = Arc::new(SqlExecutor::with_limits(build_resource_limits(cli)).await?);
+    open_session(&executor, cli).await?;
+
+    let transport = match (args.stdio, args.port) {
+        (true, None) => ServeTransport::Stdio,
+        (false, Some(port)) => ServeTransport::Http(SocketAddr::new(args.host, port)),
+        _ => unreachable!("the transport group takes exactly one of --port and --stdio"),
+    };
+    let safe_mode = cli.unattended_safe_mode("serve");
+    serve_mcp(executor, safe_mode, cli.show_sql, transport).await
+}
+
+/// Run every case of an eval suite on a fresh catalog and print the report
+/// as JSON.
+async fn run_eval(cli: &Cli, args: &EvalArgs) -> Result<()> {
+    let cases = load_suite(&args.suite)?;
+    let mut config = build_acp_config(cli);
+    // Every case has to reach the agent to measure it.
+    config.cache_dir = None;
+    config.safe_mode = cli.unattended_safe_mode("eval");
+    config.confirm_mutations = false;
+
+    let mut results = Vec::new();
+    for case in &cases {
+        let executor = Arc::new(SqlExecutor::with_limits(build_resource_limits(cli)).await?);
+        register_files(&executor, cli).await?;
+        let result = run_case(case, executor, &config).await;
+        match &result.error {
+            None => eprintln!("[PASS] {}", case.name),
+            Some(error) => eprintln!("[FAIL] {}: {error}", case.name),
+        }
+        results.push(result);
+    }
+
+    let report = EvalReport::new(
+        &args.suite.to_string_lossy(),
+        &agent_setting(&config),
+        results,
+    );
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    if let Some(min) = args.min_accuracy {
+        if report.accuracy < min {
+            anyhow::bail!(
+                "Accuracy {:.3} is below --min-accuracy {min}",
+                report.accuracy
+            );
+        }
+    }
+    Ok(())
 }
 
 async fn run_repl(cli: &Cli) -> Result<()> {
-    let executor = Arc::new(SqlExecutor::new().await?);
-    register_files(&executor, &cli.file).await?;
+    // The ACP connection behind a conversation is !Send, so the REPL runs on
+    // a LocalSet to keep one session alive across questions.
+    let local = tokio::task::LocalSet::new();
+    local.run_until(repl_loop(cli)).await
+}
+
+async fn run_script(cli: &Cli, path: &Path) -> Result<()> {
+    let script = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read script '{}'", path.display()))?;
+    let statements = split_statements(&script);
+
+    // Like the REPL, CLAUDE statements in a script share one conversation.
+    let local = tokio::task::LocalSet::new();
+    local
+        .run_until(async {
+            let mut runner = StatementRunner::new(cli).await?;
+            let outcome = runner.run_all(&statements, cli.stop_on_error).await;
+            if let Some(path) = &cli.session {
+                save_session(&runner.executor, &runner.history, path).await?;
+            }
+            runner.close().await;
+            let failed = outcome?;
+            if failed > 0 {
+                anyhow::bail!("{failed} of {} statements failed", statements.len());
+            }
+            Ok(())
+        })
+        .await
+}
 
-    let config = Arc::new(build_acp_config(cli));
-    register_claude_table_function(&executor.ctx, executor.clone(), config.clone())?;
+/// What the statements of a REPL session or script share: the agent
+/// conversation, the questions asked so far and the current output settings.
+struct StatementRunner {
+    executor: Arc<SqlExecutor>,
+    config: Arc<AcpConfig>,
+    claude: ClaudeAnswers,
+    session: AcpSession,
+    history: Vec<HistoryEntry>,
+    format: OutputFormat,
+    max_width: Option<usize>,
+    output_file: Option<String>,
+    timing: bool,
+    explain: Option<ExplainMode>,
+}
 
-    let mut rl = DefaultEditor::new()?;
+impl StatementRunner {
+    async fn new(cli: &Cli) -> Result<Self> {
+        let executor = Arc::new(SqlExecutor::with_limits(build_resource_limits(cli)).await?);
+        let history = open_session(&executor, cli).await?;
+
+        let config = Arc::new(build_acp_config(cli));
+        let claude = register_claude_functions(&executor.ctx, executor.clone(), config.clone())?;
+
+        let mut session = AcpSession::new(executor.clone(), &config);
+        if let Some(last) = history.last() {
+            session.resume(&last.question, &last.sql);
+        }
+        Ok(Self {
+            executor,
+            config,
+            claude,
+            session,
+            history,
+            format: cli.format.clone(),
+            max_width: cli.max_width,
+            output_file: cli.output_file.clone(),
+            timing: false,
+            explain: cli.explain,
+        })
+    }
+
+    /// Run one SQL or `CLAUDE` statement. A final query the user rejects
+    /// under `--confirm-mutations` is skipped rather than failed.
+    async fn run(&mut self, statement: &str) -> Result<()> {
+        let started = Instant::now();
+        let mut parser = ClaudeParser::new(statement)?;
+        match parser.parse_statement()? {
+            ClaudeStatement::Claude(nl) => {
+                let result = self.session.prompt(&nl).await?;
+                self.history.push(HistoryEntry {
+                    question: nl.clone(),
+                    sql: result.sql.clone(),
+                });
+                if self.config.confirm_mutations {
+                    match confirm_mutation(&self.executor, &result.sql).await {
+                        Ok(()) => {}
+                        Err(e) if e.is::<MutationRejected>() => return Ok(()),
+                        Err(e) => return Err(e),
+                    }
+                }
+                if let Some(mode) = self.explain {
+                    print_explanation(&self.executor, &result, mode, self.config.safe_mode).await?;
+                    return Ok(());
+                }
+                let batches = emit_result(
+                    &self.executor,
+                    &result.sql,
+                    &self.format,
+                    self.max_width,
+                    self.output_file.as_deref(),
+                    self.config.safe_mode,
+                )
+                .await?;
+                if let Some(batches) = batches {
+                    self.session.record_result(&batches)?;
+                }
+            }
+            ClaudeStatement::DFStatement(stmt) => {
+                drop(stmt);
+                self.claude.prepare(statement).await?;
+                emit_result(
+                    &self.executor,
+                    statement,
+                    &self.format,
+                    self.max_width,
+                    self.output_file.as_deref(),
+                    self.config.safe_mode,
+                )
+                .await?;
+            }
+        }
+        if self.timing {
+            eprintln!("Time: {:.3} ms", started.elapsed().as_secs_f64() * 1000.0);
+        }
+        Ok(())
+    }
+
+    /// Run `statements` in order. A failure is reported and the next
+    /// statement runs, unless `stop_on_error` is set, in which case the
+    /// failure is returned. Returns how many statements failed.
+    async fn run_all(&mut self, statements: &[String], stop_on_error: bool) -> Result<usize> {
+        let mut failed = 0;
+        for statement in statements {
+            if let Err(e) = self.run(statement).await {
+                if stop_on_error {
+                    return Err(e);
+                }
+                eprintln!("Error: {e:#}");
+                failed += 1;
+            }
+        }
+        Ok(failed)
+    }
+
+    /// Start a new agent conversation.
+    async fn reset(&mut self) {
+        let session = AcpSession::new(self.executor.clone(), &self.config);
+        std::mem::replace(&mut self.session, session).close().await;
+    }
+
+    async fn close(self) {
+        self.session.close().await;
+    }
+}
+
+/// Where REPL history is kept between sessions. `DATAFUSION_ACP_HISTORY`
+/// overrides the default `~/.datafusion_acp_history`; setting it to an empty
+/// string disables history.
+fn history_path() -> Option<PathBuf> {
+    match std::env::var_os("DATAFUSION_ACP_HISTORY") {
+        Some(path) if path.is_empty() => None,
+        Some(path) => Some(PathBuf::from(path)),
+        None => {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".datafusion_acp_history"))
+        }
+    }
+}
+
+/// `\d`: list the registered tables.
+async fn list_tables(
+    executor: &SqlExecutor,
+    format: &OutputFormat,
+    max_width: Option<usize>,
+) -> Result<()> {
+    print_dataframe(
+        executor,
+        "SELECT table_schema, table_name, table_type FROM information_schema.tables \
+         WHERE table_schema <> 'information_schema' ORDER BY table_schema, table_name",
+        format,
+        max_width,
+        false,
+    )
+    .await?;
+    Ok(())
+}
+
+/// `\d table`: show the columns and types of `table`, which may be
+/// qualified with its schema, as in `\d staging.orders`.
+async fn describe_table(
+    executor: &SqlExecutor,
+    table: &str,
+    format: &OutputFormat,
+    max_width: Option<usize>,
+) -> Result<()> {
+    let reference = TableReference::from(table);
+    if !executor.ctx.table_exist(reference.clone())? {
+        anyhow::bail!("Table '{table}' not found");
+    }
+    let options = executor.ctx.state().config().options().catalog.clone();
+    let quote = |name: &str| format!("'{}'", name.replace('\'', "''"));
+    let sql = format!(
+        "SELECT column_name, data_type, is_nullable FROM information_schema.columns \
+         WHERE table_catalog = {} AND table_schema = {} AND table_name = {} \
+         ORDER BY ordinal_position",
+        quote(reference.catalog().unwrap_or(&options.default_catalog)),
+        quote(reference.schema().unwrap_or(&options.default_schema)),
+        quote(reference.table()),
+    );
+    print_dataframe(executor, &sql, format, max_width, false).await?;
+    Ok(())
+}
+
+/// `\load name=path`: register another file while the REPL is running.
+async fn load_file(executor: &SqlExecutor, spec: &str) -> Result<()> {
+    let (name, path) = parse_file_spec(spec)?;
+    if executor
+        .ctx
+        .table_exist(TableReference::bare(name.as_str()))?
+    {
+        anyhow::bail!("Table '{name}' is already registered");
+    }
+    executor
+        .register_file(&name, &path)
+        .await
+        .with_context(|| format!("Failed to load '{}={}'", name, path))?;
+    eprintln!("Registered '{path}' as table '{name}'.");
+    Ok(())
+}
+
+/// `\save [path]`: save the session to `path`, or to the `--session` file.
+async fn save_to(runner: &StatementRunner, arg: &str, default: Option<&Path>) -> Result<()> {
+    let path = match (arg, default) {
+        ("", Some(path)) => path.to_path_buf(),
+        ("", None) => anyhow::bail!("No --session file to save to. Usage: \\save PATH"),
+        (path, _) => PathBuf::from(path),
+    };
+    save_session(&runner.executor, &runner.history, &path).await?;
+    eprintln!("Saved the session to '{}'.", path.display());
+    Ok(())
+}
 
-    loop {
-        let line = match rl.readline("datafusion-acp> ") {
+/// `\history`: list the questions asked and the SQL they were answered with.
+fn print_history(history: &[HistoryEntry]) {
+    if history.is_empty() {
+        eprintln!("No questions asked yet.");
+    }
+    for (i, entry) in history.iter().enumerate() {
+        println!("{}. {}\n{}\n", i + 1, entry.question, entry.sql);
+    }
+}
+
+/// `\explain` toggles explaining agent answers; `\explain analyze`,
+/// `\explain plan` and `\explain off` pick the mode.
+fn set_explain(explain: &mut Option<ExplainMode>, arg: &str) -> Result<()> {
+    *explain = match arg {
+        "" if explain.is_some() => None,
+        "" => Some(ExplainMode::Analyze),
+        "off" => None,
+        mode => Some(ExplainMode::from_str(mode, true).map_err(|_| {
+            anyhow::anyhow!("Unknown mode '{mode}', expected analyze, plan or off")
+        })?),
+    };
+    match explain {
+        Some(ExplainMode::Analyze) => eprintln!("Explaining answers with EXPLAIN ANALYZE."),
+        Some(ExplainMode::Plan) => eprintln!("Explaining answers with EXPLAIN."),
+        None => eprintln!("Explain is off."),
+    }
+    Ok(())
+}
+
+/// `\format [table|json|csv|ndjson|markdown|arrow|vertical]`: change or show
+/// the REPL output format.
+fn set_format(format: &mut OutputFormat, arg: &str) -> Result<()> {
+    if !arg.is_empty() {
+        *format = OutputFormat::from_str(arg, true)
+            .map_err(|_| anyhow::anyhow!(
+                "Unknown format '{arg}', expected table, json, csv, ndjson, markdown, arrow or vertical"
+            ))?;
+    }
+    if let Some(value) = format.to_possible_value() {
+        eprintln!("Output format is {}.", value.get_name());
+    }
+    Ok(())
+}
+
+async fn repl_loop(cli: &Cli) -> Result<()> {
+    let mut runner = StatementRunner::new(cli).await?;
+
+    let mut rl: Editor<ReplHelper, DefaultHistory> = Editor::new()?;
+    rl.set_helper(Some(ReplHelper::new()));
+    let history = history_path();
+    if let Some(path) = &history {
+        // A missing history file just means this is the first session.
+        rl.load_history(path).ok();
+    }
+
+    // Input read so far when a string literal or block comment spans lines.
+    let mut buffer = String::new();
+
+    // Errors end the loop rather than returning, so the session is still
+    // saved.
+    let outcome = loop {
+        // Completion is synchronous, so take a fresh snapshot of the catalog
+        // before every prompt to pick up tables created in the meantime.
+        let catalog = CompletionCatalog::load(&runner.executor.ctx).await;
+        if let Some(helper) = rl.helper_mut() {
+            helper.set_catalog(catalog);
+        }
+
+        let prompt = if buffer.is_empty() {
+            "datafusion-acp> "
+        } else {
+            "datafusion-acp-> "
+        };
+        let line = match rl.readline(prompt) {
             Ok(line) => line,
-            Err(ReadlineError::Interrupted) => continue,
-            Err(ReadlineError::Eof) => break,
-            Err(e) => return Err(e.into()),
+            Err(ReadlineError::Interrupted) => {
+                buffer.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => break Ok(()),
+            Err(e) => break Err(e.into()),
         };
 
-        let trimmed = line.trim();
+        if !buffer.is_empty() {
+            buffer.push('\n');
+        }
+        buffer.push_str(&line);
+        if is_incomplete(&buffer) {
+            continue;
+        }
+        let input = std::mem::take(&mut buffer);
+
+        let trimmed = input.trim();
         if trimmed.is_empty() {
             continue;
         }
         if matches!(trimmed.to_ascii_lowercase().as_str(), "exit" | "quit") {
-            break;
+            break Ok(());
         }
 
         rl.add_history_entry(trimmed).ok();
+        if let Some(path) = &history {
+            if let Err(e) = rl.append_history(path) {
+                tracing::warn!("Failed to save history to '{}': {}", path.display(), e);
+            }
+        }
 
-        let mut parser = ClaudeParser::new(trimmed)?;
-        match parser.parse_statement()? {
-            ClaudeStatement::Claude(nl) => {
-                let result = run_acp(&nl, executor.clone(), &config).await?;
-                print_dataframe(&executor, &result.sql, &cli.format).await?;
+        if trimmed == "\\reset" {
+            runner.reset().await;
+            eprintln!("Started a new conversation.");
+            continue;
+        }
+
+        if trimmed == "\\cache clear" {
+            match &runner.config.cache_dir {
+                Some(dir) => match QueryCache::new(dir).clear() {
+                    Ok(removed) => eprintln!("Removed {removed} cached answers."),
+                    Err(e) => eprintln!("Error: {e:#}"),
+                },
+                None => eprintln!("The query cache is disabled."),
             }
-            ClaudeStatement::DFStatement(stmt) => {
-                drop(stmt);
-                print_dataframe(&executor, trimmed, &cli.format).await?;
+            continue;
+        }
+
+        if trimmed == "\\history" {
+            print_history(&runner.history);
+            continue;
+        }
+
+        if trimmed == "\\timing" {
+            runner.timing = !runner.timing;
+            eprintln!("Timing is {}.", if runner.timing { "on" } else { "off" });
+            continue;
+        }
+
+        if let Some((command, arg)) = meta_command(trimmed) {
+            let outcome = match command {
+                "\\explain" => set_explain(&mut runner.explain, arg),
+                "\\d" if arg.is_empty() => {
+                    list_tables(&runner.executor, &runner.format, runner.max_width).await
+                }
+                "\\d" => {
+                    describe_table(&runner.executor, arg, &runner.format, runner.max_width).await
+                }
+                "\\load" => load_file(&runner.executor, arg).await,
+                "\\format" => set_format(&mut runner.format, arg),
+                "\\save" => save_to(&runner, arg, cli.session.as_deref()).await,
+                _ => Ok(()),
+            };
+            if let Err(e) = outcome {
+                eprintln!("Error: {e:#}");
+            }
+            continue;
+        }
+
+        if let Some(arg) = trimmed.strip_prefix("\\o") {
+            if arg.is_empty() || arg.starts_with(char::is_whitespace) {
+                runner.output_file = match arg.trim() {
+                    "" => None,
+                    path => {
+                        if let Err(e) = ExportFormat::from_path(path) {
+                            eprintln!("Error: {e:#}");
+                            continue;
+                        }
+                        Some(path.to_string())
+                    }
+                };
+                match &runner.output_file {
+                    Some(path) => eprintln!("Writing results to '{path}'."),
+                    None => eprintln!("Writing results to stdout."),
+                }
+                continue;
             }
         }
+
+        // With --stop-on-error a failure skips the rest of the line, but
+        // the REPL carries on.
+        if let Err(e) = runner
+            .run_all(&split_statements(trimmed), cli.stop_on_error)
+            .await
+        {
+            eprintln!("Error: {e:#}");
+        }
+    };
+
+    if let Some(path) = &cli.session {
+        save_session(&runner.executor, &runner.history, path).await?;
     }
+    runner.close().await;
 
-    Ok(())
+    outcome
+}
+
+/// Split `\d`, `\load`, `\format`, `\explain` and `\save` lines into the
+/// command and its argument.
+fn meta_command(line: &str) -> Option<(&str, &str)> {
+    let (command, arg) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    matches!(
+        command,
+        "\\d" | "\\load" | "\\format" | "\\explain" | "\\save"
+    )
+    .then(|| (command, arg.trim()))
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
 
+    // Logs go to stderr: stdout carries query results and, with `serve
+    // --stdio`, the MCP protocol itself.
     tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
         .with_target(false)
         .try_init()
         .ok();
 
-    if cli.query.is_some() {
-        run_one_shot(&cli).await
-    } else {
-        run_repl(&cli).await
+    match &cli.command {
+        Some(Command::Serve(args)) => run_serve(&cli, args).await,
+        Some(Command::Eval(args)) => run_eval(&cli, args).await,
+        Some(Command::Run(args)) => run_template(&cli, args).await,
+        None => match &cli.execute_file {
+            Some(path) => run_script(&cli, path).await,
+            None if cli.query.is_some() => run_one_shot(&cli).await,
+            None => run_repl(&cli).await,
+        },
     }
 }
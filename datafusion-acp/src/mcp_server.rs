@@ -1,4 +1,6 @@
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Instant;
 
 use axum::Router;
 use rmcp::{
@@ -7,24 +9,49 @@ use rmcp::{
     transport::streamable_http_server::{
         session::local::LocalSessionManager, StreamableHttpServerConfig, StreamableHttpService,
     },
-    ErrorData as McpError,
+    ErrorData as McpError, ServiceExt,
 };
 use serde::Deserialize;
 use serde_json::Value;
 use tokio::net::TcpListener;
 use tokio::sync::{mpsc, oneshot};
 
-use crate::sql_executor::{is_mutation_sql, SqlExecutor};
+use crate::confirm::{confirm_mutation, MutationRejected};
+use crate::sql_executor::{LimitExceeded, SafeModeViolation, SqlExecutor};
+use crate::telemetry::TraceRecorder;
+use crate::template::{QueryTemplate, TemplateParameter};
+
+/// URI prefix of the per-table resources, e.g. `datafusion://tables/orders`.
+pub const TABLE_RESOURCE_PREFIX: &str = "datafusion://tables/";
+
+/// Rows included in each table resource.
+const SAMPLE_ROWS: usize = 5;
 
 #[derive(Debug, Clone)]
 pub struct FinalQueryResult {
+    /// The final SQL, with any template parameters at their defaults.
     pub sql: String,
     pub summary: Option<String>,
     pub datasources: Option<String>,
+    /// Set when the agent declared `parameters` for `$placeholders` in its SQL.
+    pub template: Option<QueryTemplate>,
+}
+
+/// How the standalone `serve` mode talks to its MCP client.
+#[derive(Debug, Clone, Copy)]
+pub enum ServeTransport {
+    /// Streamable HTTP on a fixed address.
+    Http(SocketAddr),
+    /// JSON-RPC over stdin/stdout, for clients that spawn the server.
+    Stdio,
 }
 
-fn get_instructions(safe_mode: bool) -> String {
-    let restrictions = if safe_mode {
+fn get_instructions(safe_mode: bool, confirm_mutations: bool, final_query: bool) -> String {
+    let restrictions = if confirm_mutations {
+        "MODE: Mutations need approval\n\
+        INSERT, UPDATE, DELETE, CREATE, etc. are shown to the user, who must approve them.\n\
+        A rejected statement returns an error; adjust your approach instead of retrying it.\n\n"
+    } else if safe_mode {
         "RESTRICTIONS (READ-ONLY MODE):\n\
         - ONLY use SELECT queries to read data\n\
         - DO NOT use INSERT, UPDATE, DELETE, DROP, ALTER, CREATE, TRUNCATE\n\
@@ -37,30 +64,52 @@ fn get_instructions(safe_mode: bool) -> String {
 
     let catalog_exploration = "\
         CATALOG EXPLORATION (do this first!):\n\
-        DataFusion uses standard information_schema. Use these queries to understand the data:\n\
+        Every table is an MCP resource `datafusion://tables/<name>` holding its columns, row count,\n\
+        column statistics and sample rows. Read those resources first if you can; it saves queries.\n\
+        Otherwise use `list_tables` and `describe_table`, or query the standard information_schema:\n\
         - SELECT table_catalog, table_schema, table_name FROM information_schema.tables;\n\
         - SELECT column_name, data_type FROM information_schema.columns WHERE table_name = 'x';\n\
         - SHOW TABLES;\n\n\
         IMPORTANT: Always explore the catalog first to understand what data is available!\n\n";
 
+    if !final_query {
+        return format!(
+            "DataFusion MCP Server.\n\n\
+            Query the tables registered in this DataFusion session.\n\n\
+            TOOLS:\n\
+            1) `list_tables` - List the available tables\n\
+            2) `describe_table` - Show the columns and types of a table\n\
+            3) `run_sql` - Execute SQL and return the rows as JSON\n\n\
+            {restrictions}\
+            {catalog_exploration}\
+            Tips:\n\
+            - DataFusion SQL dialect (supports CTEs, window functions, standard SQL)\n\
+            - Keep LIMIT small during exploration"
+        );
+    }
+
     format!(
         "DataFusion MCP Server for ACP/CLAUDE SQL generation.\n\n\
         You are being invoked from a DataFusion-based SQL environment.\n\
         Your job is to generate a SQL query that answers the user's question.\n\n\
         TOOLS:\n\
-        1) `run_sql` - Execute SQL to explore the catalog/schema and test queries\n\
-        2) `final_query` - YOU MUST CALL THIS at the end with your final SQL answer\n\n\
+        1) `list_tables` / `describe_table` - Inspect the catalog\n\
+        2) `run_sql` - Execute SQL to explore the catalog/schema and test queries\n\
+        3) `final_query` - YOU MUST CALL THIS at the end with your final SQL answer\n\n\
         {restrictions}\
         {catalog}\
         Workflow:\n\
-        1. EXPLORE: Use run_sql to query information_schema and understand available tables/columns\n\
-        2. INVESTIGATE: Look at sample data with SELECT * FROM table LIMIT 5\n\
+        1. EXPLORE: Read the table resources, or use list_tables, describe_table or information_schema\n\
+        2. INVESTIGATE: Look at sample data (in the resources, or SELECT * FROM table LIMIT 5)\n\
         3. BUILD: Construct and test your query\n\
         4. SUBMIT: ALWAYS call final_query with the SQL that answers the question\n\n\
         Tips:\n\
         - DataFusion SQL dialect (supports CTEs, window functions, standard SQL)\n\
         - Keep LIMIT small during exploration\n\
-        - Use information_schema for catalog queries (no PRAGMA, no DESCRIBE)",
+        - Use information_schema for catalog queries (no PRAGMA, no DESCRIBE)\n\
+        - If the question names a value likely to change between runs (a date, a region),\n  \
+          you may write it as a named placeholder like $region and declare it in the\n  \
+          `parameters` of final_query with a type and the value asked for as default",
         restrictions = restrictions,
         catalog = catalog_exploration
     )
@@ -70,8 +119,14 @@ fn get_instructions(safe_mode: bool) -> String {
 struct DataFusionMcpService {
     executor: Arc<SqlExecutor>,
     safe_mode: bool,
+    /// Ask the user before `run_sql` executes a mutation.
+    confirm_mutations: bool,
     show_sql: bool,
-    final_result_tx: mpsc::Sender<FinalQueryResult>,
+    /// Set while serving an ACP prompt; `None` in standalone mode, which hides
+    /// the `final_query` tool.
+    final_result_tx: Option<mpsc::Sender<FinalQueryResult>>,
+    /// Records each `run_sql` call for `--show-stats` / `--trace-file`.
+    trace: Option<TraceRecorder>,
 }
 
 #[derive(Deserialize)]
@@ -79,6 +134,11 @@ struct RunSqlParams {
     sql: String,
 }
 
+#[derive(Deserialize)]
+struct DescribeTableParams {
+    table: String,
+}
+
 #[derive(Deserialize)]
 struct FinalQueryParams {
     sql: String,
@@ -86,15 +146,38 @@ struct FinalQueryParams {
     summary: Option<String>,
     #[serde(default)]
     datasources: Option<String>,
+    #[serde(default)]
+    parameters: Vec<TemplateParameter>,
+}
+
+fn parse_params<T: serde::de::DeserializeOwned>(args: JsonObject) -> Result<T, McpError> {
+    serde_json::from_value(Value::Object(args))
+        .map_err(|e| McpError::invalid_params(format!("bad arguments: {e}"), None))
+}
+
+fn json_text(result: anyhow::Result<String>) -> CallToolResult {
+    let text = match result {
+        Ok(json) => json,
+        Err(e) => serde_json::json!({"error": e.to_string()}).to_string(),
+    };
+    CallToolResult::success(vec![Content::text(text)])
 }
 
 impl ServerHandler for DataFusionMcpService {
     fn get_info(&self) -> ServerInfo {
         ServerInfo {
             protocol_version: ProtocolVersion::V_2024_11_05,
-            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            capabilities: ServerCapabilities::builder()
+                .enable_tools()
+                .enable_resources()
+                .enable_prompts()
+                .build(),
             server_info: Implementation::from_build_env(),
-            instructions: Some(get_instructions(self.safe_mode)),
+            instructions: Some(get_instructions(
+                self.safe_mode,
+                self.confirm_mutations,
+                self.final_result_tx.is_some(),
+            )),
         }
     }
 
@@ -125,40 +208,94 @@ impl ServerHandler for DataFusionMcpService {
 
         let run_sql_tool = Tool::new("run_sql", run_sql_desc, rmcp::model::object(run_sql_schema));
 
-        let final_query_schema = serde_json::json!({
+        let list_tables_schema = serde_json::json!({
+            "type": "object",
+            "properties": {},
+            "additionalProperties": false
+        });
+
+        let list_tables_tool = Tool::new(
+            "list_tables",
+            "List the tables available in the DataFusion catalog.",
+            rmcp::model::object(list_tables_schema),
+        );
+
+        let describe_table_schema = serde_json::json!({
             "type": "object",
             "properties": {
-                "sql": {
-                    "type": "string",
-                    "description": "The final SQL query that answers the user's question"
-                },
-                "summary": {
+                "table": {
                     "type": "string",
-                    "description": "A brief summary of the analysis"
-                },
-                "datasources": {
-                    "type": "string",
-                    "description": "Description of data sources used"
+                    "description": "Table name, optionally qualified as schema.table"
                 }
             },
-            "required": ["sql"],
+            "required": ["table"],
             "additionalProperties": false
         });
 
-        let final_query_desc = if safe_mode {
-            "REQUIRED: Call this with the final query. READ-ONLY MODE: Only SELECT queries allowed."
-        } else {
-            "REQUIRED: Call this with the final SQL that answers the user's question."
-        };
-
-        let final_query_tool = Tool::new(
-            "final_query",
-            final_query_desc,
-            rmcp::model::object(final_query_schema),
+        let describe_table_tool = Tool::new(
+            "describe_table",
+            "Show the column names, data types and nullability of a table.",
+            rmcp::model::object(describe_table_schema),
         );
 
+        let mut tools = vec![run_sql_tool, list_tables_tool, describe_table_tool];
+
+        if self.final_result_tx.is_some() {
+            let final_query_schema = serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "sql": {
+                        "type": "string",
+                        "description": "The final SQL query that answers the user's question"
+                    },
+                    "summary": {
+                        "type": "string",
+                        "description": "A brief summary of the analysis"
+                    },
+                    "datasources": {
+                        "type": "string",
+                        "description": "Description of data sources used"
+                    },
+                    "parameters": {
+                        "type": "array",
+                        "description": "Optional named parameters for values likely to change between runs, such as a date or a region. Refer to each one as $name in the SQL; the query is run with the defaults now and can be rerun later with other values.",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "name": { "type": "string" },
+                                "type": {
+                                    "type": "string",
+                                    "enum": ["string", "integer", "float", "boolean", "date", "timestamp"]
+                                },
+                                "default": {
+                                    "type": ["string", "number", "boolean"],
+                                    "description": "Value used for this run"
+                                }
+                            },
+                            "required": ["name", "type", "default"],
+                            "additionalProperties": false
+                        }
+                    }
+                },
+                "required": ["sql"],
+                "additionalProperties": false
+            });
+
+            let final_query_desc = if safe_mode {
+                "REQUIRED: Call this with the final query. READ-ONLY MODE: Only SELECT queries allowed."
+            } else {
+                "REQUIRED: Call this with the final SQL that answers the user's question."
+            };
+
+            tools.push(Tool::new(
+                "final_query",
+                final_query_desc,
+                rmcp::model::object(final_query_schema),
+            ));
+        }
+
         std::future::ready(Ok(ListToolsResult {
-            tools: vec![run_sql_tool, final_query_tool],
+            tools,
             next_cursor: None,
         }))
     }
@@ -170,52 +307,136 @@ impl ServerHandler for DataFusionMcpService {
     ) -> impl std::future::Future<Output = Result<CallToolResult, McpError>> + Send + '_ {
         let executor = self.executor.clone();
         let safe_mode = self.safe_mode;
+        let confirm_mutations = self.confirm_mutations;
         let show_sql = self.show_sql;
         let final_result_tx = self.final_result_tx.clone();
+        let trace = self.trace.clone();
 
         async move {
-            let args = request
-                .arguments
-                .ok_or_else(|| McpError::invalid_params("missing arguments", None))?;
+            let args = request.arguments.unwrap_or_default();
 
-            match request.name.as_ref() {
-                "run_sql" => {
-                    let params: RunSqlParams = serde_json::from_value(Value::Object(args))
-                        .map_err(|e| {
-                            McpError::invalid_params(format!("bad arguments: {e}"), None)
-                        })?;
+            match (request.name.as_ref(), final_result_tx) {
+                ("run_sql", _) => {
+                    let params: RunSqlParams = parse_params(args)?;
 
                     if show_sql {
                         eprintln!("\n[Explore SQL]\n{}", params.sql);
                     }
 
-                    if safe_mode && is_mutation_sql(&params.sql) {
-                        return Ok(CallToolResult::success(vec![Content::text(
-                            r#"{"error": "Safe mode is enabled. Mutation queries (INSERT, UPDATE, DELETE, DROP, etc.) are not allowed."}"#,
-                        )]));
+                    let started = Instant::now();
+                    let record = |outcome: Result<usize, String>| {
+                        if let Some(trace) = &trace {
+                            trace.record_sql(&params.sql, started.elapsed(), outcome);
+                        }
+                    };
+
+                    if safe_mode {
+                        if let Err(e) = executor.ensure_read_only(&params.sql).await {
+                            let error = match e.downcast_ref::<SafeModeViolation>() {
+                                Some(violation) => serde_json::json!({
+                                    "error": violation.to_string(),
+                                    "rejected_operation": violation.operation,
+                                }),
+                                None => serde_json::json!({"error": e.to_string()}),
+                            };
+                            record(Err(e.to_string()));
+                            return Ok(CallToolResult::error(vec![Content::text(
+                                error.to_string(),
+                            )]));
+                        }
                     }
 
-                    let result = match executor.execute_sql_json(&params.sql).await {
-                        Ok(json) => json,
-                        Err(e) => serde_json::json!({"error": e.to_string()}).to_string(),
-                    };
+                    if confirm_mutations {
+                        if let Err(e) = confirm_mutation(&executor, &params.sql).await {
+                            let error = match e.downcast_ref::<MutationRejected>() {
+                                Some(rejected) => serde_json::json!({
+                                    "error": rejected.to_string(),
+                                    "rejected_operation": rejected.operation,
+                                    "hint": "Adjust the statement or answer without it.",
+                                }),
+                                None => serde_json::json!({"error": e.to_string()}),
+                            };
+                            record(Err(e.to_string()));
+                            return Ok(CallToolResult::error(vec![Content::text(
+                                error.to_string(),
+                            )]));
+                        }
+                    }
 
-                    Ok(CallToolResult::success(vec![Content::text(result)]))
+                    let result = executor.run_exploration_sql(&params.sql).await;
+                    record(
+                        result
+                            .as_ref()
+                            .map(|result| result.rows)
+                            .map_err(|e| e.to_string()),
+                    );
+
+                    match result {
+                        Ok(result) if result.truncated => {
+                            let rows: Value = serde_json::from_str(&result.json)
+                                .map_err(|e| McpError::internal_error(e.to_string(), None))?;
+                            let truncated = serde_json::json!({
+                                "rows": rows,
+                                "truncated": true,
+                                "note": format!(
+                                    "Only the first {} rows are shown. Aggregate, filter or add \
+                                     a LIMIT to see the rest.",
+                                    result.rows
+                                ),
+                            });
+                            Ok(CallToolResult::success(vec![Content::text(
+                                truncated.to_string(),
+                            )]))
+                        }
+                        Ok(result) => Ok(json_text(Ok(result.json))),
+                        Err(e) => match e.downcast_ref::<LimitExceeded>() {
+                            Some(exceeded) => {
+                                let error = serde_json::json!({
+                                    "error": exceeded.to_string(),
+                                    "limit_exceeded": exceeded.limit,
+                                });
+                                Ok(CallToolResult::error(vec![Content::text(
+                                    error.to_string(),
+                                )]))
+                            }
+                            None => Ok(json_text(Err(e))),
+                        },
+                    }
+                }
+                ("list_tables", _) => Ok(json_text(executor.list_tables_json().await)),
+                ("describe_table", _) => {
+                    let params: DescribeTableParams = parse_params(args)?;
+                    Ok(json_text(executor.describe_table_json(&params.table).await))
                 }
-                "final_query" => {
-                    let params: FinalQueryParams = serde_json::from_value(Value::Object(args))
-                        .map_err(|e| {
-                            McpError::invalid_params(format!("bad arguments: {e}"), None)
-                        })?;
+                ("final_query", Some(final_result_tx)) => {
+                    let params: FinalQueryParams = parse_params(args)?;
+
+                    // A template is answered with its default instance, which
+                    // is what gets validated and run now.
+                    let template = if params.parameters.is_empty() {
+                        None
+                    } else {
+                        let template = QueryTemplate::new(&params.sql, params.parameters)
+                            .and_then(|t| Ok((t.render(&t.values(&[])?)?, t)));
+                        match template {
+                            Ok(template) => Some(template),
+                            Err(e) => return Ok(json_text(Err(e))),
+                        }
+                    };
+                    let (sql, template) = match template {
+                        Some((sql, template)) => (sql, Some(template)),
+                        None => (params.sql, None),
+                    };
 
                     let final_result = FinalQueryResult {
-                        sql: params.sql.clone(),
+                        sql: sql.clone(),
                         summary: params.summary,
                         datasources: params.datasources,
+                        template,
                     };
                     let _ = final_result_tx.try_send(final_result);
 
-                    let result = serde_json::json!({ "FINAL_SQL": params.sql });
+                    let result = serde_json::json!({ "FINAL_SQL": sql });
                     Ok(CallToolResult::success(vec![Content::text(
                         result.to_string(),
                     )]))
@@ -227,20 +448,166 @@ impl ServerHandler for DataFusionMcpService {
             }
         }
     }
+
+    fn list_resources(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: rmcp::service::RequestContext<rmcp::service::RoleServer>,
+    ) -> impl std::future::Future<Output = Result<ListResourcesResult, McpError>> + Send + '_ {
+        let executor = self.executor.clone();
+        async move {
+            let tables = executor
+                .table_names()
+                .await
+                .map_err(|e| McpError::internal_error(e.to_string(), None))?;
+            let resources = tables
+                .into_iter()
+                .map(|table| {
+                    let mut resource =
+                        RawResource::new(format!("{TABLE_RESOURCE_PREFIX}{table}"), &table);
+                    resource.description = Some(format!(
+                        "Columns, row count, column statistics and sample rows of {table}"
+                    ));
+                    resource.mime_type = Some("application/json".to_string());
+                    resource.no_annotation()
+                })
+                .collect();
+            Ok(ListResourcesResult {
+                resources,
+                next_cursor: None,
+            })
+        }
+    }
+
+    fn read_resource(
+        &self,
+        request: ReadResourceRequestParam,
+        _context: rmcp::service::RequestContext<rmcp::service::RoleServer>,
+    ) -> impl std::future::Future<Output = Result<ReadResourceResult, McpError>> + Send + '_ {
+        let executor = self.executor.clone();
+        async move {
+            let Some(table) = request.uri.strip_prefix(TABLE_RESOURCE_PREFIX) else {
+                return Err(McpError::resource_not_found(
+                    format!("Unknown resource: {}", request.uri),
+                    None,
+                ));
+            };
+            let profile = executor
+                .table_profile_json(table, SAMPLE_ROWS)
+                .await
+                .map_err(|e| McpError::resource_not_found(format!("{e:#}"), None))?;
+            Ok(ReadResourceResult {
+                contents: vec![ResourceContents::TextResourceContents {
+                    uri: request.uri,
+                    mime_type: Some("application/json".to_string()),
+                    text: profile,
+                    meta: None,
+                }],
+            })
+        }
+    }
+
+    fn list_prompts(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: rmcp::service::RequestContext<rmcp::service::RoleServer>,
+    ) -> impl std::future::Future<Output = Result<ListPromptsResult, McpError>> + Send + '_ {
+        let question = PromptArgument {
+            name: "question".to_string(),
+            title: None,
+            description: Some("The question the SQL should answer".to_string()),
+            required: Some(true),
+        };
+        std::future::ready(Ok(ListPromptsResult {
+            prompts: vec![Prompt::new(
+                "answer_question",
+                Some("Answer a question with SQL, given every table's schema, statistics and sample rows"),
+                Some(vec![question]),
+            )],
+            next_cursor: None,
+        }))
+    }
+
+    fn get_prompt(
+        &self,
+        request: GetPromptRequestParam,
+        _context: rmcp::service::RequestContext<rmcp::service::RoleServer>,
+    ) -> impl std::future::Future<Output = Result<GetPromptResult, McpError>> + Send + '_ {
+        let executor = self.executor.clone();
+        let final_query = self.final_result_tx.is_some();
+        async move {
+            if request.name != "answer_question" {
+                return Err(McpError::invalid_params(
+                    format!("Unknown prompt: {}", request.name),
+                    None,
+                ));
+            }
+            let question = request
+                .arguments
+                .as_ref()
+                .and_then(|args| args.get("question"))
+                .and_then(Value::as_str)
+                .ok_or_else(|| McpError::invalid_params("missing argument: question", None))?
+                .to_string();
+
+            let internal = |e: anyhow::Error| McpError::internal_error(format!("{e:#}"), None);
+            let mut messages = Vec::new();
+            for table in executor.table_names().await.map_err(internal)? {
+                // One table that cannot be profiled (a timeout, the memory
+                // limit, a type `describe` rejects) should not cost the agent
+                // the context of all the others.
+                let profile = match executor.table_profile_json(&table, SAMPLE_ROWS).await {
+                    Ok(profile) => profile,
+                    Err(e) => {
+                        messages.push(PromptMessage::new_text(
+                            PromptMessageRole::User,
+                            format!(
+                                "Table `{table}` could not be profiled ({e:#}). Use \
+                                 `describe_table` to see its columns."
+                            ),
+                        ));
+                        continue;
+                    }
+                };
+                messages.push(PromptMessage::new_resource(
+                    PromptMessageRole::User,
+                    format!("{TABLE_RESOURCE_PREFIX}{table}"),
+                    Some("application/json".to_string()),
+                    Some(profile),
+                    None,
+                    None,
+                    None,
+                ));
+            }
+            let submit = if final_query {
+                "Test it with `run_sql`, then call `final_query` with it."
+            } else {
+                "Test it with `run_sql` and reply with the final SQL."
+            };
+            messages.push(PromptMessage::new_text(
+                PromptMessageRole::User,
+                format!(
+                    "The tables above are everything in the catalog. Write one DataFusion SQL \
+                     query that answers: {question}\n\n{submit}"
+                ),
+            ));
+
+            Ok(GetPromptResult {
+                description: Some(format!("Answer with SQL: {question}")),
+                messages,
+            })
+        }
+    }
 }
 
-pub async fn start_mcp_http_server(
+fn mcp_router(
     executor: Arc<SqlExecutor>,
     safe_mode: bool,
+    confirm_mutations: bool,
     show_sql: bool,
-) -> anyhow::Result<(u16, oneshot::Sender<()>, mpsc::Receiver<FinalQueryResult>)> {
-    let listener = TcpListener::bind("127.0.0.1:0").await?;
-    let addr = listener.local_addr()?;
-    let port = addr.port();
-
-    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
-    let (final_result_tx, final_result_rx) = mpsc::channel::<FinalQueryResult>(1);
-
+    final_result_tx: Option<mpsc::Sender<FinalQueryResult>>,
+    trace: Option<TraceRecorder>,
+) -> Router {
     let session_manager = Arc::new(LocalSessionManager::default());
     let config = StreamableHttpServerConfig {
         sse_keep_alive: Some(std::time::Duration::from_secs(15)),
@@ -252,15 +619,41 @@ pub async fn start_mcp_http_server(
             Ok(DataFusionMcpService {
                 executor: executor.clone(),
                 safe_mode,
+                confirm_mutations,
                 show_sql,
                 final_result_tx: final_result_tx.clone(),
+                trace: trace.clone(),
             })
         },
         session_manager,
         config,
     );
 
-    let app = Router::new().fallback_service(tower::ServiceBuilder::new().service(mcp_service));
+    Router::new().fallback_service(tower::ServiceBuilder::new().service(mcp_service))
+}
+
+pub async fn start_mcp_http_server(
+    executor: Arc<SqlExecutor>,
+    safe_mode: bool,
+    confirm_mutations: bool,
+    show_sql: bool,
+    trace: TraceRecorder,
+) -> anyhow::Result<(u16, oneshot::Sender<()>, mpsc::Receiver<FinalQueryResult>)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let port = addr.port();
+
+    let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+    let (final_result_tx, final_result_rx) = mpsc::channel::<FinalQueryResult>(1);
+
+    let app = mcp_router(
+        executor,
+        safe_mode,
+        confirm_mutations,
+        show_sql,
+        Some(final_result_tx),
+        Some(trace),
+    );
 
     tokio::spawn(async move {
         let server = axum::serve(listener, app).with_graceful_shutdown(async {
@@ -275,9 +668,56 @@ pub async fn start_mcp_http_server(
     Ok((port, shutdown_tx, final_result_rx))
 }
 
+/// Serve the catalog to any MCP client until it disconnects or Ctrl-C.
+///
+/// Unlike [`start_mcp_http_server`], this exposes only the exploration tools:
+/// there is no ACP prompt waiting for a `final_query`.
+pub async fn serve_mcp(
+    executor: Arc<SqlExecutor>,
+    safe_mode: bool,
+    show_sql: bool,
+    transport: ServeTransport,
+) -> anyhow::Result<()> {
+    match transport {
+        ServeTransport::Http(addr) => {
+            let listener = TcpListener::bind(addr)
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to bind MCP server to {addr}: {e}"))?;
+            eprintln!("MCP server listening on http://{}/", listener.local_addr()?);
+
+            let app = mcp_router(executor, safe_mode, false, show_sql, None, None);
+            axum::serve(listener, app)
+                .with_graceful_shutdown(async {
+                    let _ = tokio::signal::ctrl_c().await;
+                })
+                .await?;
+        }
+        ServeTransport::Stdio => {
+            let service = DataFusionMcpService {
+                executor,
+                safe_mode,
+                confirm_mutations: false,
+                show_sql,
+                final_result_tx: None,
+                trace: None,
+            };
+            let server = service.serve(rmcp::transport::stdio()).await?;
+            tokio::select! {
+                result = server.waiting() => {
+                    result?;
+                }
+                _ = tokio::signal::ctrl_c() => {}
+            }
+        }
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::sql_executor::ResourceLimits;
+    use crate::test_util::products_executor;
     use serde_json::json;
     use tokio::time::{sleep, timeout, Duration};
 
@@ -285,7 +725,7 @@ mod tests {
         exec: Arc<SqlExecutor>,
         safe_mode: bool,
     ) -> Option<(u16, oneshot::Sender<()>, mpsc::Receiver<FinalQueryResult>)> {
-        match start_mcp_http_server(exec, safe_mode, false).await {
+        match start_mcp_http_server(exec, safe_mode, false, false, TraceRecorder::default()).await {
             Ok(server) => Some(server),
             Err(e) => {
                 let msg = e.to_string().to_lowercase();
@@ -381,6 +821,48 @@ mod tests {
         let _ = shutdown_tx.send(());
     }
 
+    #[tokio::test]
+    async fn test_run_sql_limits_over_http() {
+        let limits = ResourceLimits {
+            max_rows: 10,
+            max_result_bytes: 1024,
+            ..Default::default()
+        };
+        let exec = Arc::new(SqlExecutor::with_limits(limits).await.unwrap());
+        let Some((port, shutdown_tx, _rx)) = start_server_or_skip(exec, true).await else {
+            return;
+        };
+        sleep(Duration::from_millis(50)).await;
+
+        let call = |id: u64, sql: &str| {
+            json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "method": "tools/call",
+                "params": {"name": "run_sql", "arguments": {"sql": sql}}
+            })
+        };
+
+        let response = post_mcp_request(port, call(13, "SELECT * FROM generate_series(1, 100)"))
+            .await
+            .unwrap();
+        let result: serde_json::Value =
+            serde_json::from_str(first_tool_text(&response).unwrap()).unwrap();
+        assert_eq!(result["truncated"], true);
+        assert_eq!(result["rows"].as_array().unwrap().len(), 10);
+        assert!(result["note"].as_str().unwrap().contains("first 10 rows"));
+
+        let response = post_mcp_request(port, call(14, "SELECT repeat('x', 2000) AS wide"))
+            .await
+            .unwrap();
+        assert_eq!(response["result"]["isError"], true);
+        let error: serde_json::Value =
+            serde_json::from_str(first_tool_text(&response).unwrap()).unwrap();
+        assert_eq!(error["limit_exceeded"], "max_result_bytes");
+
+        let _ = shutdown_tx.send(());
+    }
+
     #[tokio::test]
     async fn test_safe_mode_blocks_mutation_over_http() {
         let exec = Arc::new(SqlExecutor::new().await.unwrap());
@@ -402,11 +884,14 @@ mod tests {
         });
 
         let response = post_mcp_request(port, payload).await.unwrap();
+        assert_eq!(response["result"]["isError"], true);
         let text = first_tool_text(&response).unwrap_or_default();
         assert!(
             text.contains("Safe mode is enabled"),
             "expected safe mode error, got: {text}"
         );
+        let error: serde_json::Value = serde_json::from_str(text).unwrap();
+        assert_eq!(error["rejected_operation"], "DROP TABLE");
 
         let _ = shutdown_tx.send(());
     }
@@ -445,6 +930,204 @@ mod tests {
         let _ = shutdown_tx.send(());
     }
 
+    #[tokio::test]
+    async fn test_standalone_tools_over_http() {
+        let exec = Arc::new(products_executor().await);
+
+        let listener = match TcpListener::bind("127.0.0.1:0").await {
+            Ok(listener) => listener,
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied => return,
+            Err(e) => panic!("unexpected error binding MCP server: {e}"),
+        };
+        let port = listener.local_addr().unwrap().port();
+        let app = mcp_router(exec, true, false, false, None, None);
+        let server = tokio::spawn(async move { axum::serve(listener, app).await });
+        sleep(Duration::from_millis(50)).await;
+
+        let response = post_mcp_request(
+            port,
+            json!({"jsonrpc": "2.0", "id": 5, "method": "tools/list"}),
+        )
+        .await
+        .unwrap();
+        let names = response["result"]["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tool| tool["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["run_sql", "list_tables", "describe_table"]);
+
+        let response = post_mcp_request(
+            port,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 6,
+                "method": "tools/call",
+                "params": {"name": "list_tables", "arguments": {}}
+            }),
+        )
+        .await
+        .unwrap();
+        let text = first_tool_text(&response).unwrap_or_default();
+        assert!(text.contains("\"table_name\":\"products\""), "{text}");
+
+        let response = post_mcp_request(
+            port,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 7,
+                "method": "tools/call",
+                "params": {"name": "describe_table", "arguments": {"table": "products"}}
+            }),
+        )
+        .await
+        .unwrap();
+        let columns: serde_json::Value =
+            serde_json::from_str(first_tool_text(&response).unwrap()).unwrap();
+        assert_eq!(columns[1]["column_name"], "name");
+        assert_eq!(columns[1]["data_type"], "Utf8");
+
+        let response = post_mcp_request(
+            port,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 8,
+                "method": "tools/call",
+                "params": {"name": "final_query", "arguments": {"sql": "SELECT 1"}}
+            }),
+        )
+        .await
+        .unwrap();
+        assert!(
+            response.get("error").is_some(),
+            "final_query should not exist in standalone mode: {response}"
+        );
+
+        server.abort();
+    }
+
+    #[tokio::test]
+    async fn test_table_resources_and_prompt_over_http() {
+        let exec = Arc::new(products_executor().await);
+        let Some((port, shutdown_tx, _rx)) = start_server_or_skip(exec, true).await else {
+            return;
+        };
+        sleep(Duration::from_millis(50)).await;
+
+        let response = post_mcp_request(
+            port,
+            json!({"jsonrpc": "2.0", "id": 9, "method": "resources/list"}),
+        )
+        .await
+        .unwrap();
+        let resources = response["result"]["resources"].as_array().unwrap();
+        assert_eq!(resources.len(), 1);
+        assert_eq!(resources[0]["uri"], "datafusion://tables/products");
+        assert_eq!(resources[0]["name"], "products");
+
+        let response = post_mcp_request(
+            port,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 10,
+                "method": "resources/read",
+                "params": {"uri": "datafusion://tables/products"}
+            }),
+        )
+        .await
+        .unwrap();
+        let profile: serde_json::Value =
+            serde_json::from_str(response["result"]["contents"][0]["text"].as_str().unwrap())
+                .unwrap();
+        assert_eq!(profile["row_count"], 3);
+        assert_eq!(profile["sample"].as_array().unwrap().len(), 3);
+
+        let response = post_mcp_request(
+            port,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 11,
+                "method": "resources/read",
+                "params": {"uri": "datafusion://tables/missing"}
+            }),
+        )
+        .await
+        .unwrap();
+        assert!(response.get("error").is_some(), "{response}");
+
+        let response = post_mcp_request(
+            port,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 12,
+                "method": "prompts/get",
+                "params": {
+                    "name": "answer_question",
+                    "arguments": {"question": "cheapest product"}
+                }
+            }),
+        )
+        .await
+        .unwrap();
+        let messages = response["result"]["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 2);
+        // One embedded table resource, then the question.
+        let resource = messages[0]["content"].to_string();
+        assert!(
+            resource.contains("datafusion://tables/products"),
+            "{resource}"
+        );
+        assert!(resource.contains("Widget"), "{resource}");
+        let text = messages[1]["content"]["text"].as_str().unwrap();
+        assert!(text.contains("cheapest product") && text.contains("final_query"));
+
+        let _ = shutdown_tx.send(());
+    }
+
+    #[tokio::test]
+    async fn test_prompt_skips_tables_that_fail_to_profile() {
+        let exec = products_executor().await;
+        exec.ctx
+            .sql("CREATE VIEW broken AS SELECT CAST(name AS INT) AS n FROM products")
+            .await
+            .unwrap();
+        let Some((port, shutdown_tx, _rx)) = start_server_or_skip(Arc::new(exec), true).await
+        else {
+            return;
+        };
+        sleep(Duration::from_millis(50)).await;
+
+        let response = post_mcp_request(
+            port,
+            json!({
+                "jsonrpc": "2.0",
+                "id": 13,
+                "method": "prompts/get",
+                "params": {
+                    "name": "answer_question",
+                    "arguments": {"question": "cheapest product"}
+                }
+            }),
+        )
+        .await
+        .unwrap();
+        let messages = response["result"]["messages"].as_array().unwrap();
+        assert_eq!(messages.len(), 3, "{response}");
+        let note = messages[0]["content"]["text"].as_str().unwrap();
+        assert!(
+            note.contains("`broken` could not be profiled") && note.contains("describe_table"),
+            "{note}"
+        );
+        let resource = messages[1]["content"].to_string();
+        assert!(
+            resource.contains("datafusion://tables/products"),
+            "{resource}"
+        );
+
+        let _ = shutdown_tx.send(());
+    }
+
     #[tokio::test]
     async fn test_final_query_tool_captures_result_over_http() {
         let exec = Arc::new(SqlExecutor::new().await.unwrap());
@@ -0,0 +1,323 @@
+use std::io::{IsTerminal, Write};
+
+use anyhow::Result;
+use clap::ValueEnum;
+use datafusion::arrow::csv;
+use datafusion::arrow::datatypes::SchemaRef;
+use datafusion::arrow::ipc::writer::StreamWriter;
+use datafusion::arrow::json::LineDelimitedWriter;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::arrow::util::display::{ArrayFormatter, FormatOptions};
+use datafusion::arrow::util::pretty::pretty_format_batches;
+use datafusion::prelude::DataFrame;
+use futures::StreamExt;
+
+use crate::acp_client::PREVIEW_ROWS;
+use crate::sql_executor::batches_to_json;
+
+#[derive(Debug, Clone, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Csv,
+    /// One JSON object per line, written batch by batch
+    Ndjson,
+    /// A Markdown table
+    Markdown,
+    /// An Arrow IPC stream, for piping into other tools
+    Arrow,
+    /// One block per row, a line per column
+    Vertical,
+}
+
+/// Narrowest a column is squeezed to by `--max-width`, ellipsis included.
+const MIN_COLUMN_WIDTH: usize = 4;
+
+/// Print the rows of `df` to stdout in `format`.
+///
+/// CSV, NDJSON and Arrow are written batch by batch as the query runs; only
+/// their first [`PREVIEW_ROWS`] rows are returned, which is all a follow-up
+/// prompt shows. The other formats need every row and return them all.
+pub async fn print_result(
+    df: DataFrame,
+    format: &OutputFormat,
+    max_width: Option<usize>,
+) -> Result<Vec<RecordBatch>> {
+    if matches!(
+        format,
+        OutputFormat::Csv | OutputFormat::Ndjson | OutputFormat::Arrow
+    ) {
+        return stream_result(df, format).await;
+    }
+
+    let schema = df.schema().inner().clone();
+    let batches = df.collect().await?;
+    let text = match format {
+        OutputFormat::Json => batches_to_json(&batches)?,
+        OutputFormat::Table => match max_width {
+            Some(width) => fitted_table(&schema, &batches, width)?,
+            None => pretty_format_batches(&batches)?.to_string(),
+        },
+        OutputFormat::Markdown => markdown_table(&schema, &batches)?,
+        OutputFormat::Vertical => vertical(&schema, &batches)?,
+        OutputFormat::Csv | OutputFormat::Ndjson | OutputFormat::Arrow => {
+            unreachable!("streamed above")
+        }
+    };
+    println!("{text}");
+    Ok(batches)
+}
+
+enum BatchWriter {
+    Csv(Box<csv::Writer<std::io::Stdout>>),
+    Ndjson(LineDelimitedWriter<std::io::Stdout>),
+    Arrow(StreamWriter<std::io::Stdout>),
+}
+
+async fn stream_result(df: DataFrame, format: &OutputFormat) -> Result<Vec<RecordBatch>> {
+    let schema = df.schema().inner().clone();
+    let mut writer = match format {
+        OutputFormat::Csv => BatchWriter::Csv(Box::new(csv::Writer::new(std::io::stdout()))),
+        OutputFormat::Ndjson => BatchWriter::Ndjson(LineDelimitedWriter::new(std::io::stdout())),
+        _ => {
+            if std::io::stdout().is_terminal() {
+                anyhow::bail!(
+                    "Refusing to write an Arrow IPC stream to a terminal. Redirect or pipe stdout."
+                );
+            }
+            BatchWriter::Arrow(StreamWriter::try_new(std::io::stdout(), &schema)?)
+        }
+    };
+
+    let mut stream = df.execute_stream().await?;
+    let mut preview = Vec::new();
+    let mut previewed = 0;
+    while let Some(batch) = stream.next().await {
+        let batch = batch?;
+        match &mut writer {
+            BatchWriter::Csv(writer) => writer.write(&batch)?,
+            BatchWriter::Ndjson(writer) => writer.write(&batch)?,
+            BatchWriter::Arrow(writer) => writer.write(&batch)?,
+        }
+        std::io::stdout().flush()?;
+        if previewed < PREVIEW_ROWS {
+            let rows = batch.num_rows().min(PREVIEW_ROWS - previewed);
+            preview.push(batch.slice(0, rows));
+            previewed += rows;
+        }
+    }
+    match &mut writer {
+        BatchWriter::Csv(_) => {}
+        BatchWriter::Ndjson(writer) => writer.finish()?,
+        BatchWriter::Arrow(writer) => writer.finish()?,
+    }
+    std::io::stdout().flush()?;
+    Ok(preview)
+}
+
+/// Column names and every row of `batches` as display strings; NULL is
+/// shown as an empty string.
+fn cells(schema: &SchemaRef, batches: &[RecordBatch]) -> Result<(Vec<String>, Vec<Vec<String>>)> {
+    let headers = schema.fields().iter().map(|f| f.name().clone()).collect();
+    let options = FormatOptions::default();
+    let mut rows = Vec::new();
+    for batch in batches {
+        let formatters = batch
+            .columns()
+            .iter()
+            .map(|array| ArrayFormatter::try_new(array.as_ref(), &options))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        for row in 0..batch.num_rows() {
+            rows.push(
+                formatters
+                    .iter()
+                    .map(|formatter| formatter.value(row).to_string())
+                    .collect(),
+            );
+        }
+    }
+    Ok((headers, rows))
+}
+
+/// The same layout as the default table, squeezed to at most `max_width`
+/// characters: the widest columns are truncated first, and columns that
+/// still do not fit are left out with a note saying how many.
+fn fitted_table(schema: &SchemaRef, batches: &[RecordBatch], max_width: usize) -> Result<String> {
+    let (headers, rows) = cells(schema, batches)?;
+    let single_line = |text: &str| text.replace(['\n', '\r'], " ");
+    let mut widths: Vec<usize> = headers
+        .iter()
+        .map(|header| header.chars().count())
+        .collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(single_line(cell).chars().count());
+        }
+    }
+
+    // Each column takes its width plus "| " and " ", and the table ends in "|".
+    let total = |widths: &[usize]| widths.iter().map(|w| w + 3).sum::<usize>() + 1;
+    while total(&widths) > max_width {
+        let Some(widest) = (0..widths.len())
+            .filter(|&i| widths[i] > MIN_COLUMN_WIDTH)
+            .max_by_key(|&i| (widths[i], std::cmp::Reverse(i)))
+        else {
+            break;
+        };
+        widths[widest] -= 1;
+    }
+    let mut hidden = 0;
+    while widths.len() > 1 && total(&widths) > max_width {
+        widths.pop();
+        hidden += 1;
+    }
+
+    let fit = |text: &str, width: usize| {
+        let text = single_line(text);
+        let text = if text.chars().count() > width {
+            let mut cut: String = text.chars().take(width - 1).collect();
+            cut.push('…');
+            cut
+        } else {
+            text
+        };
+        let padding = width - text.chars().count();
+        format!("{text}{}", " ".repeat(padding))
+    };
+    let border = widths
+        .iter()
+        .map(|w| "-".repeat(w + 2))
+        .collect::<Vec<_>>()
+        .join("+");
+    let border = format!("+{border}+");
+    let line = |row: &[String]| {
+        let cells = widths
+            .iter()
+            .zip(row)
+            .map(|(w, cell)| fit(cell, *w))
+            .collect::<Vec<_>>();
+        format!("| {} |", cells.join(" | "))
+    };
+
+    let mut out = vec![border.clone(), line(&headers), border.clone()];
+    out.extend(rows.iter().map(|row| line(row)));
+    if !rows.is_empty() {
+        out.push(border);
+    }
+    if hidden > 0 {
+        out.push(format!("({hidden} more columns not shown)"));
+    }
+    Ok(out.join("\n"))
+}
+
+fn markdown_table(schema: &SchemaRef, batches: &[RecordBatch]) -> Result<String> {
+    let (headers, rows) = cells(schema, batches)?;
+    let escape = |text: &str| {
+        text.replace('|', "\\|")
+            .replace("\r\n", "<br>")
+            .replace('\n', "<br>")
+    };
+    let line = |row: &[String]| {
+        let cells = row.iter().map(|cell| escape(cell)).collect::<Vec<_>>();
+        format!("| {} |", cells.join(" | "))
+    };
+    let mut out = vec![
+        line(&headers),
+        format!("|{}", " --- |".repeat(headers.len())),
+    ];
+    out.extend(rows.iter().map(|row| line(row)));
+    Ok(out.join("\n"))
+}
+
+/// One block per row, like `psql`'s expanded display.
+fn vertical(schema: &SchemaRef, batches: &[RecordBatch]) -> Result<String> {
+    let (headers, rows) = cells(schema, batches)?;
+    if rows.is_empty() {
+        return Ok("(0 rows)".to_string());
+    }
+    let name_width = headers
+        .iter()
+        .map(|header| header.chars().count())
+        .max()
+        .unwrap_or(0);
+    let mut out = Vec::new();
+    for (i, row) in rows.iter().enumerate() {
+        let title = format!("-[ RECORD {} ]", i + 1);
+        let rule = (name_width + 3).saturating_sub(title.chars().count());
+        out.push(format!("{title}{}", "-".repeat(rule)));
+        for (header, cell) in headers.iter().zip(row) {
+            let padding = name_width - header.chars().count();
+            out.push(format!("{header}{} | {cell}", " ".repeat(padding)));
+        }
+    }
+    Ok(out.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::products_executor;
+
+    async fn products() -> (SchemaRef, Vec<RecordBatch>) {
+        let executor = products_executor().await;
+        let df = executor
+            .execute_sql("SELECT id, name, category FROM products ORDER BY id LIMIT 2")
+            .await
+            .unwrap();
+        let schema = df.schema().inner().clone();
+        (schema, df.collect().await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_fitted_table() {
+        let (schema, batches) = products().await;
+        // Fits as is: the same table as the default output.
+        assert_eq!(
+            fitted_table(&schema, &batches, 80).unwrap(),
+            pretty_format_batches(&batches).unwrap().to_string()
+        );
+        assert_eq!(
+            fitted_table(&schema, &batches, 24).unwrap(),
+            "+----+--------+--------+\n\
+             | id | name   | categ… |\n\
+             +----+--------+--------+\n\
+             | 1  | Widget | hardw… |\n\
+             | 2  | Gadget | elect… |\n\
+             +----+--------+--------+"
+        );
+        assert_eq!(
+            fitted_table(&schema, &batches, 16).unwrap(),
+            "+----+------+\n\
+             | id | name |\n\
+             +----+------+\n\
+             | 1  | Wid… |\n\
+             | 2  | Gad… |\n\
+             +----+------+\n\
+             (1 more columns not shown)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_markdown_and_vertical() {
+        let (schema, batches) = products().await;
+        assert_eq!(
+            markdown_table(&schema, &batches).unwrap(),
+            "| id | name | category |\n\
+             | --- | --- | --- |\n\
+             | 1 | Widget | hardware |\n\
+             | 2 | Gadget | electronics |"
+        );
+        assert_eq!(
+            vertical(&schema, &batches).unwrap(),
+            "-[ RECORD 1 ]\n\
+             id       | 1\n\
+             name     | Widget\n\
+             category | hardware\n\
+             -[ RECORD 2 ]\n\
+             id       | 2\n\
+             name     | Gadget\n\
+             category | electronics"
+        );
+        assert_eq!(vertical(&schema, &[]).unwrap(), "(0 rows)");
+    }
+}
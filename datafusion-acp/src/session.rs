@@ -0,0 +1,452 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use datafusion::catalog::MemorySchemaProvider;
+use datafusion::common::TableReference;
+use datafusion::dataframe::DataFrameWriteOptions;
+use datafusion::datasource::MemTable;
+use datafusion::prelude::ParquetReadOptions;
+use serde::{Deserialize, Serialize};
+
+use crate::sql_executor::{FileRegistration, SqlExecutor};
+use crate::sqlite::SqliteSchema;
+
+const SESSION_VERSION: u32 = 1;
+
+/// A saved REPL session: everything needed to rebuild the catalog and pick
+/// the conversation back up.
+///
+/// ```json
+/// {
+///   "version": 1,
+///   "files": [{ "name": "sales", "path": "/data/sales.csv" }],
+///   "schemas": ["staging"],
+///   "tables": [{ "schema": "public", "name": "top", "file": "session.tables.1/0-top.parquet" }],
+///   "views": [{ "schema": "public", "name": "eu", "sql": "CREATE VIEW eu AS ..." }],
+///   "history": [{ "question": "total sales per region", "sql": "SELECT ..." }]
+/// }
+/// ```
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SessionFile {
+    version: u32,
+    #[serde(default)]
+    files: Vec<FileRegistration>,
+    /// Schemas created with `CREATE SCHEMA`, restored even when empty.
+    #[serde(default)]
+    schemas: Vec<String>,
+    #[serde(default)]
+    tables: Vec<SavedTable>,
+    #[serde(default)]
+    views: Vec<SavedView>,
+    #[serde(default)]
+    history: Vec<HistoryEntry>,
+}
+
+/// An in-memory table, saved as a Parquet file next to the session file.
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedTable {
+    schema: String,
+    name: String,
+    /// Relative to the session file's directory.
+    file: String,
+}
+
+/// A view or external table, saved as the statement that created it.
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedView {
+    schema: String,
+    name: String,
+    sql: String,
+}
+
+/// A question answered by the agent and the SQL it settled on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub question: String,
+    pub sql: String,
+}
+
+/// The directories holding the Parquet files of a session's tables are
+/// numbered, `session.tables.1/`, `session.tables.2/`, ... for
+/// `session.json`: the prefix they share, and the existing ones.
+fn tables_dirs(path: &Path) -> (String, Vec<(u64, PathBuf)>) {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "session".to_string());
+    let prefix = format!("{stem}.tables.");
+    let parent = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let dirs = std::fs::read_dir(parent)
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let number = name.strip_prefix(&prefix)?.parse().ok()?;
+            Some((number, path.with_file_name(name)))
+        })
+        .collect();
+    (prefix, dirs)
+}
+
+/// Create a tables directory numbered after every existing one.
+fn create_tables_dir(path: &Path) -> Result<PathBuf> {
+    let (prefix, dirs) = tables_dirs(path);
+    let mut number = dirs.iter().map(|(number, _)| number + 1).max().unwrap_or(1);
+    loop {
+        let dir = path.with_file_name(format!("{prefix}{number}"));
+        match std::fs::create_dir(&dir) {
+            Ok(()) => return Ok(dir),
+            // Taken by a concurrent save.
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => number += 1,
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to create '{}'", dir.display()))
+            }
+        }
+    }
+}
+
+/// Save the tables and views of `executor` and the question history to
+/// `path`.
+///
+/// Registered files and SQLite databases are saved as their paths, views
+/// and external tables as their `CREATE` statements, and every other table
+/// as a Parquet file under a new tables directory. The session file is
+/// replaced by renaming a temporary one over it, and only then are the
+/// previous tables directories removed, so until the rename succeeds the
+/// previous session stays readable.
+pub async fn save_session(
+    executor: &SqlExecutor,
+    history: &[HistoryEntry],
+    path: &Path,
+) -> Result<()> {
+    let dir = create_tables_dir(path)?;
+    let dir_name = dir
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let temp = path.with_file_name(format!(".{}-{file_name}", std::process::id()));
+
+    let written = async {
+        let session = snapshot(executor, history, &dir, &dir_name).await?;
+        if session.tables.is_empty() {
+            std::fs::remove_dir(&dir)?;
+        }
+        let json = serde_json::to_string_pretty(&session)?;
+        std::fs::write(&temp, json)?;
+        std::fs::rename(&temp, path)
+            .with_context(|| format!("Failed to write session file '{}'", path.display()))
+    }
+    .await;
+    if let Err(e) = written {
+        let _ = std::fs::remove_dir_all(&dir);
+        let _ = std::fs::remove_file(&temp);
+        return Err(e);
+    }
+
+    for (_, old) in tables_dirs(path).1 {
+        if old != dir {
+            let _ = std::fs::remove_dir_all(old);
+        }
+    }
+    Ok(())
+}
+
+/// The session file for `executor`, with the Parquet files of its tables
+/// written to `dir` and referenced as `dir_name/...`.
+async fn snapshot(
+    executor: &SqlExecutor,
+    history: &[HistoryEntry],
+    dir: &Path,
+    dir_name: &str,
+) -> Result<SessionFile> {
+    let files = executor.registrations();
+    let mut session = SessionFile {
+        version: SESSION_VERSION,
+        history: history.to_vec(),
+        ..Default::default()
+    };
+
+    let options = executor.ctx.state().config().options().catalog.clone();
+    let catalog = executor
+        .ctx
+        .catalog(&options.default_catalog)
+        .ok_or_else(|| anyhow::anyhow!("Default catalog not found"))?;
+    let mut schema_names = catalog.schema_names();
+    schema_names.sort();
+    for schema_name in schema_names {
+        if schema_name == "information_schema" {
+            continue;
+        }
+        let Some(schema) = catalog.schema(&schema_name) else {
+            continue;
+        };
+        // A SQLite schema comes back with its database.
+        if schema.as_any().is::<SqliteSchema>() {
+            continue;
+        }
+        if schema_name != options.default_schema {
+            session.schemas.push(schema_name.clone());
+        }
+
+        let mut table_names = schema.table_names();
+        table_names.sort();
+        for name in table_names {
+            if schema_name == options.default_schema && files.iter().any(|f| f.name == name) {
+                continue;
+            }
+            let Some(table) = schema.table(&name).await? else {
+                continue;
+            };
+            if let Some(sql) = table.get_table_definition() {
+                session.views.push(SavedView {
+                    schema: schema_name.clone(),
+                    name,
+                    sql: sql.to_string(),
+                });
+                continue;
+            }
+
+            let file_name = format!(
+                "{}-{}.parquet",
+                session.tables.len(),
+                name.replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+            );
+            let file = dir.join(&file_name);
+            let reference = TableReference::partial(schema_name.as_str(), name.as_str());
+            executor
+                .ctx
+                .table(reference)
+                .await?
+                .write_parquet(
+                    &file.to_string_lossy(),
+                    DataFrameWriteOptions::new().with_single_file_output(true),
+                    None,
+                )
+                .await
+                .with_context(|| format!("Failed to save table '{schema_name}.{name}'"))?;
+            session.tables.push(SavedTable {
+                schema: schema_name.clone(),
+                name,
+                file: format!("{dir_name}/{file_name}"),
+            });
+        }
+    }
+    session.files = files;
+    Ok(session)
+}
+
+/// Rebuild the tables and views saved in the session file at `path` and
+/// return its question history.
+///
+/// Tables that are already registered, such as those given with `--file`,
+/// take precedence over the session's.
+pub async fn restore_session(executor: &SqlExecutor, path: &Path) -> Result<Vec<HistoryEntry>> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read session file '{}'", path.display()))?;
+    let session: SessionFile = serde_json::from_str(&text)
+        .with_context(|| format!("Invalid session file '{}'", path.display()))?;
+    if session.version != SESSION_VERSION {
+        anyhow::bail!(
+            "Unsupported session file version {} in '{}'",
+            session.version,
+            path.display()
+        );
+    }
+
+    let options = executor.ctx.state().config().options().catalog.clone();
+    let catalog = executor
+        .ctx
+        .catalog(&options.default_catalog)
+        .ok_or_else(|| anyhow::anyhow!("Default catalog not found"))?;
+    let exists = |schema: &str, name: &str| {
+        executor
+            .ctx
+            .table_exist(TableReference::partial(schema, name))
+            .unwrap_or(false)
+    };
+
+    let registered = executor.registrations();
+    for file in &session.files {
+        if exists(&options.default_schema, &file.name) || catalog.schema(&file.name).is_some() {
+            let same = registered
+                .iter()
+                .any(|r| r.name == file.name && r.path == file.path);
+            if !same {
+                eprintln!(
+                    "Keeping the registered '{}' over the session's '{}'.",
+                    file.name, file.path
+                );
+            }
+            continue;
+        }
+        executor
+            .register_with_options(&file.name, &file.path, &file.options)
+            .await
+            .with_context(|| format!("Failed to restore '{}={}'", file.name, file.path))?;
+    }
+
+    for schema in &session.schemas {
+        if catalog.schema(schema).is_none() {
+            catalog.register_schema(schema, Arc::new(MemorySchemaProvider::new()))?;
+        }
+    }
+
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    for table in &session.tables {
+        if exists(&table.schema, &table.name) {
+            continue;
+        }
+        let file = base.join(&table.file);
+        let df = executor
+            .ctx
+            .read_parquet(
+                file.to_string_lossy().as_ref(),
+                ParquetReadOptions::default(),
+            )
+            .await
+            .with_context(|| format!("Failed to read '{}'", file.display()))?;
+        let schema = df.schema().inner().clone();
+        let batches = df.collect().await?;
+        let provider = MemTable::try_new(schema, vec![batches])?;
+        executor.ctx.register_table(
+            TableReference::partial(table.schema.as_str(), table.name.as_str()),
+            Arc::new(provider),
+        )?;
+    }
+
+    // Views may be saved before the views they select from, so replay them
+    // until a round makes no progress.
+    let mut pending: Vec<&SavedView> = session
+        .views
+        .iter()
+        .filter(|view| !exists(&view.schema, &view.name))
+        .collect();
+    while !pending.is_empty() {
+        let mut failed = Vec::new();
+        let mut errors = Vec::new();
+        for view in &pending {
+            match executor.execute_sql(&view.sql).await {
+                Ok(df) => {
+                    df.collect().await?;
+                }
+                Err(e) => {
+                    failed.push(*view);
+                    errors.push(format!("'{}.{}': {e:#}", view.schema, view.name));
+                }
+            }
+        }
+        if failed.len() == pending.len() {
+            anyhow::bail!("Failed to restore views {}", errors.join("; "));
+        }
+        pending = failed;
+    }
+
+    Ok(session.history)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::products_executor;
+    use datafusion::arrow::util::pretty::pretty_format_batches;
+
+    async fn rows(executor: &SqlExecutor, sql: &str) -> String {
+        let batches = executor.execute_sql(sql).await.unwrap().collect().await;
+        pretty_format_batches(&batches.unwrap())
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_save_and_restore_session() {
+        let dir =
+            std::env::temp_dir().join(format!("datafusion_acp_session_{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("session.json");
+
+        let executor = products_executor().await;
+        for sql in [
+            "CREATE TABLE cheap AS SELECT id, name FROM products WHERE price < 20",
+            "CREATE SCHEMA staging",
+            "CREATE TABLE staging.empty (id BIGINT)",
+            "CREATE VIEW cheap_ids AS SELECT id, name FROM cheap",
+            // Saved before the view it selects from, so restoring retries it.
+            "CREATE VIEW all_names AS SELECT name FROM cheap_ids",
+        ] {
+            executor
+                .execute_sql(sql)
+                .await
+                .unwrap()
+                .collect()
+                .await
+                .unwrap();
+        }
+        let history = vec![HistoryEntry {
+            question: "cheap products".to_string(),
+            sql: "SELECT * FROM cheap".to_string(),
+        }];
+        save_session(&executor, &history, &path).await.unwrap();
+
+        let restored = SqlExecutor::new().await.unwrap();
+        assert_eq!(restore_session(&restored, &path).await.unwrap(), history);
+        for sql in [
+            "SELECT * FROM products ORDER BY id",
+            "SELECT * FROM cheap ORDER BY id",
+            "SELECT * FROM all_names ORDER BY name",
+            "SELECT count(*) FROM staging.empty",
+        ] {
+            assert_eq!(
+                rows(&restored, sql).await,
+                rows(&executor, sql).await,
+                "{sql}"
+            );
+        }
+        assert_eq!(restored.registrations().len(), 1);
+
+        // Saving again writes a new tables directory and removes the old one.
+        save_session(&restored, &history, &path).await.unwrap();
+        let tables = dir.join("session.tables.2");
+        assert_eq!(std::fs::read_dir(&tables).unwrap().count(), 2);
+        assert!(!dir.join("session.tables.1").exists());
+
+        // A table that cannot be read fails the save and keeps the last one.
+        let csv = dir.join("broken.csv");
+        std::fs::write(&csv, "id\n1\n").unwrap();
+        restored
+            .ctx
+            .register_csv(
+                TableReference::partial("staging", "broken"),
+                csv.to_str().unwrap(),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        std::fs::write(&csv, "id\nnot a number\n").unwrap();
+        assert!(save_session(&restored, &[], &path).await.is_err());
+        std::fs::remove_file(&csv).unwrap();
+        let again = SqlExecutor::new().await.unwrap();
+        assert_eq!(restore_session(&again, &path).await.unwrap(), history);
+        assert_eq!(
+            rows(&again, "SELECT * FROM cheap ORDER BY id").await,
+            rows(&executor, "SELECT * FROM cheap ORDER BY id").await
+        );
+        let mut entries: Vec<String> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        entries.sort();
+        assert_eq!(entries, ["session.json", "session.tables.2"]);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
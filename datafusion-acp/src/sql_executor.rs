@@ -1,18 +1,240 @@
+use std::collections::BTreeMap;
 use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use anyhow::{Context, Result};
+use datafusion::arrow::array::UInt64Array;
+use datafusion::arrow::datatypes::{DataType, Schema, SchemaRef};
 use datafusion::arrow::json::ArrayWriter;
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::common::tree_node::TreeNodeRecursion;
+use datafusion::dataframe::DataFrameWriteOptions;
+use datafusion::datasource::file_format::options::{ArrowReadOptions, ReadOptions};
+use datafusion::datasource::listing::ListingTableUrl;
+use datafusion::error::DataFusionError;
+use datafusion::execution::context::SQLOptions;
+use datafusion::execution::runtime_env::RuntimeEnvBuilder;
+use datafusion::logical_expr::LogicalPlan;
+use datafusion::physical_plan::collect;
+use datafusion::physical_plan::display::DisplayableExecutionPlan;
 use datafusion::prelude::*;
+use datafusion::sql::parser::{DFParser, Statement};
+use datafusion::sql::sqlparser::ast;
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+
+use crate::sqlite::SqliteSchema;
+
+/// File formats that can be registered as tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataFormat {
+    Csv,
+    Parquet,
+    Json,
+    Arrow,
+    #[cfg(feature = "avro")]
+    Avro,
+    /// A SQLite database, registered as a schema of its tables.
+    Sqlite,
+}
+
+impl DataFormat {
+    fn from_extension(ext: &str) -> Result<Self> {
+        match ext.to_lowercase().as_str() {
+            "csv" => Ok(Self::Csv),
+            "parquet" | "pq" => Ok(Self::Parquet),
+            "json" | "ndjson" => Ok(Self::Json),
+            "arrow" | "feather" | "ipc" => Ok(Self::Arrow),
+            #[cfg(feature = "avro")]
+            "avro" => Ok(Self::Avro),
+            "sqlite" | "sqlite3" | "db" => Ok(Self::Sqlite),
+            #[cfg(not(feature = "avro"))]
+            "avro" => {
+                anyhow::bail!(
+                    "Avro support is not enabled in this build. Rebuild with `--features avro`."
+                )
+            }
+            other => anyhow::bail!("Unsupported file format '.{other}'. Supported: .csv, .parquet, .pq, .json, .ndjson, .arrow, .feather, .ipc, .avro, .sqlite, .sqlite3, .db"),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Csv => "CSV",
+            Self::Parquet => "Parquet",
+            Self::Json => "JSON",
+            Self::Arrow => "Arrow IPC",
+            #[cfg(feature = "avro")]
+            Self::Avro => "Avro",
+            Self::Sqlite => "SQLite",
+        }
+    }
+}
+
+/// Options for registering a file, directory or glob as a table.
+///
+/// Deserialisable so the same options can be declared per table in a
+/// `--catalog` file, and serialisable so sessions can record them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RegisterOptions {
+    /// Force a format instead of detecting it from the file extension.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<DataFormat>,
+    /// CSV field delimiter.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delimiter: Option<char>,
+    /// Whether CSV files start with a header row (default: true).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_header: Option<bool>,
+    /// Column type overrides, keyed by column name, as Arrow type names
+    /// such as `Int64`, `Float64`, `Utf8` or `Date32`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub schema: BTreeMap<String, String>,
+    /// Allow `INSERT` into the tables of a SQLite database, which are
+    /// read-only otherwise.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub writable: bool,
+}
+
+/// A file, directory, glob or SQLite database registered under `name`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileRegistration {
+    pub name: String,
+    /// The path as registered, made absolute.
+    pub path: String,
+    #[serde(default)]
+    pub options: RegisterOptions,
+}
+
+impl RegisterOptions {
+    fn is_default(&self) -> bool {
+        self.format.is_none()
+            && self.delimiter.is_none()
+            && self.has_header.is_none()
+            && self.schema.is_empty()
+            && !self.writable
+    }
+}
+
+/// File formats query results can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Parquet,
+    Csv,
+    NdJson,
+}
+
+impl ExportFormat {
+    /// Infer the export format from the extension of `path`.
+    pub fn from_path(path: &str) -> Result<Self> {
+        let ext = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+        match ext.as_deref() {
+            Some("parquet") | Some("pq") => Ok(Self::Parquet),
+            Some("csv") => Ok(Self::Csv),
+            Some("json") | Some("ndjson") | Some("jsonl") => Ok(Self::NdJson),
+            Some(other) => anyhow::bail!(
+                "Unsupported output format '.{other}'. Supported: .parquet, .pq, .csv, .json, .ndjson, .jsonl"
+            ),
+            None => anyhow::bail!("Output file '{path}' has no extension. Cannot determine format."),
+        }
+    }
+}
+
+/// Guards on the queries an agent runs while exploring, so one `SELECT *`
+/// cannot exhaust memory or the agent's context.
+#[derive(Debug, Clone)]
+pub struct ResourceLimits {
+    /// Rows returned by one `run_sql` call; the rest are cut off.
+    pub max_rows: usize,
+    /// Size of one `run_sql` result as JSON.
+    pub max_result_bytes: usize,
+    /// Time one exploration query may run.
+    pub query_timeout: Duration,
+    /// Memory pool size for the whole session; unbounded when `None`.
+    pub memory_limit: Option<usize>,
+}
+
+impl Default for ResourceLimits {
+    fn default() -> Self {
+        Self {
+            max_rows: 1000,
+            max_result_bytes: 256 * 1024,
+            query_timeout: Duration::from_secs(60),
+            memory_limit: None,
+        }
+    }
+}
+
+/// An exploration query that hit one of the [`ResourceLimits`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LimitExceeded {
+    /// Which limit: `max_result_bytes`, `query_timeout` or `memory_limit`.
+    pub limit: &'static str,
+    message: String,
+}
+
+impl std::fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+/// The rows of an exploration query, cut off at `max_rows`.
+#[derive(Debug)]
+pub struct ExplorationResult {
+    /// The rows as a JSON array.
+    pub json: String,
+    pub rows: usize,
+    /// Whether more rows were left out.
+    pub truncated: bool,
+}
+
+/// The plans of a query, from [`SqlExecutor::explain_sql`].
+#[derive(Debug)]
+pub struct PlanExplanation {
+    /// The optimized logical plan.
+    pub logical: String,
+    /// The physical plan, with per-operator metrics when it was run.
+    pub physical: String,
+    /// The physical plan as a Graphviz DOT graph.
+    pub dot: String,
+    /// Rows produced, when the plan was run.
+    pub rows: Option<usize>,
+}
 
 pub struct SqlExecutor {
     pub ctx: SessionContext,
+    limits: ResourceLimits,
+    registrations: Mutex<Vec<FileRegistration>>,
 }
 
 impl SqlExecutor {
+    /// An executor with the default [`ResourceLimits`].
+    #[cfg(test)]
     pub async fn new() -> Result<Self> {
+        Self::with_limits(ResourceLimits::default()).await
+    }
+
+    pub async fn with_limits(limits: ResourceLimits) -> Result<Self> {
         let config = SessionConfig::new().with_information_schema(true);
-        let ctx = SessionContext::new_with_config(config);
-        Ok(Self { ctx })
+        let mut runtime = RuntimeEnvBuilder::new();
+        if let Some(bytes) = limits.memory_limit {
+            runtime = runtime.with_memory_limit(bytes, 1.0);
+        }
+        let ctx = SessionContext::new_with_config_rt(config, runtime.build_arc()?);
+        Ok(Self {
+            ctx,
+            limits,
+            registrations: Mutex::new(Vec::new()),
+        })
     }
 
     pub async fn register_csv(&self, table: &str, path: &str) -> Result<()> {
@@ -36,28 +258,220 @@ impl SqlExecutor {
             .with_context(|| format!("Failed to register JSON file '{path}' as table '{table}'"))
     }
 
+    pub async fn register_arrow(&self, table: &str, path: &str) -> Result<()> {
+        // `.feather` and `.ipc` are the same format, so match on the real extension.
+        let extension = file_extension_filter(path);
+        let options = ArrowReadOptions {
+            file_extension: &extension,
+            ..Default::default()
+        };
+        self.ctx
+            .register_arrow(table, path, options)
+            .await
+            .with_context(|| {
+                format!("Failed to register Arrow IPC file '{path}' as table '{table}'")
+            })
+    }
+
+    #[cfg(feature = "avro")]
+    pub async fn register_avro(&self, table: &str, path: &str) -> Result<()> {
+        self.ctx
+            .register_avro(table, path, AvroReadOptions::default())
+            .await
+            .with_context(|| format!("Failed to register Avro file '{path}' as table '{table}'"))
+    }
+
+    /// Register every table and view of a SQLite database as `schema.table`.
+    pub fn register_sqlite(&self, schema: &str, path: &str, writable: bool) -> Result<()> {
+        let default_catalog = self
+            .ctx
+            .state()
+            .config_options()
+            .catalog
+            .default_catalog
+            .clone();
+        let catalog = self
+            .ctx
+            .catalog(&default_catalog)
+            .context("The default catalog is missing")?;
+        if catalog.schema(schema).is_some() {
+            anyhow::bail!(
+                "Schema '{schema}' already exists. Register '{path}' under another name."
+            );
+        }
+        catalog
+            .register_schema(schema, Arc::new(SqliteSchema::open(path, writable)?))
+            .with_context(|| {
+                format!("Failed to register SQLite database '{path}' as '{schema}'")
+            })?;
+        Ok(())
+    }
+
     pub async fn register_file(&self, table: &str, path: &str) -> Result<()> {
-        if !Path::new(path).exists() {
+        self.register_with_options(table, path, &RegisterOptions::default())
+            .await
+    }
+
+    /// Register a single file, a directory or a glob pattern as one table.
+    ///
+    /// Directories are scanned recursively and Hive-style `key=value` path
+    /// segments become partition columns.
+    pub async fn register_with_options(
+        &self,
+        table: &str,
+        path: &str,
+        options: &RegisterOptions,
+    ) -> Result<()> {
+        self.register_source(table, path, options).await?;
+        let path = std::path::absolute(path)
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| path.to_string());
+        let mut registrations = self
+            .registrations
+            .lock()
+            .map_err(|_| anyhow::anyhow!("mutex poisoned"))?;
+        registrations.retain(|r| r.name != table);
+        registrations.push(FileRegistration {
+            name: table.to_string(),
+            path,
+            options: options.clone(),
+        });
+        Ok(())
+    }
+
+    /// The files registered through [`Self::register_with_options`], in
+    /// order. A name registered again keeps only its latest registration.
+    pub fn registrations(&self) -> Vec<FileRegistration> {
+        self.registrations
+            .lock()
+            .map(|r| r.clone())
+            .unwrap_or_default()
+    }
+
+    async fn register_source(
+        &self,
+        table: &str,
+        path: &str,
+        options: &RegisterOptions,
+    ) -> Result<()> {
+        let existing = match glob_prefix(path) {
+            Some("") => ".",
+            Some(prefix) => prefix,
+            None => path,
+        };
+        if !Path::new(existing).exists() {
             anyhow::bail!("File not found: '{path}'");
         }
 
-        let ext = Path::new(path)
-            .extension()
-            .and_then(|e| e.to_str())
-            .map(|e| e.to_lowercase());
+        let extension = detect_extension(path)?;
+        let format = match (options.format, extension.as_deref()) {
+            (Some(format), _) => format,
+            (None, Some(ext)) => DataFormat::from_extension(ext)?,
+            (None, None) => {
+                anyhow::bail!("File '{path}' has no extension. Cannot determine format.")
+            }
+        };
 
-        match ext.as_deref() {
-            Some("csv") => self.register_csv(table, path).await,
-            Some("parquet") | Some("pq") => self.register_parquet(table, path).await,
-            Some("json") | Some("ndjson") => self.register_json(table, path).await,
-            Some("avro") => {
-                anyhow::bail!(
-                    "Avro registration is not available in the current offline build environment"
-                )
+        let plain_file = glob_prefix(path).is_none() && !Path::new(path).is_dir();
+        if format == DataFormat::Sqlite {
+            if !plain_file {
+                anyhow::bail!("SQLite databases must be registered one file at a time: '{path}'");
             }
-            Some(other) => anyhow::bail!("Unsupported file format '.{other}'. Supported: .csv, .parquet, .pq, .json, .ndjson, .avro"),
-            None => anyhow::bail!("File '{path}' has no extension. Cannot determine format."),
+            return self.register_sqlite(table, path, options.writable);
+        }
+        if options.writable {
+            anyhow::bail!("Only SQLite databases can be registered as writable: '{path}'");
+        }
+        if plain_file && options.is_default() {
+            return match format {
+                DataFormat::Csv => self.register_csv(table, path).await,
+                DataFormat::Parquet => self.register_parquet(table, path).await,
+                DataFormat::Json => self.register_json(table, path).await,
+                DataFormat::Arrow => self.register_arrow(table, path).await,
+                #[cfg(feature = "avro")]
+                DataFormat::Avro => self.register_avro(table, path).await,
+                DataFormat::Sqlite => unreachable!("SQLite databases are registered as schemas"),
+            };
+        }
+
+        let config = self.ctx.copied_config();
+        let table_options = self.ctx.copied_table_options();
+        let listing = match format {
+            DataFormat::Csv => {
+                let mut read = CsvReadOptions::new();
+                if let Some(delimiter) = options.delimiter {
+                    if !delimiter.is_ascii() {
+                        anyhow::bail!(
+                            "CSV delimiter must be a single ASCII character, got '{delimiter}'"
+                        );
+                    }
+                    read = read.delimiter(delimiter as u8);
+                }
+                if let Some(has_header) = options.has_header {
+                    read = read.has_header(has_header);
+                }
+                read.to_listing_options(&config, table_options)
+            }
+            DataFormat::Parquet => {
+                ParquetReadOptions::default().to_listing_options(&config, table_options)
+            }
+            DataFormat::Json => {
+                NdJsonReadOptions::default().to_listing_options(&config, table_options)
+            }
+            DataFormat::Arrow => {
+                ArrowReadOptions::default().to_listing_options(&config, table_options)
+            }
+            #[cfg(feature = "avro")]
+            DataFormat::Avro => {
+                AvroReadOptions::default().to_listing_options(&config, table_options)
+            }
+            DataFormat::Sqlite => unreachable!("SQLite databases are registered as schemas"),
+        };
+        let listing =
+            listing.with_file_extension(extension.map(|ext| format!(".{ext}")).unwrap_or_default());
+
+        let listing_path = strip_hive_glob_segments(path)?;
+        let state = self.ctx.state();
+        let url = ListingTableUrl::parse(&listing_path)
+            .with_context(|| format!("Invalid table path '{path}'"))?;
+
+        let partition_cols = listing
+            .infer_partitions(&state, &url)
+            .await
+            .with_context(|| format!("Failed to infer partitions under '{path}'"))?
+            .into_iter()
+            .map(|name| {
+                let data_type = match options.schema.get(&name) {
+                    Some(type_name) => parse_data_type(type_name)?,
+                    None => DataType::Utf8,
+                };
+                Ok((name, data_type))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let partition_names = partition_cols
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        let listing = listing.with_table_partition_cols(partition_cols);
+
+        let inferred = listing
+            .infer_schema(&state, &url)
+            .await
+            .with_context(|| format!("Failed to infer schema for '{path}'"))?;
+        if inferred.fields().is_empty() {
+            anyhow::bail!("No data files matched '{path}'");
         }
+        let schema = apply_schema_overrides(&inferred, &options.schema, &partition_names)?;
+
+        self.ctx
+            .register_listing_table(table, &listing_path, listing, Some(schema), None)
+            .await
+            .with_context(|| {
+                format!(
+                    "Failed to register {} data '{path}' as table '{table}'",
+                    format.name()
+                )
+            })
     }
 
     pub async fn execute_sql(&self, sql: &str) -> Result<DataFrame> {
@@ -67,128 +481,689 @@ impl SqlExecutor {
             .with_context(|| format!("Failed to execute SQL: {sql}"))
     }
 
+    /// Fail with a [`SafeModeViolation`] unless `sql` is a single read-only
+    /// query.
+    ///
+    /// The parsed statement is checked first, so mutations of tables that do
+    /// not exist are still reported as such; the planned `LogicalPlan` is then
+    /// verified with [`read_only_sql_options`].
+    pub async fn ensure_read_only(&self, sql: &str) -> Result<()> {
+        let mut statements =
+            DFParser::parse_sql(sql).with_context(|| format!("Failed to parse SQL: {sql}"))?;
+        if statements.len() > 1 {
+            return Err(violation("Multiple statements"));
+        }
+        let Some(statement) = statements.pop_front() else {
+            return Ok(());
+        };
+
+        if let Some(operation) = statement_operation(&statement) {
+            return Err(violation(operation));
+        }
+
+        let plan = self
+            .ctx
+            .state()
+            .statement_to_plan(statement)
+            .await
+            .with_context(|| format!("Failed to plan SQL: {sql}"))?;
+        if let Err(e) = read_only_sql_options().verify_plan(&plan) {
+            return Err(violation(
+                plan_operation(&plan).unwrap_or_else(|| e.to_string()),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Check that `sql` would run without running its side effects.
+    ///
+    /// The statement is always planned, and checked with
+    /// [`Self::ensure_read_only`] in safe mode. With `run_limit`, read-only
+    /// queries are also executed with that `LIMIT` to catch runtime errors
+    /// such as failed casts.
+    pub async fn validate_sql(
+        &self,
+        sql: &str,
+        safe_mode: bool,
+        run_limit: Option<usize>,
+    ) -> Result<()> {
+        if safe_mode {
+            self.ensure_read_only(sql).await?;
+        }
+
+        let state = self.ctx.state();
+        let plan = state
+            .create_logical_plan(sql)
+            .await
+            .with_context(|| format!("Failed to plan SQL: {sql}"))?;
+
+        if let Some(limit) = run_limit {
+            if read_only_sql_options().verify_plan(&plan).is_ok() {
+                DataFrame::new(state, plan)
+                    .limit(0, Some(limit))?
+                    .collect()
+                    .await
+                    .with_context(|| format!("Failed to execute SQL: {sql}"))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Plan `sql` without side effects and, when `analyze` is set, run it to
+    /// collect per-operator metrics, as `EXPLAIN [ANALYZE]` would.
+    pub async fn explain_sql(&self, sql: &str, analyze: bool) -> Result<PlanExplanation> {
+        let state = self.ctx.state();
+        let logical = state
+            .create_logical_plan(sql)
+            .await
+            .with_context(|| format!("Failed to plan SQL: {sql}"))?;
+        if matches!(logical, LogicalPlan::Ddl(_) | LogicalPlan::Statement(_)) {
+            anyhow::bail!("Only queries and DML statements can be explained: {sql}");
+        }
+        let optimized = state.optimize(&logical)?;
+        let physical = state.create_physical_plan(&logical).await?;
+
+        let mut rows = None;
+        if analyze {
+            let batches = collect(physical.clone(), self.ctx.task_ctx())
+                .await
+                .with_context(|| format!("Failed to execute SQL: {sql}"))?;
+            rows = Some(batches.iter().map(RecordBatch::num_rows).sum());
+        }
+        let displayable = if analyze {
+            DisplayableExecutionPlan::with_metrics(physical.as_ref())
+        } else {
+            DisplayableExecutionPlan::new(physical.as_ref())
+        };
+        let physical_text = displayable.indent(true).to_string();
+        let dot = displayable.graphviz().to_string();
+        let logical_text = optimized.display_indent().to_string();
+        Ok(PlanExplanation {
+            logical: logical_text,
+            physical: physical_text,
+            dot,
+            rows,
+        })
+    }
+
+    /// Run `sql` and stream its result into a single file at `path`, without
+    /// collecting it in memory. Returns the number of rows written.
+    pub async fn write_sql_to_file(&self, sql: &str, path: &str) -> Result<u64> {
+        ExportFormat::from_path(path)?;
+        let df = self.execute_sql(sql).await?;
+        self.write_dataframe_to_file(df, path).await
+    }
+
+    /// Stream the result of `df` into a single file at `path`, replacing
+    /// any file already there. Returns the number of rows written.
+    ///
+    /// The result is written to a hidden file next to `path` and renamed
+    /// over it once the query succeeds, so a failed query leaves the
+    /// previous file in place.
+    pub async fn write_dataframe_to_file(&self, df: DataFrame, path: &str) -> Result<u64> {
+        let format = ExportFormat::from_path(path)?;
+        let target = Path::new(path);
+        let file_name = target
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow::anyhow!("Output path '{path}' has no file name"))?;
+        // Keeps the extension last, which DataFusion's writers look at.
+        let partial = target.with_file_name(format!(".{}-{file_name}", std::process::id()));
+        let partial_path = partial.to_string_lossy().into_owned();
+
+        let options = DataFrameWriteOptions::new().with_single_file_output(true);
+        let written = match format {
+            ExportFormat::Parquet => df.write_parquet(&partial_path, options, None).await,
+            ExportFormat::Csv => df.write_csv(&partial_path, options, None).await,
+            ExportFormat::NdJson => df.write_json(&partial_path, options, None).await,
+        }
+        .with_context(|| format!("Failed to write results to '{path}'"))
+        .and_then(|counts| {
+            std::fs::rename(&partial, target)
+                .with_context(|| format!("Failed to replace '{path}'"))?;
+            Ok(counts)
+        });
+        let counts = match written {
+            Ok(counts) => counts,
+            Err(e) => {
+                std::fs::remove_file(&partial).ok();
+                return Err(e);
+            }
+        };
+
+        let rows = counts
+            .first()
+            .and_then(|batch| batch.column(0).as_any().downcast_ref::<UInt64Array>())
+            .map(|count| count.value(0))
+            .unwrap_or(0);
+        Ok(rows)
+    }
+
+    /// Run a query for the agent within the [`ResourceLimits`]: at most
+    /// `max_rows` rows are collected, and exceeding the timeout, the result
+    /// size or the memory pool fails with [`LimitExceeded`].
+    pub async fn run_exploration_sql(&self, sql: &str) -> Result<ExplorationResult> {
+        let max_rows = self.limits.max_rows;
+        let collect = async {
+            let mut stream = self.execute_sql(sql).await?.execute_stream().await?;
+            let mut batches = Vec::new();
+            let mut rows = 0;
+            let mut truncated = false;
+            while let Some(batch) = stream.next().await {
+                let batch = batch?;
+                let room = max_rows - rows;
+                if batch.num_rows() > room {
+                    // Dropping the stream stops the rest of the query.
+                    batches.push(batch.slice(0, room));
+                    rows += room;
+                    truncated = true;
+                    break;
+                }
+                rows += batch.num_rows();
+                batches.push(batch);
+            }
+            Ok::<_, anyhow::Error>((batches, rows, truncated))
+        };
+
+        let (batches, rows, truncated) = self
+            .within_timeout(collect)
+            .await?
+            .map_err(|e| self.memory_error(e))?;
+
+        let json = batches_to_json(&batches)?;
+        if json.len() > self.limits.max_result_bytes {
+            return Err(LimitExceeded {
+                limit: "max_result_bytes",
+                message: format!(
+                    "The result is {} bytes of JSON ({rows} rows), over the limit of {} bytes \
+                     per call. Select fewer columns or rows, or aggregate.",
+                    json.len(),
+                    self.limits.max_result_bytes
+                ),
+            }
+            .into());
+        }
+        Ok(ExplorationResult {
+            json,
+            rows,
+            truncated,
+        })
+    }
+
+    async fn within_timeout<T>(&self, fut: impl std::future::Future<Output = T>) -> Result<T> {
+        let timeout = self.limits.query_timeout;
+        tokio::time::timeout(timeout, fut).await.map_err(|_| {
+            LimitExceeded {
+                limit: "query_timeout",
+                message: format!(
+                    "The query was stopped after the {}s timeout. Filter earlier, \
+                     aggregate or add a LIMIT.",
+                    timeout.as_secs_f64()
+                ),
+            }
+            .into()
+        })
+    }
+
+    /// Turn a memory pool failure into a [`LimitExceeded`]; other errors
+    /// pass through.
+    fn memory_error(&self, e: anyhow::Error) -> anyhow::Error {
+        let exhausted = e.chain().any(|cause| {
+            matches!(
+                cause
+                    .downcast_ref::<DataFusionError>()
+                    .map(|e| e.find_root()),
+                Some(DataFusionError::ResourcesExhausted(_))
+            )
+        });
+        match (exhausted, self.limits.memory_limit) {
+            (true, Some(bytes)) => LimitExceeded {
+                limit: "memory_limit",
+                message: format!(
+                    "The query needs more than the {bytes} byte memory limit. Filter earlier, \
+                     aggregate or add a LIMIT. ({e:#})"
+                ),
+            }
+            .into(),
+            _ => e,
+        }
+    }
+
     pub async fn execute_sql_json(&self, sql: &str) -> Result<String> {
         let df = self.execute_sql(sql).await?;
         let batches = df.collect().await.context("Failed to collect results")?;
-        let refs = batches.iter().collect::<Vec<_>>();
-        let mut writer = ArrayWriter::new(Vec::new());
-        writer.write_batches(&refs)?;
-        writer.finish()?;
-        let out = writer.into_inner();
-        let json = String::from_utf8(out).context("JSON output was not valid UTF-8")?;
-        Ok(json)
+        batches_to_json(&batches)
     }
-}
 
-pub fn is_mutation_sql(sql: &str) -> bool {
-    let trimmed = sql.trim();
+    /// List every user table as JSON rows, leaving out `information_schema`.
+    pub async fn list_tables_json(&self) -> Result<String> {
+        self.execute_sql_json(
+            "SELECT table_catalog, table_schema, table_name, table_type \
+             FROM information_schema.tables \
+             WHERE table_schema <> 'information_schema' \
+             ORDER BY table_catalog, table_schema, table_name",
+        )
+        .await
+    }
 
-    // Skip leading comments
-    let effective = skip_comments(trimmed);
+    /// Describe the columns of `table` (optionally `schema.table`) as JSON rows.
+    pub async fn describe_table_json(&self, table: &str) -> Result<String> {
+        let provider = self
+            .ctx
+            .table_provider(table)
+            .await
+            .with_context(|| format!("Table '{table}' not found"))?;
+        let columns = provider
+            .schema()
+            .fields()
+            .iter()
+            .map(|field| {
+                serde_json::json!({
+                    "column_name": field.name(),
+                    "data_type": field.data_type().to_string(),
+                    "is_nullable": field.is_nullable(),
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(serde_json::Value::Array(columns).to_string())
+    }
 
-    // EXPLAIN should not be flagged
-    if effective.starts_with("EXPLAIN ") || effective.starts_with("EXPLAIN\n") {
-        return false;
+    /// Names of the user tables, qualified as `schema.table` outside the
+    /// default schema.
+    pub async fn table_names(&self) -> Result<Vec<String>> {
+        let default_schema = self
+            .ctx
+            .state()
+            .config_options()
+            .catalog
+            .default_schema
+            .clone();
+        let rows: Vec<serde_json::Value> = serde_json::from_str(&self.list_tables_json().await?)?;
+        Ok(rows
+            .iter()
+            .filter_map(|row| {
+                let schema = row["table_schema"].as_str()?;
+                let table = row["table_name"].as_str()?;
+                Some(if schema == default_schema {
+                    table.to_string()
+                } else {
+                    format!("{schema}.{table}")
+                })
+            })
+            .collect())
     }
 
-    // Check for WITH ... <mutation> pattern
-    if effective.starts_with("WITH ") || effective.starts_with("WITH\n") {
-        let upper = effective.to_uppercase();
-        for kw in MUTATION_KEYWORDS {
-            if upper.contains(kw) && !is_keyword_in_identifier_context(&upper, kw) {
-                return true;
+    /// Everything an agent usually looks up before writing a query against
+    /// `table`, as one JSON object: its columns, row count, per-column
+    /// statistics from `DataFrame::describe` and the first `sample_rows`
+    /// rows.
+    pub async fn table_profile_json(&self, table: &str, sample_rows: usize) -> Result<String> {
+        let columns: serde_json::Value =
+            serde_json::from_str(&self.describe_table_json(table).await?)?;
+        let df = self
+            .ctx
+            .table(table)
+            .await
+            .with_context(|| format!("Table '{table}' not found"))?;
+
+        let profile = async {
+            let row_count = df.clone().count().await?;
+            let sample = df.clone().limit(0, Some(sample_rows))?.collect().await?;
+            let described = df.describe().await?.collect().await?;
+            Ok::<_, DataFusionError>((row_count, sample, described))
+        };
+        let (row_count, sample, described) = self
+            .within_timeout(profile)
+            .await?
+            .map_err(|e| self.memory_error(e.into()))?;
+
+        // `describe` has one row per statistic; turn it into one object of
+        // statistics per column.
+        let mut statistics = serde_json::Map::new();
+        let rows: Vec<serde_json::Map<String, serde_json::Value>> =
+            serde_json::from_str(&batches_to_json(&described)?)?;
+        for mut row in rows {
+            let Some(serde_json::Value::String(statistic)) = row.remove("describe") else {
+                continue;
+            };
+            for (column, value) in row {
+                statistics
+                    .entry(column)
+                    .or_insert_with(|| serde_json::json!({}))[&statistic] = value;
             }
         }
-        return false;
+
+        let sample: serde_json::Value = serde_json::from_str(&batches_to_json(&sample)?)?;
+        Ok(serde_json::json!({
+            "table": table,
+            "columns": columns,
+            "row_count": row_count,
+            "statistics": statistics,
+            "sample": sample,
+        })
+        .to_string())
+    }
+}
+
+/// Serialise record batches as a single JSON array of row objects.
+pub fn batches_to_json(batches: &[RecordBatch]) -> Result<String> {
+    let refs = batches.iter().collect::<Vec<_>>();
+    let mut writer = ArrayWriter::new(Vec::new());
+    writer.write_batches(&refs)?;
+    writer.finish()?;
+    let out = writer.into_inner();
+    let json = String::from_utf8(out).context("JSON output was not valid UTF-8")?;
+    Ok(json)
+}
+
+/// A statement rejected because safe mode only allows read-only queries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SafeModeViolation {
+    /// The rejected operation, e.g. `INSERT` or `CREATE TABLE`.
+    pub operation: String,
+}
+
+impl std::fmt::Display for SafeModeViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Safe mode is enabled. {} is not allowed; only read-only queries can run.",
+            self.operation
+        )
     }
+}
 
-    // Check first keyword
-    let first_word = effective
-        .split(|c: char| c.is_whitespace() || c == '(')
-        .next()
-        .unwrap_or("")
-        .to_uppercase();
+impl std::error::Error for SafeModeViolation {}
 
-    MUTATION_KEYWORDS.contains(&first_word.as_str())
+fn violation(operation: impl Into<String>) -> anyhow::Error {
+    SafeModeViolation {
+        operation: operation.into(),
+    }
+    .into()
 }
 
-const MUTATION_KEYWORDS: &[&str] = &[
-    "INSERT", "UPDATE", "DELETE", "DROP", "ALTER", "CREATE", "TRUNCATE", "COPY", "IMPORT",
-    "EXPORT", "ATTACH", "DETACH",
-];
+/// `SQLOptions` that only admit read-only queries.
+pub fn read_only_sql_options() -> SQLOptions {
+    SQLOptions::new()
+        .with_allow_ddl(false)
+        .with_allow_dml(false)
+        .with_allow_statements(false)
+}
 
-fn skip_comments(s: &str) -> &str {
-    let mut rest = s;
-    loop {
-        rest = rest.trim_start();
-        if rest.starts_with("--") {
-            if let Some(pos) = rest.find('\n') {
-                rest = &rest[pos + 1..];
-            } else {
-                return "";
-            }
-        } else if rest.starts_with("/*") {
-            if let Some(pos) = rest.find("*/") {
-                rest = &rest[pos + 2..];
-            } else {
-                return "";
-            }
-        } else {
-            return rest;
+/// The mutating operation performed by a parsed statement, if any.
+fn statement_operation(stmt: &Statement) -> Option<String> {
+    match stmt {
+        Statement::Statement(stmt) => sql_statement_operation(stmt),
+        Statement::Explain(explain) => statement_operation(&explain.statement),
+        Statement::CreateExternalTable(_) => Some("CREATE EXTERNAL TABLE".to_string()),
+        Statement::CopyTo(_) => Some("COPY".to_string()),
+        Statement::Reset(_) => Some("RESET".to_string()),
+    }
+}
+
+fn sql_statement_operation(stmt: &ast::Statement) -> Option<String> {
+    use ast::Statement as S;
+    match stmt {
+        S::Query(query) => query_operation(query),
+        S::Explain { statement, .. } => sql_statement_operation(statement),
+        S::ExplainTable { .. }
+        | S::ShowTables { .. }
+        | S::ShowColumns { .. }
+        | S::ShowViews { .. }
+        | S::ShowSchemas { .. }
+        | S::ShowDatabases { .. }
+        | S::ShowFunctions { .. }
+        | S::ShowVariable { .. }
+        | S::ShowVariables { .. }
+        | S::ShowCreate { .. } => None,
+        other => Some(leading_keywords(&other.to_string())),
+    }
+}
+
+fn query_operation(query: &ast::Query) -> Option<String> {
+    let ctes = query.with.iter().flat_map(|with| &with.cte_tables);
+    for cte in ctes {
+        if let Some(op) = query_operation(&cte.query) {
+            return Some(op);
         }
     }
+    set_expr_operation(&query.body)
 }
 
-fn is_keyword_in_identifier_context(upper: &str, keyword: &str) -> bool {
-    // Check if keyword appears only as part of an identifier (e.g., "insert_log")
-    for (idx, _) in upper.match_indices(keyword) {
-        let before = if idx > 0 {
-            upper.as_bytes()[idx - 1]
-        } else {
-            b' '
+fn set_expr_operation(body: &ast::SetExpr) -> Option<String> {
+    use ast::SetExpr as E;
+    match body {
+        E::Query(query) => query_operation(query),
+        E::SetOperation { left, right, .. } => {
+            set_expr_operation(left).or_else(|| set_expr_operation(right))
+        }
+        E::Insert(stmt) | E::Update(stmt) | E::Delete(stmt) | E::Merge(stmt) => {
+            Some(leading_keywords(&stmt.to_string()))
+        }
+        E::Select(_) | E::Values(_) | E::Table(_) => None,
+    }
+}
+
+/// `CREATE TABLE ...` -> `CREATE TABLE`, `INSERT INTO ...` -> `INSERT`.
+fn leading_keywords(sql: &str) -> String {
+    let mut words = sql.split_whitespace();
+    let first = words.next().unwrap_or("statement").to_uppercase();
+    if !matches!(first.as_str(), "CREATE" | "DROP" | "ALTER") {
+        return first;
+    }
+    let object = words
+        .find(|w| {
+            !matches!(
+                w.to_uppercase().as_str(),
+                "OR" | "REPLACE" | "TEMPORARY" | "TEMP" | "UNIQUE" | "EXTERNAL"
+            )
+        })
+        .filter(|w| w.chars().all(|c| c.is_ascii_alphabetic()));
+    match object {
+        Some(object) => format!("{first} {}", object.to_uppercase()),
+        None => first,
+    }
+}
+
+/// The first DDL, DML or other non-query node in a logical plan, if any.
+fn plan_operation(plan: &LogicalPlan) -> Option<String> {
+    let mut operation = None;
+    plan.apply_with_subqueries(|node| {
+        operation = match node {
+            LogicalPlan::Ddl(ddl) => Some(ddl.name().to_string()),
+            LogicalPlan::Dml(dml) => Some(dml.op.to_string()),
+            LogicalPlan::Copy(_) => Some("COPY".to_string()),
+            LogicalPlan::Statement(stmt) => Some(stmt.name().to_string()),
+            _ => None,
         };
-        let after_idx = idx + keyword.len();
-        let after = if after_idx < upper.len() {
-            upper.as_bytes()[after_idx]
+        Ok(if operation.is_some() {
+            TreeNodeRecursion::Stop
         } else {
-            b' '
-        };
+            TreeNodeRecursion::Continue
+        })
+    })
+    .ok()?;
+    operation
+}
+
+/// `.ext` of the file at `path`, used to filter listed files.
+fn file_extension_filter(path: &str) -> String {
+    Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| format!(".{e}"))
+        .unwrap_or_default()
+}
+
+fn is_glob_char(c: char) -> bool {
+    matches!(c, '*' | '?' | '[')
+}
+
+/// The part of `path` before the first segment containing a glob character,
+/// or `None` if `path` is not a glob.
+fn glob_prefix(path: &str) -> Option<&str> {
+    let glob_at = path.find(is_glob_char)?;
+    let prefix_end = path[..glob_at].rfind('/').map(|i| i + 1).unwrap_or(0);
+    Some(&path[..prefix_end])
+}
+
+/// Drop `key=*` segments from the glob part of `path`.
+///
+/// DataFusion ignores Hive-style `key=value` directories when matching a glob
+/// against file paths, so `events/date=*/*.parquet` has to be listed as
+/// `events/*.parquet`; the partition column is still inferred from the
+/// directory names.
+fn strip_hive_glob_segments(path: &str) -> Result<String> {
+    let Some(prefix) = glob_prefix(path) else {
+        return Ok(path.to_string());
+    };
+
+    let mut segments = Vec::new();
+    for segment in path[prefix.len()..].split('/') {
+        match segment.split_once('=') {
+            Some((_, "*")) => continue,
+            Some(_) => anyhow::bail!(
+                "Unsupported partition pattern '{segment}' in '{path}'. Use 'key=*' and filter the partition column in SQL instead."
+            ),
+            None => segments.push(segment),
+        }
+    }
+    Ok(format!("{prefix}{}", segments.join("/")))
+}
+
+/// Extension of the data files behind `path`.
+///
+/// For a glob this is the extension of the pattern itself; for a directory it
+/// is the extension of the first file with a known format found beneath it.
+fn detect_extension(path: &str) -> Result<Option<String>> {
+    let file_extension = |p: &Path| {
+        p.extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_string())
+    };
+
+    let dir = match glob_prefix(path) {
+        Some(prefix) => match file_extension(Path::new(path)) {
+            Some(ext) if !ext.contains(is_glob_char) => return Ok(Some(ext)),
+            _ if prefix.is_empty() => Path::new("."),
+            _ => Path::new(prefix),
+        },
+        None if Path::new(path).is_dir() => Path::new(path),
+        None => return Ok(file_extension(Path::new(path))),
+    };
+
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut entries = std::fs::read_dir(&dir)
+            .with_context(|| format!("Failed to list directory '{}'", dir.display()))?
+            .collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries.iter().rev() {
+            let name = entry.file_name();
+            if name.to_string_lossy().starts_with(['.', '_']) {
+                continue;
+            }
+            let entry_path = entry.path();
+            if entry_path.is_dir() {
+                pending.push(entry_path);
+            } else if let Some(ext) = file_extension(&entry_path) {
+                if DataFormat::from_extension(&ext).is_ok() {
+                    return Ok(Some(ext));
+                }
+            }
+        }
+    }
+
+    anyhow::bail!("No data files with a supported extension found under '{path}'")
+}
 
-        let before_is_boundary = !before.is_ascii_alphanumeric() && before != b'_';
-        let after_is_boundary = !after.is_ascii_alphanumeric() && after != b'_';
+fn parse_data_type(type_name: &str) -> Result<DataType> {
+    DataType::from_str(type_name)
+        .map_err(|e| anyhow::anyhow!("Invalid type '{type_name}' in schema override: {e}"))
+}
 
-        if before_is_boundary && after_is_boundary {
-            return false; // Found a standalone keyword usage
+fn apply_schema_overrides(
+    inferred: &Schema,
+    overrides: &BTreeMap<String, String>,
+    partition_names: &[String],
+) -> Result<SchemaRef> {
+    for column in overrides.keys() {
+        if inferred.field_with_name(column).is_err() && !partition_names.contains(column) {
+            anyhow::bail!("Schema override for unknown column '{column}'");
         }
     }
-    true // All occurrences are part of identifiers
+
+    let fields = inferred
+        .fields()
+        .iter()
+        .map(|field| match overrides.get(field.name()) {
+            Some(type_name) => Ok(Arc::new(
+                field
+                    .as_ref()
+                    .clone()
+                    .with_data_type(parse_data_type(type_name)?),
+            )),
+            None => Ok(field.clone()),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Arc::new(Schema::new_with_metadata(
+        fields,
+        inferred.metadata().clone(),
+    )))
 }
 
 /// Parse a file spec of the form `[table_name=]path` into (table_name, path).
+///
+/// `path` may be a file, a directory or a glob pattern. An `=` inside a path
+/// segment (as in Hive-style `date=2024-01-01/`) is not taken as a table name.
 pub fn parse_file_spec(spec: &str) -> Result<(String, String)> {
     if let Some((name, path)) = spec.split_once('=') {
         if name.is_empty() {
             anyhow::bail!("Empty table name in file spec: '{spec}'");
         }
-        Ok((name.to_string(), path.to_string()))
-    } else {
-        let path = Path::new(spec);
-        let stem = path
-            .file_stem()
-            .and_then(|s| s.to_str())
-            .ok_or_else(|| anyhow::anyhow!("Cannot determine table name from path: '{spec}'"))?;
-        Ok((stem.to_string(), spec.to_string()))
+        if !name.contains(['/', '\\']) && !name.contains(is_glob_char) {
+            return Ok((name.to_string(), path.to_string()));
+        }
     }
+
+    let path = match glob_prefix(spec) {
+        Some(prefix) => Path::new(prefix),
+        None => Path::new(spec),
+    };
+    let stem = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .ok_or_else(|| anyhow::anyhow!("Cannot determine table name from path: '{spec}'"))?;
+    Ok((stem.to_string(), spec.to_string()))
+}
+
+/// Parse a byte size such as `1048576`, `512K`, `64MiB` or `2G` (binary
+/// units).
+pub fn parse_byte_size(size: &str) -> Result<usize> {
+    let trimmed = size.trim();
+    let split = trimmed
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(trimmed.len());
+    let (digits, unit) = trimmed.split_at(split);
+    let value: usize = digits
+        .parse()
+        .with_context(|| format!("Invalid byte size '{size}'"))?;
+    let multiplier: usize = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        _ => anyhow::bail!("Invalid byte size '{size}'. Use e.g. 1048576, 512K, 64M or 2G"),
+    };
+    value
+        .checked_mul(multiplier)
+        .with_context(|| format!("Byte size '{size}' is too large"))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::sync::Arc;
-
-    fn test_data_path(name: &str) -> String {
-        format!("{}/tests/data/{name}", env!("CARGO_MANIFEST_DIR"))
-    }
+    use crate::test_util::{products_executor, test_data_path};
 
     #[tokio::test]
     async fn test_csv_select() {
@@ -205,6 +1180,28 @@ mod tests {
         assert_eq!(rows[0]["name"], "Widget");
     }
 
+    #[tokio::test]
+    async fn test_list_and_describe_tables() {
+        let exec = SqlExecutor::new().await.unwrap();
+        exec.register_csv("products", &test_data_path("products.csv"))
+            .await
+            .unwrap();
+
+        let tables: Vec<serde_json::Value> =
+            serde_json::from_str(&exec.list_tables_json().await.unwrap()).unwrap();
+        assert_eq!(tables.len(), 1);
+        assert_eq!(tables[0]["table_name"], "products");
+
+        let columns: Vec<serde_json::Value> =
+            serde_json::from_str(&exec.describe_table_json("public.products").await.unwrap())
+                .unwrap();
+        assert_eq!(columns[0]["column_name"], "id");
+        assert_eq!(columns[0]["data_type"], "Int64");
+
+        let err = exec.describe_table_json("missing").await.unwrap_err();
+        assert!(err.to_string().contains("'missing' not found"));
+    }
+
     #[tokio::test]
     async fn test_parquet_select() {
         generate_test_parquet();
@@ -231,6 +1228,66 @@ mod tests {
         assert!(!rows.is_empty());
     }
 
+    #[tokio::test]
+    async fn test_arrow_select() {
+        generate_test_arrow();
+        let exec = SqlExecutor::new().await.unwrap();
+        exec.register_arrow("sales", &test_data_path("sales.arrow"))
+            .await
+            .unwrap();
+        let json = exec
+            .execute_sql_json("SELECT SUM(amount) AS total FROM sales")
+            .await
+            .unwrap();
+        let rows: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+        assert_eq!(rows.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_register_file_detects_feather() {
+        generate_test_arrow();
+        let feather = std::env::temp_dir().join(format!(
+            "datafusion_acp_sales_{}.feather",
+            std::process::id()
+        ));
+        std::fs::copy(test_data_path("sales.arrow"), &feather).unwrap();
+        let exec = SqlExecutor::new().await.unwrap();
+        exec.register_file("sales", feather.to_str().unwrap())
+            .await
+            .unwrap();
+        let df = exec.execute_sql("SELECT * FROM sales").await.unwrap();
+        let batches = df.collect().await.unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 5);
+        let _ = std::fs::remove_file(&feather);
+    }
+
+    #[cfg(feature = "avro")]
+    #[tokio::test]
+    async fn test_avro_select() {
+        let exec = SqlExecutor::new().await.unwrap();
+        exec.register_avro("users", &test_data_path("users.avro"))
+            .await
+            .unwrap();
+        let json = exec
+            .execute_sql_json("SELECT * FROM users ORDER BY id")
+            .await
+            .unwrap();
+        let rows: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0]["name"], "alice");
+    }
+
+    #[cfg(not(feature = "avro"))]
+    #[tokio::test]
+    async fn test_avro_requires_feature() {
+        let exec = SqlExecutor::new().await.unwrap();
+        let err = exec
+            .register_file("users", &test_data_path("users.avro"))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("--features avro"));
+    }
+
     #[tokio::test]
     async fn test_execute_sql_returns_dataframe() {
         let exec = SqlExecutor::new().await.unwrap();
@@ -256,29 +1313,128 @@ mod tests {
         assert!(parsed.is_array());
     }
 
-    #[test]
-    fn test_mutation_detection() {
-        assert!(is_mutation_sql("INSERT INTO t VALUES (1)"));
-        assert!(is_mutation_sql("  insert INTO t VALUES (1)"));
-        assert!(is_mutation_sql("UPDATE t SET x = 1"));
-        assert!(is_mutation_sql("DELETE FROM t WHERE id = 1"));
-        assert!(is_mutation_sql("DROP TABLE t"));
-        assert!(is_mutation_sql("ALTER TABLE t ADD COLUMN x INT"));
-        assert!(is_mutation_sql("CREATE TABLE t (x INT)"));
-        assert!(is_mutation_sql("TRUNCATE TABLE t"));
-        // CTE with mutation
-        assert!(is_mutation_sql(
-            "WITH cte AS (SELECT 1) INSERT INTO t SELECT * FROM cte"
-        ));
+    async fn rejected_operation(exec: &SqlExecutor, sql: &str) -> Option<String> {
+        match exec.ensure_read_only(sql).await {
+            Ok(()) => None,
+            Err(e) => Some(
+                e.downcast_ref::<SafeModeViolation>()
+                    .unwrap_or_else(|| panic!("expected a safe mode violation for {sql}: {e}"))
+                    .operation
+                    .clone(),
+            ),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mutation_detection() {
+        let exec = SqlExecutor::new().await.unwrap();
+        exec.register_csv("t", &test_data_path("products.csv"))
+            .await
+            .unwrap();
+        for (sql, op) in [
+            ("INSERT INTO t VALUES (1, 'a', 1.0, 'b')", "INSERT"),
+            ("  insert INTO t VALUES (1, 'a', 1.0, 'b')", "INSERT"),
+            ("UPDATE t SET price = 1", "UPDATE"),
+            ("DELETE FROM t WHERE id = 1", "DELETE"),
+            ("DROP TABLE t", "DROP TABLE"),
+            ("ALTER TABLE t ADD COLUMN x INT", "ALTER TABLE"),
+            ("CREATE TABLE t2 (x INT)", "CREATE TABLE"),
+            ("CREATE OR REPLACE VIEW v AS SELECT 1", "CREATE VIEW"),
+            ("TRUNCATE TABLE t", "TRUNCATE"),
+            ("COPY t TO '/tmp/out.csv'", "COPY"),
+            ("SET datafusion.execution.batch_size = 1", "SET"),
+            (
+                "WITH cte AS (SELECT 1) INSERT INTO t SELECT * FROM cte",
+                "INSERT",
+            ),
+        ] {
+            assert_eq!(
+                rejected_operation(&exec, sql).await.as_deref(),
+                Some(op),
+                "{sql}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_non_mutation() {
+        let exec = SqlExecutor::new().await.unwrap();
+        exec.register_csv("t", &test_data_path("products.csv"))
+            .await
+            .unwrap();
+        for sql in [
+            "SELECT * FROM t",
+            "  select 1",
+            "SHOW TABLES",
+            "EXPLAIN SELECT 1",
+            "DESCRIBE t",
+            "WITH cte AS (SELECT 1) SELECT * FROM cte",
+            "SELECT 1 UNION ALL SELECT 2",
+        ] {
+            assert_eq!(rejected_operation(&exec, sql).await, None, "{sql}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_sql_to_file_formats() {
+        let exec = SqlExecutor::new().await.unwrap();
+        exec.register_csv("products", &test_data_path("products.csv"))
+            .await
+            .unwrap();
+        let dir =
+            std::env::temp_dir().join(format!("datafusion_acp_export_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for name in ["out.parquet", "out.csv", "out.json"] {
+            let path = dir.join(name);
+            let path = path.to_str().unwrap();
+            // Written twice to check an existing file is replaced, not appended to.
+            for _ in 0..2 {
+                let rows = exec
+                    .write_sql_to_file("SELECT * FROM products", path)
+                    .await
+                    .unwrap();
+                assert_eq!(rows, 3, "{name}");
+            }
+
+            let read_back = SqlExecutor::new().await.unwrap();
+            read_back.register_file("out", path).await.unwrap();
+            let json = read_back
+                .execute_sql_json("SELECT * FROM out ORDER BY id")
+                .await
+                .unwrap();
+            let rows: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+            assert_eq!(rows.len(), 3, "{name}");
+            assert_eq!(rows[0]["name"], "Widget", "{name}");
+        }
+
+        // A query that fails while running leaves the previous export alone.
+        let path = dir.join("out.csv");
+        let path = path.to_str().unwrap();
+        assert!(exec
+            .write_sql_to_file("SELECT CAST(name AS INT) FROM products", path)
+            .await
+            .is_err());
+        let csv = std::fs::read_to_string(path).unwrap();
+        assert_eq!(csv.lines().count(), 4, "{csv}");
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 3);
+
+        std::fs::remove_dir_all(&dir).ok();
     }
 
     #[test]
-    fn test_non_mutation() {
-        assert!(!is_mutation_sql("SELECT * FROM t"));
-        assert!(!is_mutation_sql("  select 1"));
-        assert!(!is_mutation_sql("SHOW TABLES"));
-        assert!(!is_mutation_sql("EXPLAIN SELECT 1"));
-        assert!(!is_mutation_sql("WITH cte AS (SELECT 1) SELECT * FROM cte"));
+    fn test_export_format_from_path() {
+        assert_eq!(
+            ExportFormat::from_path("a.PARQUET").unwrap(),
+            ExportFormat::Parquet
+        );
+        assert_eq!(ExportFormat::from_path("a.csv").unwrap(), ExportFormat::Csv);
+        assert_eq!(
+            ExportFormat::from_path("a.jsonl").unwrap(),
+            ExportFormat::NdJson
+        );
+        assert!(ExportFormat::from_path("a.xlsx").is_err());
+        assert!(ExportFormat::from_path("noext").is_err());
     }
 
     #[tokio::test]
@@ -408,28 +1564,399 @@ mod tests {
         assert_eq!(rows.len(), 0);
     }
 
+    #[tokio::test]
+    async fn test_mutation_detection_tricky() {
+        let exec = SqlExecutor::new().await.unwrap();
+        exec.register_csv("insert_log", &test_data_path("products.csv"))
+            .await
+            .unwrap();
+
+        // Leading whitespace and comments
+        assert!(rejected_operation(&exec, "\n\tDELETE FROM insert_log")
+            .await
+            .is_some());
+        assert!(rejected_operation(
+            &exec,
+            "-- comment\nINSERT INTO insert_log VALUES (1, 'a', 1.0, 'b')"
+        )
+        .await
+        .is_some());
+
+        // Keywords as identifiers are not mutations
+        assert_eq!(
+            rejected_operation(&exec, "SELECT * FROM insert_log").await,
+            None
+        );
+        assert_eq!(
+            rejected_operation(
+                &exec,
+                "WITH cte AS (SELECT 1 AS \"update\", 2 AS \"delete\") SELECT \"update\" FROM cte"
+            )
+            .await,
+            None
+        );
+
+        // Mutations hidden behind a read-only first statement or EXPLAIN
+        assert_eq!(
+            rejected_operation(&exec, "SELECT 1; DROP TABLE insert_log")
+                .await
+                .as_deref(),
+            Some("Multiple statements")
+        );
+        assert_eq!(
+            rejected_operation(&exec, "EXPLAIN ANALYZE DELETE FROM insert_log")
+                .await
+                .as_deref(),
+            Some("DELETE")
+        );
+
+        // Statements the parser cannot see through are caught on the plan
+        assert!(rejected_operation(&exec, "CREATE VIEW v AS SELECT 1")
+            .await
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn test_table_profile_json() {
+        let exec = products_executor().await;
+        exec.ctx.sql("CREATE SCHEMA staging").await.unwrap();
+        exec.ctx
+            .sql("CREATE TABLE staging.orders (id INT)")
+            .await
+            .unwrap();
+        assert_eq!(
+            exec.table_names().await.unwrap(),
+            ["products", "staging.orders"]
+        );
+
+        let profile: serde_json::Value =
+            serde_json::from_str(&exec.table_profile_json("products", 2).await.unwrap()).unwrap();
+        assert_eq!(profile["row_count"], 3);
+        assert_eq!(profile["columns"][2]["column_name"], "price");
+        assert_eq!(profile["sample"].as_array().unwrap().len(), 2);
+        assert_eq!(profile["sample"][0]["name"], "Widget");
+        assert_eq!(profile["statistics"]["price"]["max"], 14.99);
+        assert_eq!(profile["statistics"]["name"]["min"], "Doohickey");
+        assert_eq!(profile["statistics"]["category"]["null_count"], "0");
+    }
+
+    #[tokio::test]
+    async fn test_exploration_limits() {
+        let limits = ResourceLimits {
+            max_rows: 2,
+            ..Default::default()
+        };
+        let exec = SqlExecutor::with_limits(limits).await.unwrap();
+        exec.register_csv("products", &test_data_path("products.csv"))
+            .await
+            .unwrap();
+
+        let result = exec
+            .run_exploration_sql("SELECT id FROM products ORDER BY id")
+            .await
+            .unwrap();
+        assert_eq!(result.rows, 2);
+        assert!(result.truncated);
+        assert_eq!(result.json, r#"[{"id":1},{"id":2}]"#);
+
+        let result = exec
+            .run_exploration_sql("SELECT COUNT(*) AS n FROM products")
+            .await
+            .unwrap();
+        assert!(!result.truncated);
+
+        let limits = ResourceLimits {
+            max_result_bytes: 20,
+            ..Default::default()
+        };
+        let exec = SqlExecutor::with_limits(limits).await.unwrap();
+        exec.register_csv("products", &test_data_path("products.csv"))
+            .await
+            .unwrap();
+        let err = exec
+            .run_exploration_sql("SELECT * FROM products")
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<LimitExceeded>().unwrap().limit,
+            "max_result_bytes"
+        );
+
+        let limits = ResourceLimits {
+            query_timeout: Duration::from_millis(50),
+            ..Default::default()
+        };
+        let exec = SqlExecutor::with_limits(limits).await.unwrap();
+        let err = exec
+            .run_exploration_sql(
+                "SELECT COUNT(*) FROM generate_series(1, 1000000000) a \
+                 JOIN generate_series(1, 1000) b ON a.value % 1000 = b.value",
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<LimitExceeded>().unwrap().limit,
+            "query_timeout"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_memory_limit() {
+        let limits = ResourceLimits {
+            memory_limit: Some(1 << 20),
+            ..Default::default()
+        };
+        let exec = SqlExecutor::with_limits(limits).await.unwrap();
+        let err = exec
+            .run_exploration_sql(
+                // A hash join's build side cannot spill to disk.
+                "SELECT COUNT(*) FROM generate_series(1, 5000000) a \
+                 JOIN generate_series(1, 5000000) b ON a.value = b.value",
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<LimitExceeded>().map(|e| e.limit),
+            Some("memory_limit"),
+            "{err:#}"
+        );
+    }
+
     #[test]
-    fn test_mutation_detection_tricky() {
-        // Leading whitespace and mixed case
-        assert!(is_mutation_sql("   INSERT INTO t VALUES (1)"));
-        assert!(is_mutation_sql("\n\tDELETE FROM t"));
+    fn test_parse_byte_size() {
+        assert_eq!(parse_byte_size("1048576").unwrap(), 1 << 20);
+        assert_eq!(parse_byte_size("512K").unwrap(), 512 << 10);
+        assert_eq!(parse_byte_size("64MiB").unwrap(), 64 << 20);
+        assert_eq!(parse_byte_size("2g").unwrap(), 2 << 30);
+        assert!(parse_byte_size("lots").is_err());
+        assert!(parse_byte_size("5T").is_err());
+    }
 
-        // Keyword as table name (should NOT be flagged as mutation)
-        assert!(!is_mutation_sql("SELECT * FROM insert_log"));
-        assert!(!is_mutation_sql("SELECT delete_count FROM stats"));
+    #[tokio::test]
+    async fn test_explain_sql() {
+        let exec = SqlExecutor::new().await.unwrap();
+        exec.register_csv("products", &test_data_path("products.csv"))
+            .await
+            .unwrap();
+        let sql = "SELECT category, MAX(price) FROM products GROUP BY category";
+
+        let plan = exec.explain_sql(sql, false).await.unwrap();
+        assert!(plan
+            .logical
+            .contains("Aggregate: groupBy=[[products.category]]"));
+        assert!(plan.physical.contains("AggregateExec"));
+        assert!(!plan.physical.contains("metrics=["));
+        assert!(plan.dot.contains("digraph {"));
+        assert!(plan.dot.contains("label=\"AggregateExec"));
+        assert_eq!(plan.rows, None);
+
+        let analyzed = exec.explain_sql(sql, true).await.unwrap();
+        assert!(analyzed.physical.contains("metrics=[output_rows="));
+        assert_eq!(analyzed.rows, Some(2));
+
+        assert!(exec
+            .explain_sql("CREATE VIEW v AS SELECT 1", false)
+            .await
+            .is_err());
+        assert!(exec.ctx.table("v").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_ensure_read_only_reports_planning_errors() {
+        let exec = SqlExecutor::new().await.unwrap();
+        let err = exec
+            .ensure_read_only("SELECT * FROM missing_table")
+            .await
+            .unwrap_err();
+        assert!(err.downcast_ref::<SafeModeViolation>().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_validate_sql() {
+        let exec = SqlExecutor::new().await.unwrap();
+        exec.validate_sql("SELECT 1 AS one", true, Some(1))
+            .await
+            .unwrap();
+        assert!(exec
+            .validate_sql("SELECT nope FROM missing_table", false, None)
+            .await
+            .is_err());
+
+        // Runtime errors only surface when the query is executed
+        let failing_cast = "SELECT CAST('abc' AS INT) AS n";
+        exec.validate_sql(failing_cast, true, None).await.unwrap();
+        assert!(exec
+            .validate_sql(failing_cast, true, Some(1))
+            .await
+            .is_err());
+
+        // Mutations are planned but never run
+        exec.validate_sql("CREATE VIEW validated_v AS SELECT 1", false, Some(1))
+            .await
+            .unwrap();
+        assert!(exec.ctx.table("validated_v").await.is_err());
+        let err = exec
+            .validate_sql("CREATE VIEW validated_v AS SELECT 1", true, None)
+            .await
+            .unwrap_err();
+        assert!(err.downcast_ref::<SafeModeViolation>().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_register_directory_infers_hive_partitions() {
+        let exec = SqlExecutor::new().await.unwrap();
+        exec.register_file("orders", &test_data_path("orders"))
+            .await
+            .unwrap();
+        let json = exec
+            .execute_sql_json(
+                "SELECT region, COUNT(*) AS n FROM orders GROUP BY region ORDER BY region",
+            )
+            .await
+            .unwrap();
+        let rows: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["region"], "EU");
+        assert_eq!(rows[0]["n"], 2);
+        assert_eq!(rows[1]["region"], "US");
+    }
 
-        // Comments before statement
-        assert!(is_mutation_sql("-- comment\nINSERT INTO t VALUES (1)"));
+    #[tokio::test]
+    async fn test_register_glob() {
+        let exec = SqlExecutor::new().await.unwrap();
+        exec.register_file("orders", &test_data_path("orders/region=EU/*.csv"))
+            .await
+            .unwrap();
+        let json = exec
+            .execute_sql_json("SELECT id FROM orders ORDER BY id")
+            .await
+            .unwrap();
+        let rows: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+        assert_eq!(rows.len(), 2);
+    }
 
-        // EXPLAIN should not be flagged
-        assert!(!is_mutation_sql("EXPLAIN INSERT INTO t VALUES (1)"));
+    #[tokio::test]
+    async fn test_register_with_csv_options_and_schema_override() {
+        let exec = SqlExecutor::new().await.unwrap();
+        let options = RegisterOptions {
+            format: Some(DataFormat::Csv),
+            delimiter: Some(';'),
+            has_header: Some(false),
+            schema: BTreeMap::from([("column_1".to_string(), "Utf8".to_string())]),
+            ..Default::default()
+        };
+        exec.register_with_options("suppliers", &test_data_path("suppliers.txt"), &options)
+            .await
+            .unwrap();
+        let df = exec.execute_sql("SELECT * FROM suppliers").await.unwrap();
+        let schema = df.schema().as_arrow().clone();
+        assert_eq!(schema.fields().len(), 3);
+        assert_eq!(schema.field(0).data_type(), &DataType::Utf8);
+        let batches = df.collect().await.unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_schema_override_unknown_column() {
+        let exec = SqlExecutor::new().await.unwrap();
+        let options = RegisterOptions {
+            schema: BTreeMap::from([("nope".to_string(), "Int64".to_string())]),
+            ..Default::default()
+        };
+        let err = exec
+            .register_with_options("products", &test_data_path("products.csv"), &options)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("unknown column 'nope'"));
+    }
+
+    #[tokio::test]
+    async fn test_register_missing_glob_prefix() {
+        let exec = SqlExecutor::new().await.unwrap();
+        let result = exec.register_file("t", "/nonexistent/dir/*.parquet").await;
+        assert!(result.unwrap_err().to_string().contains("File not found"));
+    }
+
+    #[tokio::test]
+    async fn test_register_glob_over_hive_partitions() {
+        let exec = SqlExecutor::new().await.unwrap();
+        exec.register_file("orders", &test_data_path("orders/region=*/*.csv"))
+            .await
+            .unwrap();
+        let json = exec
+            .execute_sql_json("SELECT DISTINCT region FROM orders ORDER BY region")
+            .await
+            .unwrap();
+        let rows: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+        assert_eq!(rows.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_register_glob_without_matches() {
+        let exec = SqlExecutor::new().await.unwrap();
+        let result = exec
+            .register_file("t", &test_data_path("orders/*.parquet"))
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_strip_hive_glob_segments() {
+        assert_eq!(
+            strip_hive_glob_segments("events/date=*/*.parquet").unwrap(),
+            "events/*.parquet"
+        );
+        assert_eq!(
+            strip_hive_glob_segments("events/date=2024-01-01/*.parquet").unwrap(),
+            "events/date=2024-01-01/*.parquet"
+        );
+        assert_eq!(strip_hive_glob_segments("events/").unwrap(), "events/");
+        assert!(strip_hive_glob_segments("events/date=2024-*/*.parquet").is_err());
+    }
+
+    #[test]
+    fn test_parse_file_spec() {
+        assert_eq!(
+            parse_file_spec("items=data/products.csv").unwrap(),
+            ("items".to_string(), "data/products.csv".to_string())
+        );
+        assert_eq!(
+            parse_file_spec("data/products.csv").unwrap(),
+            ("products".to_string(), "data/products.csv".to_string())
+        );
+        assert_eq!(
+            parse_file_spec("data/events/").unwrap(),
+            ("events".to_string(), "data/events/".to_string())
+        );
+        assert_eq!(
+            parse_file_spec("data/events/date=*/*.parquet").unwrap(),
+            (
+                "events".to_string(),
+                "data/events/date=*/*.parquet".to_string()
+            )
+        );
+        assert!(parse_file_spec("=data/products.csv").is_err());
+    }
+
+    /// Generate the test Arrow IPC file with the same rows as sales.parquet
+    fn generate_test_arrow() {
+        use arrow::ipc::writer::FileWriter;
+        use std::fs::File;
+
+        let path = test_data_path("sales.arrow");
+        if Path::new(&path).exists() {
+            return;
+        }
+
+        let batch = sales_batch();
+        let file = File::create(&path).unwrap();
+        let mut writer = FileWriter::try_new(file, &batch.schema()).unwrap();
+        writer.write(&batch).unwrap();
+        writer.finish().unwrap();
     }
 
     /// Generate the test parquet file
     fn generate_test_parquet() {
-        use arrow::array::{Float64Array, Int64Array, StringArray};
-        use arrow::datatypes::{DataType, Field, Schema};
-        use arrow::record_batch::RecordBatch;
         use parquet::arrow::ArrowWriter;
         use std::fs::File;
 
@@ -438,6 +1965,18 @@ mod tests {
             return;
         }
 
+        let batch = sales_batch();
+        let file = File::create(&path).unwrap();
+        let mut writer = ArrowWriter::try_new(file, batch.schema(), None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+    }
+
+    fn sales_batch() -> arrow::record_batch::RecordBatch {
+        use arrow::array::{Float64Array, Int64Array, StringArray};
+        use arrow::datatypes::{DataType, Field, Schema};
+        use arrow::record_batch::RecordBatch;
+
         let schema = Arc::new(Schema::new(vec![
             Field::new("id", DataType::Int64, false),
             Field::new("product_id", DataType::Int64, false),
@@ -445,8 +1984,8 @@ mod tests {
             Field::new("sale_date", DataType::Utf8, false),
         ]));
 
-        let batch = RecordBatch::try_new(
-            schema.clone(),
+        RecordBatch::try_new(
+            schema,
             vec![
                 Arc::new(Int64Array::from(vec![1, 2, 3, 4, 5])),
                 Arc::new(Int64Array::from(vec![1, 2, 1, 3, 2])),
@@ -462,11 +2001,6 @@ mod tests {
                 ])),
             ],
         )
-        .unwrap();
-
-        let file = File::create(&path).unwrap();
-        let mut writer = ArrowWriter::try_new(file, schema, None).unwrap();
-        writer.write(&batch).unwrap();
-        writer.close().unwrap();
+        .unwrap()
     }
 }
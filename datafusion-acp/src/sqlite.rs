@@ -0,0 +1,895 @@
+use std::any::Any;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use datafusion::arrow::array::{
+    ArrayRef, BinaryBuilder, BooleanBuilder, Float64Builder, Int64Builder, StringBuilder,
+};
+use datafusion::arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use datafusion::arrow::record_batch::RecordBatch;
+use datafusion::catalog::{SchemaProvider, Session, TableProvider};
+use datafusion::common::{
+    exec_datafusion_err, not_impl_err, plan_err, DataFusionError, Result as DataFusionResult,
+    ScalarValue,
+};
+use datafusion::datasource::sink::{DataSink, DataSinkExec};
+use datafusion::datasource::TableType;
+use datafusion::execution::{SendableRecordBatchStream, TaskContext};
+use datafusion::logical_expr::dml::InsertOp;
+use datafusion::logical_expr::expr::{Between, InList, Like};
+use datafusion::logical_expr::{BinaryExpr, Expr, Operator, TableProviderFilterPushDown};
+use datafusion::physical_expr::EquivalenceProperties;
+use datafusion::physical_plan::execution_plan::{Boundedness, EmissionType};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::{
+    DisplayAs, DisplayFormatType, ExecutionPlan, Partitioning, PlanProperties,
+};
+use futures::StreamExt;
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{Connection, OpenFlags};
+
+/// Rows per record batch read from SQLite.
+const BATCH_SIZE: usize = 8192;
+
+/// A SQLite database file exposed as a schema: every table and view in it
+/// becomes a [`SqliteTable`].
+///
+/// The file is opened read-only for every scan. Only a database registered
+/// as `writable` accepts `INSERT`, and safe mode rejects that before it gets
+/// here.
+#[derive(Debug)]
+pub struct SqliteSchema {
+    tables: BTreeMap<String, Arc<SqliteTable>>,
+}
+
+impl SqliteSchema {
+    /// Read the table definitions of the database at `path`.
+    pub fn open(path: &str, writable: bool) -> Result<Self> {
+        let conn = open_connection(Path::new(path), false)
+            .with_context(|| format!("Failed to open SQLite database '{path}'"))?;
+        let mut stmt = conn.prepare(
+            "SELECT name, type FROM sqlite_master \
+             WHERE type IN ('table', 'view') AND name NOT LIKE 'sqlite_%' ORDER BY name",
+        )?;
+        let entries = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .with_context(|| format!("Failed to list the tables of '{path}'"))?;
+
+        let path = Arc::new(PathBuf::from(path));
+        let mut tables = BTreeMap::new();
+        for (name, kind) in entries {
+            let mut columns = conn.prepare(&format!(
+                "SELECT name, type FROM pragma_table_info({})",
+                quote_literal(&name)
+            ))?;
+            let declared = columns
+                .query_map([], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()
+                .with_context(|| format!("Failed to read the columns of '{name}'"))?;
+            let fields: Vec<Field> = declared
+                .iter()
+                .map(|(column, declared)| Field::new(column, column_type(declared), true))
+                .collect();
+            let filterable = declared
+                .into_iter()
+                .filter(|(_, declared)| affinity_matches(declared))
+                .map(|(column, _)| column)
+                .collect();
+            let view = kind == "view";
+            tables.insert(
+                name.clone(),
+                Arc::new(SqliteTable {
+                    path: path.clone(),
+                    name,
+                    schema: Arc::new(Schema::new(fields)),
+                    filterable,
+                    view,
+                    writable: writable && !view,
+                }),
+            );
+        }
+        Ok(Self { tables })
+    }
+}
+
+#[async_trait]
+impl SchemaProvider for SqliteSchema {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn table_names(&self) -> Vec<String> {
+        self.tables.keys().cloned().collect()
+    }
+
+    async fn table(&self, name: &str) -> DataFusionResult<Option<Arc<dyn TableProvider>>> {
+        Ok(self
+            .tables
+            .get(name)
+            .map(|table| table.clone() as Arc<dyn TableProvider>))
+    }
+
+    fn table_exist(&self, name: &str) -> bool {
+        self.tables.contains_key(name)
+    }
+}
+
+/// One SQLite table or view. Projections, limits and the filters
+/// [`filter_to_sql`] can translate are pushed into the SQLite query.
+///
+/// Pushed filters are reported as inexact: SQLite's `LIKE` ignores case and
+/// its comparisons follow column affinity, so DataFusion filters the rows
+/// again.
+#[derive(Debug)]
+pub struct SqliteTable {
+    path: Arc<PathBuf>,
+    name: String,
+    schema: SchemaRef,
+    /// Columns filters may be pushed on: those whose SQLite affinity
+    /// matches their Arrow type (see [`affinity_matches`]).
+    filterable: BTreeSet<String>,
+    view: bool,
+    writable: bool,
+}
+
+#[async_trait]
+impl TableProvider for SqliteTable {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.schema.clone()
+    }
+
+    fn table_type(&self) -> TableType {
+        if self.view {
+            TableType::View
+        } else {
+            TableType::Base
+        }
+    }
+
+    fn supports_filters_pushdown(
+        &self,
+        filters: &[&Expr],
+    ) -> DataFusionResult<Vec<TableProviderFilterPushDown>> {
+        Ok(filters
+            .iter()
+            .map(|filter| match filter_to_sql(filter, &self.filterable) {
+                Some(_) => TableProviderFilterPushDown::Inexact,
+                None => TableProviderFilterPushDown::Unsupported,
+            })
+            .collect())
+    }
+
+    async fn scan(
+        &self,
+        _state: &dyn Session,
+        projection: Option<&Vec<usize>>,
+        filters: &[Expr],
+        limit: Option<usize>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        let schema = match projection {
+            Some(projection) => Arc::new(self.schema.project(projection)?),
+            None => self.schema.clone(),
+        };
+        let columns = if schema.fields().is_empty() {
+            // A bare `COUNT(*)` needs the rows but none of the columns.
+            "NULL".to_string()
+        } else {
+            schema
+                .fields()
+                .iter()
+                .map(|field| quote_identifier(field.name()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let mut sql = format!("SELECT {columns} FROM {}", quote_identifier(&self.name));
+        let conditions: Vec<String> = filters
+            .iter()
+            .filter_map(|filter| filter_to_sql(filter, &self.filterable))
+            .collect();
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        if let Some(limit) = limit {
+            sql.push_str(&format!(" LIMIT {limit}"));
+        }
+        Ok(Arc::new(SqliteExec::new(self.path.clone(), sql, schema)))
+    }
+
+    async fn insert_into(
+        &self,
+        _state: &dyn Session,
+        input: Arc<dyn ExecutionPlan>,
+        insert_op: InsertOp,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        if !self.writable {
+            return plan_err!(
+                "SQLite table '{}' is read-only. Declare the database with `writable = true` \
+                 in a --catalog file to allow INSERT.",
+                self.name
+            );
+        }
+        if insert_op != InsertOp::Append {
+            return not_impl_err!("{insert_op} is not supported for SQLite tables");
+        }
+        let sink = SqliteSink {
+            path: self.path.clone(),
+            table: self.name.clone(),
+            schema: self.schema.clone(),
+        };
+        Ok(Arc::new(DataSinkExec::new(input, Arc::new(sink), None)))
+    }
+}
+
+/// Runs one query against a SQLite file on a blocking thread and streams
+/// the rows back in batches.
+#[derive(Debug)]
+struct SqliteExec {
+    path: Arc<PathBuf>,
+    sql: String,
+    schema: SchemaRef,
+    properties: PlanProperties,
+}
+
+impl SqliteExec {
+    fn new(path: Arc<PathBuf>, sql: String, schema: SchemaRef) -> Self {
+        let properties = PlanProperties::new(
+            EquivalenceProperties::new(schema.clone()),
+            Partitioning::UnknownPartitioning(1),
+            EmissionType::Incremental,
+            Boundedness::Bounded,
+        );
+        Self {
+            path,
+            sql,
+            schema,
+            properties,
+        }
+    }
+}
+
+impl DisplayAs for SqliteExec {
+    fn fmt_as(&self, _t: DisplayFormatType, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "SqliteExec: file={}, sql={}",
+            self.path.display(),
+            self.sql
+        )
+    }
+}
+
+impl ExecutionPlan for SqliteExec {
+    fn name(&self) -> &'static str {
+        "SqliteExec"
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn properties(&self) -> &PlanProperties {
+        &self.properties
+    }
+
+    fn children(&self) -> Vec<&Arc<dyn ExecutionPlan>> {
+        vec![]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        _: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        Ok(self)
+    }
+
+    fn execute(
+        &self,
+        _partition: usize,
+        _context: Arc<TaskContext>,
+    ) -> DataFusionResult<SendableRecordBatchStream> {
+        let (tx, rx) = tokio::sync::mpsc::channel(2);
+        let path = self.path.clone();
+        let sql = self.sql.clone();
+        let schema = self.schema.clone();
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = read_batches(&path, &sql, &schema, &tx) {
+                let _ = tx.blocking_send(Err(e));
+            }
+        });
+        let stream = futures::stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|batch| (batch, rx))
+        });
+        Ok(Box::pin(RecordBatchStreamAdapter::new(
+            self.schema.clone(),
+            stream,
+        )))
+    }
+}
+
+/// Run `sql` and send its rows to `tx` in batches of [`BATCH_SIZE`]. Stops
+/// early once the receiver is gone.
+fn read_batches(
+    path: &Path,
+    sql: &str,
+    schema: &SchemaRef,
+    tx: &tokio::sync::mpsc::Sender<DataFusionResult<RecordBatch>>,
+) -> DataFusionResult<()> {
+    let sqlite_error = |e: rusqlite::Error| exec_datafusion_err!("SQLite query failed: {e}");
+    let conn = open_connection(path, false).map_err(sqlite_error)?;
+    let mut stmt = conn.prepare(sql).map_err(sqlite_error)?;
+    let mut rows = stmt.query([]).map_err(sqlite_error)?;
+
+    let new_builders = || {
+        schema
+            .fields()
+            .iter()
+            .map(|field| ColumnBuilder::new(field.data_type()))
+            .collect::<Vec<_>>()
+    };
+    let mut builders = new_builders();
+    let mut buffered = 0;
+    let flush = |builders: Vec<ColumnBuilder>, rows: usize| {
+        let columns: Vec<ArrayRef> = builders.into_iter().map(ColumnBuilder::finish).collect();
+        let options =
+            datafusion::arrow::record_batch::RecordBatchOptions::new().with_row_count(Some(rows));
+        RecordBatch::try_new_with_options(schema.clone(), columns, &options)
+            .map_err(DataFusionError::from)
+    };
+
+    while let Some(row) = rows.next().map_err(sqlite_error)? {
+        for (i, builder) in builders.iter_mut().enumerate() {
+            builder.append(row.get_ref(i).map_err(sqlite_error)?);
+        }
+        buffered += 1;
+        if buffered == BATCH_SIZE {
+            let batch = flush(std::mem::replace(&mut builders, new_builders()), buffered)?;
+            buffered = 0;
+            if tx.blocking_send(Ok(batch)).is_err() {
+                return Ok(());
+            }
+        }
+    }
+    if buffered > 0 {
+        let _ = tx.blocking_send(flush(builders, buffered));
+    }
+    Ok(())
+}
+
+/// Appends SQLite values to an Arrow array of the column's type. SQLite
+/// columns can hold values of any type; ones that do not convert become
+/// NULL.
+enum ColumnBuilder {
+    Int64(Int64Builder),
+    Float64(Float64Builder),
+    Boolean(BooleanBuilder),
+    Binary(BinaryBuilder),
+    Utf8(StringBuilder),
+}
+
+impl ColumnBuilder {
+    fn new(data_type: &DataType) -> Self {
+        match data_type {
+            DataType::Int64 => Self::Int64(Int64Builder::new()),
+            DataType::Float64 => Self::Float64(Float64Builder::new()),
+            DataType::Boolean => Self::Boolean(BooleanBuilder::new()),
+            DataType::Binary => Self::Binary(BinaryBuilder::new()),
+            _ => Self::Utf8(StringBuilder::new()),
+        }
+    }
+
+    fn append(&mut self, value: ValueRef) {
+        let text = || match value {
+            ValueRef::Text(bytes) => std::str::from_utf8(bytes).ok().map(str::trim),
+            _ => None,
+        };
+        match self {
+            Self::Int64(builder) => builder.append_option(match value {
+                ValueRef::Integer(i) => Some(i),
+                ValueRef::Real(f) if f.fract() == 0.0 => Some(f as i64),
+                _ => text().and_then(|t| t.parse().ok()),
+            }),
+            Self::Float64(builder) => builder.append_option(match value {
+                ValueRef::Integer(i) => Some(i as f64),
+                ValueRef::Real(f) => Some(f),
+                _ => text().and_then(|t| t.parse().ok()),
+            }),
+            Self::Boolean(builder) => builder.append_option(match value {
+                ValueRef::Integer(i) => Some(i != 0),
+                ValueRef::Real(f) => Some(f != 0.0),
+                _ => text().and_then(|t| match t.to_lowercase().as_str() {
+                    "true" | "t" | "1" => Some(true),
+                    "false" | "f" | "0" => Some(false),
+                    _ => None,
+                }),
+            }),
+            Self::Binary(builder) => match value {
+                ValueRef::Blob(bytes) | ValueRef::Text(bytes) => builder.append_value(bytes),
+                ValueRef::Integer(i) => builder.append_value(i.to_string()),
+                ValueRef::Real(f) => builder.append_value(f.to_string()),
+                ValueRef::Null => builder.append_null(),
+            },
+            Self::Utf8(builder) => match value {
+                ValueRef::Text(bytes) | ValueRef::Blob(bytes) => {
+                    builder.append_option(std::str::from_utf8(bytes).ok())
+                }
+                ValueRef::Integer(i) => builder.append_value(i.to_string()),
+                ValueRef::Real(f) => builder.append_value(f.to_string()),
+                ValueRef::Null => builder.append_null(),
+            },
+        }
+    }
+
+    fn finish(self) -> ArrayRef {
+        match self {
+            Self::Int64(mut builder) => Arc::new(builder.finish()),
+            Self::Float64(mut builder) => Arc::new(builder.finish()),
+            Self::Boolean(mut builder) => Arc::new(builder.finish()),
+            Self::Binary(mut builder) => Arc::new(builder.finish()),
+            Self::Utf8(mut builder) => Arc::new(builder.finish()),
+        }
+    }
+}
+
+/// Appends the rows of an `INSERT` to a SQLite table in one transaction.
+#[derive(Debug)]
+struct SqliteSink {
+    path: Arc<PathBuf>,
+    table: String,
+    schema: SchemaRef,
+}
+
+impl DisplayAs for SqliteSink {
+    fn fmt_as(&self, _t: DisplayFormatType, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SqliteSink: table={}", self.table)
+    }
+}
+
+#[async_trait]
+impl DataSink for SqliteSink {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    async fn write_all(
+        &self,
+        mut data: SendableRecordBatchStream,
+        _context: &Arc<TaskContext>,
+    ) -> DataFusionResult<u64> {
+        let mut batches = Vec::new();
+        while let Some(batch) = data.next().await {
+            batches.push(batch?);
+        }
+        let path = self.path.clone();
+        let table = self.table.clone();
+        let schema = self.schema.clone();
+        tokio::task::spawn_blocking(move || insert_batches(&path, &table, &schema, &batches))
+            .await
+            .map_err(|e| DataFusionError::External(Box::new(e)))?
+    }
+}
+
+fn insert_batches(
+    path: &Path,
+    table: &str,
+    schema: &SchemaRef,
+    batches: &[RecordBatch],
+) -> DataFusionResult<u64> {
+    let sqlite_error = |e: rusqlite::Error| exec_datafusion_err!("SQLite insert failed: {e}");
+    let mut conn = open_connection(path, true).map_err(sqlite_error)?;
+    let tx = conn.transaction().map_err(sqlite_error)?;
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| quote_identifier(field.name()))
+        .collect::<Vec<_>>();
+    let placeholders = vec!["?"; columns.len()].join(", ");
+    let sql = format!(
+        "INSERT INTO {} ({}) VALUES ({placeholders})",
+        quote_identifier(table),
+        columns.join(", ")
+    );
+
+    let mut count = 0;
+    {
+        let mut stmt = tx.prepare(&sql).map_err(sqlite_error)?;
+        for batch in batches {
+            for row in 0..batch.num_rows() {
+                let values = batch
+                    .columns()
+                    .iter()
+                    .map(|array| ScalarValue::try_from_array(array, row).map(sqlite_value))
+                    .collect::<DataFusionResult<Vec<_>>>()?;
+                stmt.execute(rusqlite::params_from_iter(values))
+                    .map_err(sqlite_error)?;
+                count += 1;
+            }
+        }
+    }
+    tx.commit().map_err(sqlite_error)?;
+    Ok(count)
+}
+
+fn sqlite_value(value: ScalarValue) -> Value {
+    match value {
+        ScalarValue::Int64(Some(i)) => Value::Integer(i),
+        ScalarValue::Float64(Some(f)) => Value::Real(f),
+        ScalarValue::Boolean(Some(b)) => Value::Integer(b.into()),
+        ScalarValue::Binary(Some(bytes)) => Value::Blob(bytes),
+        ScalarValue::Utf8(Some(text)) => Value::Text(text),
+        value if value.is_null() => Value::Null,
+        value => Value::Text(value.to_string()),
+    }
+}
+
+fn open_connection(path: &Path, writable: bool) -> rusqlite::Result<Connection> {
+    let mode = if writable {
+        OpenFlags::SQLITE_OPEN_READ_WRITE
+    } else {
+        OpenFlags::SQLITE_OPEN_READ_ONLY
+    };
+    Connection::open_with_flags(path, mode | OpenFlags::SQLITE_OPEN_NO_MUTEX)
+}
+
+/// The Arrow type for a column declared as `declared`, following SQLite's
+/// type affinity rules. Dates and times are kept as text, and columns
+/// without a declared type (such as view expressions) are read as text.
+fn column_type(declared: &str) -> DataType {
+    let declared = declared.to_uppercase();
+    let has = |part: &str| declared.contains(part);
+    if has("INT") {
+        DataType::Int64
+    } else if has("CHAR") || has("CLOB") || has("TEXT") {
+        DataType::Utf8
+    } else if has("BLOB") {
+        DataType::Binary
+    } else if has("REAL") || has("FLOA") || has("DOUB") {
+        DataType::Float64
+    } else if has("BOOL") {
+        DataType::Boolean
+    } else if has("DATE") || has("TIME") || declared.is_empty() {
+        DataType::Utf8
+    } else {
+        DataType::Float64
+    }
+}
+
+/// Whether SQLite compares the values of a column declared as `declared`
+/// the way DataFusion compares its [`column_type`]. Columns with INTEGER,
+/// REAL or TEXT affinity have their values converted to that type when
+/// stored. Untyped columns (such as view expressions) and NUMERIC ones
+/// (`DECIMAL`, `DATE`, `BOOLEAN`) keep whatever was stored, so a filter
+/// SQLite evaluates on them can drop rows DataFusion would keep.
+fn affinity_matches(declared: &str) -> bool {
+    let declared = declared.to_uppercase();
+    let has = |part: &str| declared.contains(part);
+    has("INT")
+        || has("CHAR")
+        || has("CLOB")
+        || has("TEXT")
+        || (!has("BLOB") && (has("REAL") || has("FLOA") || has("DOUB")))
+}
+
+/// The SQLite condition for a DataFusion filter, if it is one of the simple
+/// forms SQLite evaluates the same way or more loosely: comparisons, `AND`,
+/// `OR`, `NOT`, `IS [NOT] NULL`, `[NOT] IN`, `[NOT] BETWEEN` and `LIKE`, on
+/// `columns` only.
+pub fn filter_to_sql(filter: &Expr, columns: &BTreeSet<String>) -> Option<String> {
+    to_sql(filter, columns, true)
+}
+
+/// `allow_like` is false beneath a `NOT`: SQLite's case-insensitive `LIKE`
+/// matches more rows than DataFusion's, so its negation matches fewer.
+fn to_sql(expr: &Expr, columns: &BTreeSet<String>, allow_like: bool) -> Option<String> {
+    match expr {
+        Expr::Column(column) if columns.contains(&column.name) => {
+            Some(quote_identifier(&column.name))
+        }
+        Expr::Literal(value, _) => literal_to_sql(value),
+        Expr::BinaryExpr(BinaryExpr { left, op, right }) => {
+            let op = match op {
+                Operator::Eq => "=",
+                Operator::NotEq => "<>",
+                Operator::Lt => "<",
+                Operator::LtEq => "<=",
+                Operator::Gt => ">",
+                Operator::GtEq => ">=",
+                Operator::And => "AND",
+                Operator::Or => "OR",
+                _ => return None,
+            };
+            Some(format!(
+                "({} {op} {})",
+                to_sql(left, columns, allow_like)?,
+                to_sql(right, columns, allow_like)?
+            ))
+        }
+        Expr::Not(inner) => Some(format!("(NOT {})", to_sql(inner, columns, false)?)),
+        Expr::IsNull(inner) => Some(format!("({} IS NULL)", to_sql(inner, columns, allow_like)?)),
+        Expr::IsNotNull(inner) => Some(format!(
+            "({} IS NOT NULL)",
+            to_sql(inner, columns, allow_like)?
+        )),
+        Expr::InList(InList {
+            expr,
+            list,
+            negated,
+        }) => {
+            let values = list
+                .iter()
+                .map(|value| to_sql(value, columns, allow_like))
+                .collect::<Option<Vec<_>>>()?;
+            Some(format!(
+                "({} {}IN ({}))",
+                to_sql(expr, columns, allow_like)?,
+                if *negated { "NOT " } else { "" },
+                values.join(", ")
+            ))
+        }
+        Expr::Between(Between {
+            expr,
+            negated,
+            low,
+            high,
+        }) => Some(format!(
+            "({} {}BETWEEN {} AND {})",
+            to_sql(expr, columns, allow_like)?,
+            if *negated { "NOT " } else { "" },
+            to_sql(low, columns, allow_like)?,
+            to_sql(high, columns, allow_like)?
+        )),
+        Expr::Like(Like {
+            negated: false,
+            expr,
+            pattern,
+            escape_char: None,
+            case_insensitive: false,
+        }) if allow_like => Some(format!(
+            "({} LIKE {})",
+            to_sql(expr, columns, allow_like)?,
+            to_sql(pattern, columns, allow_like)?
+        )),
+        _ => None,
+    }
+}
+
+fn literal_to_sql(value: &ScalarValue) -> Option<String> {
+    match value {
+        ScalarValue::Boolean(Some(b)) => Some(if *b { "1" } else { "0" }.to_string()),
+        ScalarValue::Int8(Some(_))
+        | ScalarValue::Int16(Some(_))
+        | ScalarValue::Int32(Some(_))
+        | ScalarValue::Int64(Some(_))
+        | ScalarValue::UInt8(Some(_))
+        | ScalarValue::UInt16(Some(_))
+        | ScalarValue::UInt32(Some(_))
+        | ScalarValue::UInt64(Some(_)) => Some(value.to_string()),
+        ScalarValue::Float32(Some(f)) if f.is_finite() => Some(format!("{f:?}")),
+        ScalarValue::Float64(Some(f)) if f.is_finite() => Some(format!("{f:?}")),
+        ScalarValue::Utf8(Some(text))
+        | ScalarValue::LargeUtf8(Some(text))
+        | ScalarValue::Utf8View(Some(text)) => Some(quote_literal(text)),
+        _ => None,
+    }
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn quote_literal(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use datafusion::prelude::{col, lit, not};
+
+    use super::*;
+    use crate::sql_executor::{RegisterOptions, SqlExecutor};
+
+    /// A database with a `countries` table and a `big_countries` view.
+    fn create_database(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!(
+            "datafusion_acp_{name}_{}.sqlite",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE countries (code TEXT PRIMARY KEY, name VARCHAR(64), \
+                 population INTEGER, area REAL, founded DATE, member BOOLEAN);
+             INSERT INTO countries VALUES
+                 ('FR', 'France', 68, 551695.0, '1958-10-04', 1),
+                 ('IS', 'Iceland', 0, 103000.0, '1944-06-17', 0),
+                 ('JP', 'Japan', 124, 377975.0, NULL, 0);
+             CREATE VIEW big_countries AS
+                 SELECT code, population * 1000000 AS people FROM countries WHERE population > 100;",
+        )
+        .unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_column_type() {
+        assert_eq!(column_type("INTEGER"), DataType::Int64);
+        assert_eq!(column_type("bigint"), DataType::Int64);
+        assert_eq!(column_type("VARCHAR(64)"), DataType::Utf8);
+        assert_eq!(column_type("DOUBLE PRECISION"), DataType::Float64);
+        assert_eq!(column_type("DECIMAL(10,2)"), DataType::Float64);
+        assert_eq!(column_type("BLOB"), DataType::Binary);
+        assert_eq!(column_type("BOOLEAN"), DataType::Boolean);
+        assert_eq!(column_type("DATETIME"), DataType::Utf8);
+        assert_eq!(column_type(""), DataType::Utf8);
+
+        assert!(affinity_matches("bigint"));
+        assert!(affinity_matches("VARCHAR(64)"));
+        assert!(affinity_matches("DOUBLE PRECISION"));
+        assert!(!affinity_matches("DECIMAL(10,2)"));
+        assert!(!affinity_matches("DATE"));
+        assert!(!affinity_matches(""));
+    }
+
+    #[test]
+    fn test_filter_to_sql() {
+        let columns: BTreeSet<String> = ["code", "name", "population", "area"]
+            .map(String::from)
+            .into();
+        let filter = col("population")
+            .gt(lit(10))
+            .and(col("name").like(lit("J%")));
+        assert_eq!(
+            filter_to_sql(&filter, &columns).unwrap(),
+            r#"(("population" > 10) AND ("name" LIKE 'J%'))"#
+        );
+        assert_eq!(
+            filter_to_sql(
+                &col("code").in_list(vec![lit("FR"), lit("it's")], true),
+                &columns
+            )
+            .unwrap(),
+            r#"("code" NOT IN ('FR', 'it''s'))"#
+        );
+        assert_eq!(
+            filter_to_sql(&col("area").between(lit(1.5), lit(2e6)), &columns).unwrap(),
+            r#"("area" BETWEEN 1.5 AND 2000000.0)"#
+        );
+        assert_eq!(
+            filter_to_sql(&col("area").is_null(), &columns).unwrap(),
+            r#"("area" IS NULL)"#
+        );
+        // SQLite's LIKE ignores case, so its negation would drop rows.
+        assert!(filter_to_sql(&not(col("name").like(lit("j%"))), &columns).is_none());
+        assert!(filter_to_sql(&col("name").not_like(lit("j%")), &columns).is_none());
+        assert!(filter_to_sql(&col("name").ilike(lit("j%")), &columns).is_none());
+        // `founded` is a DATE column, whose affinity is NUMERIC.
+        assert!(filter_to_sql(&col("founded").eq(lit("1958-10-04")), &columns).is_none());
+        assert!(filter_to_sql(&(col("population") + lit(1)).gt(lit(10)), &columns).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_tables_are_queryable() {
+        let path = create_database("reference");
+        let executor = SqlExecutor::new().await.unwrap();
+        executor.register_file("ref", &path).await.unwrap();
+
+        let tables = executor.table_names().await.unwrap();
+        assert!(tables.contains(&"ref.countries".to_string()), "{tables:?}");
+        assert!(tables.contains(&"ref.big_countries".to_string()));
+
+        let json = executor
+            .execute_sql_json(
+                "SELECT name, area, founded, member FROM ref.countries \
+                 WHERE population > 10 ORDER BY name",
+            )
+            .await
+            .unwrap();
+        let rows: Vec<serde_json::Value> = serde_json::from_str(&json).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["name"], "France");
+        assert_eq!(rows[0]["area"], 551695.0);
+        assert_eq!(rows[0]["founded"], "1958-10-04");
+        assert_eq!(rows[0]["member"], true);
+        assert!(rows[1].get("founded").is_none());
+
+        let json = executor
+            .execute_sql_json("SELECT code, people FROM ref.big_countries")
+            .await
+            .unwrap();
+        assert!(json.contains("124000000"), "{json}");
+
+        // `people` is an untyped view column read as text, but SQLite holds
+        // integers there: filtering on it is left to DataFusion.
+        let sql = "SELECT code FROM ref.big_countries WHERE people = '124000000' AND code = 'JP'";
+        let json = executor.execute_sql_json(sql).await.unwrap();
+        assert_eq!(json, r#"[{"code":"JP"}]"#);
+        let explanation = executor.explain_sql(sql, false).await.unwrap();
+        assert!(
+            explanation.physical.contains(
+                r#"sql=SELECT "code", "people" FROM "big_countries" WHERE ("code" = 'JP')"#
+            ),
+            "{}",
+            explanation.physical
+        );
+
+        let json = executor
+            .execute_sql_json("SELECT COUNT(*) AS n FROM ref.countries")
+            .await
+            .unwrap();
+        assert_eq!(json, r#"[{"n":3}]"#);
+
+        // The projection and the filter reach SQLite.
+        let explanation = executor
+            .explain_sql(
+                "SELECT name FROM ref.countries WHERE code = 'JP' AND name LIKE 'J%'",
+                false,
+            )
+            .await
+            .unwrap();
+        assert!(
+            explanation.physical.contains(
+                r#"sql=SELECT "code", "name" FROM "countries" WHERE ("code" = 'JP') AND ("name" LIKE 'J%')"#
+            ),
+            "{}",
+            explanation.physical
+        );
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_is_read_only_by_default() {
+        let path = create_database("writable");
+        let executor = SqlExecutor::new().await.unwrap();
+        executor.register_file("ref", &path).await.unwrap();
+        let err = executor
+            .execute_sql("INSERT INTO ref.countries (code, name) VALUES ('IT', 'Italy')")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("read-only"), "{err}");
+
+        let executor = SqlExecutor::new().await.unwrap();
+        let options = RegisterOptions {
+            writable: true,
+            ..Default::default()
+        };
+        executor
+            .register_with_options("ref", &path, &options)
+            .await
+            .unwrap();
+        executor
+            .execute_sql("INSERT INTO ref.countries (code, name) VALUES ('IT', 'Italy')")
+            .await
+            .unwrap()
+            .collect()
+            .await
+            .unwrap();
+        let json = executor
+            .execute_sql_json("SELECT COUNT(*) AS n FROM ref.countries")
+            .await
+            .unwrap();
+        assert_eq!(json, r#"[{"n":4}]"#);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
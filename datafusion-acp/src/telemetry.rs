@@ -0,0 +1,333 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+
+/// Structured record of one question: what the agent explored, how long it
+/// took and what it cost.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct QueryTrace {
+    pub question: String,
+    pub agent: String,
+    pub cached: bool,
+    pub final_sql: Option<String>,
+    pub error: Option<String>,
+    pub repairs: u32,
+    pub wall_time_ms: u64,
+    pub run_sql_calls: usize,
+    pub exploration: Vec<ExplorationQuery>,
+    pub tool_calls: Vec<ToolCallTrace>,
+    pub usage: Option<TokenUsage>,
+}
+
+/// One `run_sql` call made by the agent through the MCP server.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExplorationQuery {
+    pub sql: String,
+    pub duration_ms: u64,
+    pub rows: Option<usize>,
+    pub error: Option<String>,
+}
+
+/// A tool call as announced by the agent over ACP.
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolCallTrace {
+    pub id: String,
+    pub title: String,
+    pub status: String,
+}
+
+/// Token usage reported by the agent in the `_meta.usage` of its responses.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cache_read_tokens: u64,
+    pub cost_usd: Option<f64>,
+}
+
+impl TokenUsage {
+    /// Read usage from an ACP `_meta` object. Both snake_case and camelCase
+    /// keys are accepted since agents disagree on the spelling.
+    pub fn from_meta(meta: &serde_json::Map<String, Value>) -> Option<Self> {
+        let usage = meta.get("usage")?.as_object()?;
+        let count = |keys: &[&str]| {
+            keys.iter()
+                .find_map(|key| usage.get(*key).and_then(Value::as_u64))
+                .unwrap_or(0)
+        };
+        let cost_usd = ["cost_usd", "costUsd", "total_cost_usd", "totalCostUsd"]
+            .iter()
+            .find_map(|key| usage.get(*key).and_then(Value::as_f64))
+            .or_else(|| {
+                ["total_cost_usd", "totalCostUsd"]
+                    .iter()
+                    .find_map(|key| meta.get(*key).and_then(Value::as_f64))
+            });
+        Some(Self {
+            input_tokens: count(&["input_tokens", "inputTokens"]),
+            output_tokens: count(&["output_tokens", "outputTokens"]),
+            cache_read_tokens: count(&["cache_read_input_tokens", "cacheReadInputTokens"]),
+            cost_usd,
+        })
+    }
+
+    fn add(&mut self, other: &TokenUsage) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.cache_read_tokens += other.cache_read_tokens;
+        self.cost_usd = match (self.cost_usd, other.cost_usd) {
+            (Some(a), Some(b)) => Some(a + b),
+            (a, b) => a.or(b),
+        };
+    }
+}
+
+/// Shared handle through which the ACP client and the MCP server add to the
+/// trace of the question being answered.
+#[derive(Debug, Clone, Default)]
+pub struct TraceRecorder {
+    inner: Arc<Mutex<Recording>>,
+}
+
+#[derive(Debug, Default)]
+struct Recording {
+    trace: QueryTrace,
+    started: Option<Instant>,
+}
+
+impl TraceRecorder {
+    /// Start a fresh trace for `question`, discarding the previous one.
+    pub fn begin(&self, question: &str, agent: &str) {
+        self.with(|recording| {
+            *recording = Recording {
+                trace: QueryTrace {
+                    question: question.to_string(),
+                    agent: agent.to_string(),
+                    ..Default::default()
+                },
+                started: Some(Instant::now()),
+            };
+        });
+    }
+
+    pub fn record_sql(&self, sql: &str, duration: Duration, outcome: Result<usize, String>) {
+        self.with(|recording| {
+            let (rows, error) = match outcome {
+                Ok(rows) => (Some(rows), None),
+                Err(error) => (None, Some(error)),
+            };
+            recording.trace.run_sql_calls += 1;
+            recording.trace.exploration.push(ExplorationQuery {
+                sql: sql.to_string(),
+                duration_ms: duration.as_millis() as u64,
+                rows,
+                error,
+            });
+        });
+    }
+
+    pub fn record_tool_call(&self, id: &str, title: &str, status: &str) {
+        self.with(|recording| {
+            recording.trace.tool_calls.push(ToolCallTrace {
+                id: id.to_string(),
+                title: title.to_string(),
+                status: status.to_string(),
+            });
+        });
+    }
+
+    /// Apply a `ToolCallUpdate` to a previously announced tool call.
+    pub fn update_tool_call(&self, id: &str, title: Option<&str>, status: Option<&str>) {
+        self.with(|recording| {
+            let Some(call) = recording
+                .trace
+                .tool_calls
+                .iter_mut()
+                .rev()
+                .find(|call| call.id == id)
+            else {
+                return;
+            };
+            if let Some(title) = title {
+                call.title = title.to_string();
+            }
+            if let Some(status) = status {
+                call.status = status.to_string();
+            }
+        });
+    }
+
+    pub fn add_usage(&self, usage: &TokenUsage) {
+        self.with(|recording| {
+            recording
+                .trace
+                .usage
+                .get_or_insert_with(TokenUsage::default)
+                .add(usage);
+        });
+    }
+
+    /// Close the current trace and return it.
+    pub fn finish(
+        &self,
+        final_sql: Option<&str>,
+        error: Option<&anyhow::Error>,
+        repairs: u32,
+        cached: bool,
+    ) -> QueryTrace {
+        self.with(|recording| {
+            let trace = &mut recording.trace;
+            trace.final_sql = final_sql.map(str::to_string);
+            trace.error = error.map(|e| format!("{e:#}"));
+            trace.repairs = repairs;
+            trace.cached = cached;
+            trace.wall_time_ms = recording
+                .started
+                .map(|started| started.elapsed().as_millis() as u64)
+                .unwrap_or_default();
+            trace.clone()
+        })
+    }
+
+    fn with<T>(&self, f: impl FnOnce(&mut Recording) -> T) -> T {
+        // A poisoned trace is still worth reporting.
+        let mut recording = self
+            .inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        f(&mut recording)
+    }
+}
+
+impl QueryTrace {
+    /// Human-readable summary for `--show-stats`.
+    pub fn render(&self) -> String {
+        let mut out = format!(
+            "Wall time: {:.2}s{}\n",
+            self.wall_time_ms as f64 / 1000.0,
+            if self.cached { " (cached answer)" } else { "" }
+        );
+        out.push_str(&format!("run_sql calls: {}\n", self.run_sql_calls));
+        for (i, query) in self.exploration.iter().enumerate() {
+            let outcome = match (&query.rows, &query.error) {
+                (Some(rows), _) => format!("{rows} rows"),
+                (None, Some(error)) => format!("error: {error}"),
+                (None, None) => "no result".to_string(),
+            };
+            out.push_str(&format!(
+                "  {}. {} ms, {}: {}\n",
+                i + 1,
+                query.duration_ms,
+                outcome,
+                single_line(&query.sql)
+            ));
+        }
+        out.push_str(&format!("Tool calls: {}\n", self.tool_calls.len()));
+        out.push_str(&format!("Repairs: {}\n", self.repairs));
+        match &self.usage {
+            Some(usage) => {
+                out.push_str(&format!(
+                    "Tokens: {} in, {} out, {} cache read",
+                    usage.input_tokens, usage.output_tokens, usage.cache_read_tokens
+                ));
+                if let Some(cost) = usage.cost_usd {
+                    out.push_str(&format!(", ${cost:.4}"));
+                }
+            }
+            None => out.push_str("Tokens: not reported by the agent"),
+        }
+        out
+    }
+
+    /// Append this trace as one JSON line to `path`.
+    pub fn append_to(&self, path: &Path) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open trace file '{}'", path.display()))?;
+        writeln!(file, "{}", serde_json::to_string(self)?)
+            .with_context(|| format!("Failed to write trace file '{}'", path.display()))
+    }
+}
+
+fn single_line(sql: &str) -> String {
+    sql.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_usage_from_meta_accepts_both_spellings() {
+        let snake = json!({"usage": {"input_tokens": 10, "output_tokens": 5, "cost_usd": 0.5}});
+        let camel = json!({"usage": {"inputTokens": 10, "outputTokens": 5}, "totalCostUsd": 0.5});
+        let expected = TokenUsage {
+            input_tokens: 10,
+            output_tokens: 5,
+            cache_read_tokens: 0,
+            cost_usd: Some(0.5),
+        };
+        assert_eq!(
+            TokenUsage::from_meta(snake.as_object().unwrap()),
+            Some(expected.clone())
+        );
+        assert_eq!(
+            TokenUsage::from_meta(camel.as_object().unwrap()),
+            Some(expected)
+        );
+        assert_eq!(TokenUsage::from_meta(json!({}).as_object().unwrap()), None);
+    }
+
+    #[test]
+    fn test_recorder_collects_one_trace_per_question() {
+        let recorder = TraceRecorder::default();
+        recorder.begin("old question", "agent");
+        recorder.record_sql("SELECT 0", Duration::ZERO, Ok(1));
+
+        recorder.begin("how many products?", "agent");
+        recorder.record_sql(
+            "SELECT COUNT(*) FROM products",
+            Duration::from_millis(7),
+            Ok(1),
+        );
+        recorder.record_sql(
+            "SELECT nope",
+            Duration::ZERO,
+            Err("no such column".to_string()),
+        );
+        recorder.record_tool_call("t1", "run_sql", "pending");
+        recorder.update_tool_call("t1", None, Some("completed"));
+        let usage = TokenUsage {
+            input_tokens: 3,
+            ..Default::default()
+        };
+        recorder.add_usage(&usage);
+        recorder.add_usage(&usage);
+
+        let trace = recorder.finish(Some("SELECT 3"), None, 1, false);
+        assert_eq!(trace.question, "how many products?");
+        assert_eq!(trace.run_sql_calls, 2);
+        assert_eq!(trace.exploration[0].duration_ms, 7);
+        assert_eq!(
+            trace.exploration[1].error.as_deref(),
+            Some("no such column")
+        );
+        assert_eq!(trace.tool_calls[0].status, "completed");
+        assert_eq!(trace.usage.as_ref().unwrap().input_tokens, 6);
+        assert_eq!(trace.repairs, 1);
+
+        let rendered = trace.render();
+        assert!(rendered.contains("run_sql calls: 2"));
+        assert!(rendered.contains("1. 7 ms, 1 rows: SELECT COUNT(*) FROM products"));
+        assert!(rendered.contains("Tokens: 6 in, 0 out"));
+    }
+}
@@ -0,0 +1,369 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use datafusion::arrow::datatypes::{DataType, TimeUnit};
+use datafusion::common::ScalarValue;
+use datafusion::logical_expr::Expr;
+use datafusion::prelude::DataFrame;
+use datafusion::sql::sqlparser::dialect::GenericDialect;
+use datafusion::sql::sqlparser::tokenizer::{Token, Tokenizer};
+use datafusion::sql::unparser::Unparser;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::sql_executor::SqlExecutor;
+
+/// A SQL query with named `$placeholders`, each declared with a type and a
+/// default value, so it can be rerun with other values without the agent.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueryTemplate {
+    pub sql: String,
+    #[serde(default)]
+    pub parameters: Vec<TemplateParameter>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TemplateParameter {
+    /// Referred to as `$name` in the SQL.
+    pub name: String,
+    #[serde(rename = "type")]
+    pub param_type: ParamType,
+    /// A JSON string, number or boolean.
+    pub default: Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParamType {
+    String,
+    Integer,
+    Float,
+    Boolean,
+    Date,
+    Timestamp,
+}
+
+impl ParamType {
+    fn data_type(self) -> DataType {
+        match self {
+            ParamType::String => DataType::Utf8,
+            ParamType::Integer => DataType::Int64,
+            ParamType::Float => DataType::Float64,
+            ParamType::Boolean => DataType::Boolean,
+            ParamType::Date => DataType::Date32,
+            ParamType::Timestamp => DataType::Timestamp(TimeUnit::Microsecond, None),
+        }
+    }
+}
+
+impl TemplateParameter {
+    /// `text` as a value of this parameter's type.
+    fn parse(&self, text: &str) -> Result<ScalarValue> {
+        ScalarValue::try_from_string(text.to_string(), &self.param_type.data_type()).map_err(|e| {
+            anyhow::anyhow!(
+                "Invalid value '{text}' for parameter '{}' of type {}: {e}",
+                self.name,
+                serde_json::to_string(&self.param_type).unwrap_or_default()
+            )
+        })
+    }
+
+    fn default_value(&self) -> Result<ScalarValue> {
+        let text = match &self.default {
+            Value::String(text) => text.clone(),
+            Value::Number(number) => number.to_string(),
+            Value::Bool(flag) => flag.to_string(),
+            other => anyhow::bail!(
+                "Default for parameter '{}' must be a string, number or boolean, got {other}",
+                self.name
+            ),
+        };
+        self.parse(&text)
+    }
+}
+
+impl QueryTemplate {
+    /// A template for `sql`, checking that every `$placeholder` it uses is
+    /// declared, every parameter is used and every default has its type.
+    pub fn new(sql: &str, parameters: Vec<TemplateParameter>) -> Result<Self> {
+        let template = Self {
+            sql: sql.to_string(),
+            parameters,
+        };
+        let mut names = HashSet::new();
+        for parameter in &template.parameters {
+            if !names.insert(parameter.name.as_str()) {
+                anyhow::bail!("Parameter '{}' is declared twice", parameter.name);
+            }
+            parameter.default_value()?;
+        }
+        let mut used = HashSet::new();
+        for token in template.tokens()? {
+            if let Token::Placeholder(placeholder) = token {
+                let name = placeholder.trim_start_matches(['$', ':', '@', '?']);
+                if !names.contains(name) {
+                    anyhow::bail!(
+                        "Placeholder '{placeholder}' is not declared. Use named placeholders \
+                         like $region and declare each one in `parameters`."
+                    );
+                }
+                used.insert(name.to_string());
+            }
+        }
+        if let Some(unused) = template
+            .parameters
+            .iter()
+            .find(|parameter| !used.contains(&parameter.name))
+        {
+            anyhow::bail!(
+                "Parameter '{}' is not used in the SQL as ${}",
+                unused.name,
+                unused.name
+            );
+        }
+        Ok(template)
+    }
+
+    fn tokens(&self) -> Result<Vec<Token>> {
+        Tokenizer::new(&GenericDialect {}, &self.sql)
+            .with_unescape(false)
+            .tokenize()
+            .with_context(|| format!("Failed to parse SQL: {}", self.sql))
+    }
+
+    /// The value of every parameter: the `name=value` pairs in `overrides`,
+    /// or the default.
+    pub fn values(&self, overrides: &[(String, String)]) -> Result<Vec<(String, ScalarValue)>> {
+        for (name, _) in overrides {
+            if !self.parameters.iter().any(|p| &p.name == name) {
+                let declared = self
+                    .parameters
+                    .iter()
+                    .map(|p| p.name.as_str())
+                    .collect::<Vec<_>>();
+                anyhow::bail!(
+                    "Unknown parameter '{name}'. The template takes: {}",
+                    if declared.is_empty() {
+                        "no parameters".to_string()
+                    } else {
+                        declared.join(", ")
+                    }
+                );
+            }
+        }
+        self.parameters
+            .iter()
+            .map(|parameter| {
+                let value = match overrides.iter().rev().find(|(n, _)| n == &parameter.name) {
+                    Some((_, text)) => parameter.parse(text)?,
+                    None => parameter.default_value()?,
+                };
+                Ok((parameter.name.clone(), value))
+            })
+            .collect()
+    }
+
+    /// The SQL with every placeholder replaced by its value as a literal,
+    /// for showing and validating one instance of the template.
+    pub fn render(&self, values: &[(String, ScalarValue)]) -> Result<String> {
+        let unparser = Unparser::default();
+        let mut sql = String::new();
+        for token in self.tokens()? {
+            let Token::Placeholder(placeholder) = &token else {
+                sql.push_str(&token.to_string());
+                continue;
+            };
+            let name = placeholder.trim_start_matches(['$', ':', '@', '?']);
+            let (_, value) = values
+                .iter()
+                .find(|(n, _)| n == name)
+                .ok_or_else(|| anyhow::anyhow!("No value for parameter '{name}'"))?;
+            let literal = unparser.expr_to_sql(&Expr::Literal(value.clone(), None))?;
+            sql.push_str(&literal.to_string());
+        }
+        Ok(sql)
+    }
+
+    /// Plan the template with `values` bound to its placeholders.
+    pub async fn dataframe(
+        &self,
+        executor: &SqlExecutor,
+        values: Vec<(String, ScalarValue)>,
+    ) -> Result<DataFrame> {
+        let df = executor.execute_sql(&self.sql).await?;
+        Ok(df.with_param_values(values)?)
+    }
+}
+
+/// A template saved under a name, with the question it answered.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SavedTemplate {
+    pub question: String,
+    #[serde(flatten)]
+    pub template: QueryTemplate,
+}
+
+/// Named templates, one JSON file each.
+pub struct TemplateStore {
+    dir: PathBuf,
+}
+
+impl TemplateStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// `$XDG_CONFIG_HOME/datafusion-acp/templates`, falling back to
+    /// `~/.config/datafusion-acp/templates`.
+    pub fn default_dir() -> Option<PathBuf> {
+        let base = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(base.join("datafusion-acp").join("templates"))
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if !valid {
+            anyhow::bail!("Invalid template name '{name}': use letters, digits, '_' and '-' only");
+        }
+        Ok(self.dir.join(format!("{name}.json")))
+    }
+
+    /// Save `template` under `name`, replacing any template of that name.
+    pub fn save(&self, name: &str, template: &SavedTemplate) -> Result<()> {
+        let path = self.path(name)?;
+        std::fs::create_dir_all(&self.dir).with_context(|| {
+            format!(
+                "Failed to create templates directory '{}'",
+                self.dir.display()
+            )
+        })?;
+        std::fs::write(&path, serde_json::to_string_pretty(template)?)
+            .with_context(|| format!("Failed to write template '{}'", path.display()))
+    }
+
+    pub fn load(&self, name: &str) -> Result<SavedTemplate> {
+        let path = self.path(name)?;
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("No template named '{name}' in '{}'", self.dir.display()))?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("Invalid template file '{}'", path.display()))
+    }
+
+    /// The saved templates by name, in name order.
+    pub fn list(&self) -> Result<Vec<(String, SavedTemplate)>> {
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return Ok(Vec::new());
+        };
+        let mut templates = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                if let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) {
+                    templates.push((name.to_string(), self.load(name)?));
+                }
+            }
+        }
+        templates.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(templates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::products_executor;
+    use datafusion::arrow::util::pretty::pretty_format_batches;
+    use serde_json::json;
+
+    fn template() -> QueryTemplate {
+        QueryTemplate::new(
+            "SELECT name FROM products WHERE category = $category AND price < $max_price ORDER BY name",
+            vec![
+                TemplateParameter {
+                    name: "category".to_string(),
+                    param_type: ParamType::String,
+                    default: json!("hardware"),
+                },
+                TemplateParameter {
+                    name: "max_price".to_string(),
+                    param_type: ParamType::Float,
+                    default: json!(100),
+                },
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_template_checks_parameters() {
+        let parameter = |name: &str, param_type, default| TemplateParameter {
+            name: name.to_string(),
+            param_type,
+            default,
+        };
+        let err =
+            |sql: &str, parameters| QueryTemplate::new(sql, parameters).unwrap_err().to_string();
+        assert!(err("SELECT $a", vec![]).contains("'$a' is not declared"));
+        assert!(err("SELECT $1", vec![]).contains("named placeholders"));
+        assert!(err(
+            "SELECT 1",
+            vec![parameter("a", ParamType::Integer, json!(1))]
+        )
+        .contains("not used"));
+        assert!(err(
+            "SELECT $d",
+            vec![parameter("d", ParamType::Date, json!("last week"))]
+        )
+        .contains("Invalid value 'last week' for parameter 'd' of type \"date\""));
+    }
+
+    #[test]
+    fn test_values_and_render() {
+        let template = template();
+        let overrides = vec![("category".to_string(), "it's".to_string())];
+        assert_eq!(
+            template
+                .render(&template.values(&overrides).unwrap())
+                .unwrap(),
+            "SELECT name FROM products WHERE category = 'it''s' AND price < 100.0 ORDER BY name"
+        );
+        let err = template
+            .values(&[("region".to_string(), "EU".to_string())])
+            .unwrap_err();
+        assert!(err.to_string().contains("category, max_price"), "{err}");
+        assert!(template
+            .values(&[("max_price".to_string(), "cheap".to_string())])
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dataframe_binds_placeholders() {
+        let executor = products_executor().await;
+        let template = template();
+        let overrides = vec![("max_price".to_string(), "9".to_string())];
+        for (overrides, expected) in [(vec![], "Doohickey\nWidget"), (overrides, "Doohickey")] {
+            let df = template
+                .dataframe(&executor, template.values(&overrides).unwrap())
+                .await
+                .unwrap();
+            let table = pretty_format_batches(&df.collect().await.unwrap())
+                .unwrap()
+                .to_string();
+            for name in expected.lines() {
+                assert!(table.contains(name), "{table}");
+            }
+            assert_eq!(
+                table.lines().count(),
+                expected.lines().count() + 4,
+                "{table}"
+            );
+        }
+    }
+}
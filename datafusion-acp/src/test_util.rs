@@ -0,0 +1,17 @@
+//! Fixtures shared by the unit tests.
+
+use crate::sql_executor::SqlExecutor;
+
+pub fn test_data_path(name: &str) -> String {
+    format!("{}/tests/data/{name}", env!("CARGO_MANIFEST_DIR"))
+}
+
+/// A fresh executor with `tests/data/products.csv` registered as `products`.
+pub async fn products_executor() -> SqlExecutor {
+    let executor = SqlExecutor::new().await.unwrap();
+    executor
+        .register_file("products", &test_data_path("products.csv"))
+        .await
+        .unwrap();
+    executor
+}
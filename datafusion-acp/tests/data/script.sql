@@ -0,0 +1,8 @@
+-- Mixed SQL and CLAUDE statements for --execute-file; the third one fails.
+CREATE TABLE notes AS VALUES ('a;b'), ('it''s');
+SELECT COUNT(*) AS n FROM notes;
+SELECT * FROM missing_table;
+CLAUDE what's the most expensive product?
+SELECT column1 AS note
+FROM notes
+ORDER BY note;
@@ -0,0 +1,711 @@
+//! End-to-end tests that drive the CLI against the scripted mock agent in
+//! `tests/support/mock_acp_agent.rs`. No API key or network access needed.
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Output, Stdio};
+
+fn data_path(name: &str) -> String {
+    format!("{}/tests/data/{name}", env!("CARGO_MANIFEST_DIR"))
+}
+
+/// Run the CLI on `products.csv` with the mock agent replaying `script`.
+fn run_cli(script: &str, args: &[&str], stdin: Option<&str>) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_datafusion-acp"))
+        .arg("--file")
+        .arg(data_path("products.csv"))
+        .arg("--agent")
+        .arg(env!("CARGO_BIN_EXE_mock-acp-agent"))
+        .arg("--no-cache")
+        .args(args)
+        .env("MOCK_ACP_SCRIPT", data_path(&format!("agent/{script}")))
+        .env_remove("ACP_SAFE_MODE")
+        .env_remove("ACP_TIMEOUT")
+        .env("DATAFUSION_ACP_HISTORY", "")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to start datafusion-acp");
+
+    let mut input = child.stdin.take().unwrap();
+    if let Some(text) = stdin {
+        input.write_all(text.as_bytes()).unwrap();
+    }
+    drop(input);
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn test_one_shot_runs_final_query() {
+    let output = run_cli(
+        "one_shot.json",
+        &["-o", "csv", "--show-summary", "most expensive product"],
+        None,
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "name\nGadget\n");
+    assert!(stderr(&output).contains("Most expensive product"));
+}
+
+#[test]
+fn test_stats_and_trace_file() {
+    let trace_path =
+        std::env::temp_dir().join(format!("datafusion-acp-trace-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&trace_path);
+
+    let output = run_cli(
+        "one_shot.json",
+        &[
+            "--show-stats",
+            "--trace-file",
+            trace_path.to_str().unwrap(),
+            "most expensive product",
+        ],
+        None,
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stderr = stderr(&output);
+    assert!(stderr.contains("run_sql calls: 1"), "{stderr}");
+    assert!(stderr.contains("Tokens: 1200 in, 80 out"), "{stderr}");
+
+    let trace = std::fs::read_to_string(&trace_path).unwrap();
+    let _ = std::fs::remove_file(&trace_path);
+    let trace: serde_json::Value = serde_json::from_str(trace.trim()).unwrap();
+    assert_eq!(trace["question"], "most expensive product");
+    assert_eq!(trace["run_sql_calls"], 1);
+    assert_eq!(trace["exploration"][0]["rows"], 1);
+    assert_eq!(trace["tool_calls"][0]["title"], "mcp__datafusion__run_sql");
+    assert_eq!(trace["tool_calls"][0]["status"], "completed");
+    assert_eq!(trace["usage"]["output_tokens"], 80);
+    assert_eq!(
+        trace["final_sql"],
+        "SELECT name FROM products ORDER BY price DESC LIMIT 1"
+    );
+}
+
+#[test]
+fn test_claude_table_function_materializes_answer() {
+    let output = run_cli(
+        "one_shot.json",
+        &["-o", "csv"],
+        Some("SELECT upper(name) AS name FROM claude('most expensive product');\n"),
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "name\nGADGET\n");
+}
+
+#[test]
+fn test_claude_filter_compiles_a_predicate() {
+    let output = run_cli(
+        "filter.json",
+        &["-o", "csv"],
+        Some("SELECT name FROM products WHERE claude_filter('cheap products') ORDER BY name;\n"),
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "name\nDoohickey\nWidget\n");
+}
+
+#[test]
+fn test_claude_sql_returns_sql_without_running_it() {
+    let output = run_cli(
+        "one_shot.json",
+        &["-o", "csv"],
+        Some("SELECT claude_sql('most expensive product') AS generated;\n"),
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "generated\nSELECT name FROM products ORDER BY price DESC LIMIT 1\n"
+    );
+}
+
+#[test]
+fn test_explain_does_not_ask_the_agent() {
+    // The script fails any prompt, so this only passes if no agent is asked.
+    let output = run_cli(
+        "no_final_query.json",
+        &[],
+        Some("EXPLAIN SELECT * FROM claude('most expensive product');\n"),
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stdout(&output).contains("unanswered"),
+        "{}",
+        stdout(&output)
+    );
+    assert!(stderr(&output).is_empty(), "{}", stderr(&output));
+}
+
+#[test]
+fn test_repl_follow_up_reuses_session() {
+    let output = run_cli(
+        "follow_up.json",
+        &["-o", "csv"],
+        Some("CLAUDE cheapest product\nCLAUDE and its price?\n"),
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "name\nDoohickey\nname,price\nDoohickey,4.99\n"
+    );
+}
+
+#[test]
+fn test_explain_prints_plans_and_summary() {
+    let output = run_cli(
+        "one_shot.json",
+        &["--explain", "most expensive product"],
+        None,
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(
+        out.contains("== Summary ==\nMost expensive product"),
+        "{out}"
+    );
+    assert!(
+        out.contains("== Logical Plan ==\nProjection: products.name"),
+        "{out}"
+    );
+    assert!(
+        out.contains("== Physical Plan (analyzed, 1 rows) =="),
+        "{out}"
+    );
+    assert!(out.contains("metrics=[output_rows="), "{out}");
+    assert!(out.contains("digraph {"), "{out}");
+    assert!(!out.contains("Gadget"), "{out}");
+
+    let output = run_cli(
+        "one_shot.json",
+        &["--explain=plan", "most expensive product"],
+        None,
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert!(out.contains("== Physical Plan ==\n"), "{out}");
+    assert!(!out.contains("metrics=["), "{out}");
+}
+
+#[test]
+fn test_repl_explain_toggle() {
+    let output = run_cli(
+        "follow_up.json",
+        &["-o", "csv"],
+        Some("\\explain plan\nCLAUDE cheapest product\n\\explain\nCLAUDE and its price?\n"),
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    let out = stdout(&output);
+    assert_eq!(out.matches("== Logical Plan ==").count(), 1, "{out}");
+    assert!(out.ends_with("name,price\nDoohickey,4.99\n"), "{out}");
+    let err = stderr(&output);
+    assert!(err.contains("Explaining answers with EXPLAIN."), "{err}");
+    assert!(err.contains("Explain is off."), "{err}");
+}
+
+#[test]
+fn test_repl_meta_commands() {
+    let stdin = format!(
+        "\\format csv\n\\d products\n\\load more={}\n\\load more={}\n\\timing\nSELECT COUNT(*) AS n FROM more\n",
+        data_path("products.csv"),
+        data_path("products.csv"),
+    );
+    let output = run_cli("one_shot.json", &[], Some(&stdin));
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "column_name,data_type,is_nullable\n\
+         id,Int64,YES\n\
+         name,Utf8,YES\n\
+         price,Float64,YES\n\
+         category,Utf8,YES\n\
+         n\n3\n"
+    );
+    let err = stderr(&output);
+    assert!(err.contains("Output format is csv."), "{err}");
+    assert!(err.contains("Table 'more' is already registered"), "{err}");
+    assert!(err.contains("Time: "), "{err}");
+}
+
+#[test]
+fn test_repl_describes_schema_qualified_tables() {
+    let stdin = "CREATE SCHEMA staging;\n\
+                 CREATE TABLE staging.products (sku VARCHAR);\n\
+                 \\d staging.products\n\
+                 \\d products\n";
+    let output = run_cli(
+        "one_shot.json",
+        &["-o", "csv", "--no-safe-mode"],
+        Some(stdin),
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    // Each table shows its own columns only, not those of its namesake.
+    assert!(
+        stdout(&output).ends_with(
+            "column_name,data_type,is_nullable\n\
+             sku,Utf8View,YES\n\
+             column_name,data_type,is_nullable\n\
+             id,Int64,YES\n\
+             name,Utf8,YES\n\
+             price,Float64,YES\n\
+             category,Utf8,YES\n"
+        ),
+        "{}",
+        stdout(&output)
+    );
+}
+
+#[test]
+fn test_execute_file_reports_failures_and_continues() {
+    let script = data_path("script.sql");
+    let output = run_cli(
+        "one_shot.json",
+        &["-o", "csv", "--no-safe-mode", "--execute-file", &script],
+        None,
+    );
+    assert!(!output.status.success());
+    assert_eq!(stdout(&output), "n\n2\nname\nGadget\nnote\na;b\nit's\n");
+    let err = stderr(&output);
+    assert!(err.contains("missing_table"), "{err}");
+    assert!(err.contains("1 of 5 statements failed"), "{err}");
+}
+
+#[test]
+fn test_execute_file_stop_on_error() {
+    let script = data_path("script.sql");
+    let output = run_cli(
+        "one_shot.json",
+        &[
+            "-o",
+            "csv",
+            "--no-safe-mode",
+            "--stop-on-error",
+            "--execute-file",
+            &script,
+        ],
+        None,
+    );
+    assert!(!output.status.success());
+    assert_eq!(stdout(&output), "n\n2\n");
+    assert!(
+        stderr(&output).contains("missing_table"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn test_repl_runs_every_statement_on_a_line() {
+    let output = run_cli(
+        "one_shot.json",
+        &["-o", "csv"],
+        Some("SELECT 1 AS a; SELECT 'x;y' AS b;\nSELECT 'multi\nline' AS c;\n"),
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "a\n1\nb\nx;y\nc\n\"multi\nline\"\n");
+}
+
+#[test]
+fn test_agent_over_tcp() {
+    let mut agent = Command::new(env!("CARGO_BIN_EXE_mock-acp-agent"))
+        .env("MOCK_ACP_SCRIPT", data_path("agent/one_shot.json"))
+        .env("MOCK_ACP_LISTEN", "127.0.0.1:0")
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("failed to start mock-acp-agent");
+    let mut addr = String::new();
+    BufReader::new(agent.stdout.take().unwrap())
+        .read_line(&mut addr)
+        .unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_datafusion-acp"))
+        .arg("--file")
+        .arg(data_path("products.csv"))
+        .arg("--agent")
+        .arg(format!("tcp://{}", addr.trim()))
+        .args(["--no-cache", "-o", "csv", "most expensive product"])
+        .env_remove("ACP_SAFE_MODE")
+        .env_remove("ACP_TIMEOUT")
+        .output()
+        .unwrap();
+    let _ = agent.kill();
+    let _ = agent.wait();
+
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "name\nGadget\n");
+}
+
+#[test]
+fn test_missing_final_query_fails() {
+    let output = run_cli("no_final_query.json", &["anything"], None);
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("Agent did not call final_query"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn test_timeout_cancels_prompt() {
+    let output = run_cli("slow.json", &["--timeout", "1", "anything"], None);
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("ACP flow timed out"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn test_permission_request_allows_once() {
+    let output = run_cli("permission.json", &["-o", "csv", "count products"], None);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "n\n3\n");
+}
+
+#[test]
+fn test_safe_mode_rejects_agent_mutations() {
+    let output = run_cli(
+        "safe_mode.json",
+        &["--max-repairs", "1", "drop the products table"],
+        None,
+    );
+    assert!(!output.status.success());
+    let stderr = stderr(&output);
+    assert!(
+        stderr.contains("still failed validation after 1 repair attempts"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("DROP TABLE is not allowed"), "{stderr}");
+}
+
+#[test]
+fn test_confirm_mutations_asks_before_each_mutation() {
+    // Reject the exploration mutation, approve the final one
+    let output = run_cli(
+        "confirm.json",
+        &["--confirm-mutations", "cheap products view"],
+        Some("n\ny\n"),
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stderr = stderr(&output);
+    assert_eq!(stderr.matches("[Confirm]").count(), 2, "{stderr}");
+    assert!(stderr.contains("Logical plan:"), "{stderr}");
+    assert!(stderr.contains("CreateView"), "{stderr}");
+}
+
+#[test]
+fn test_confirm_mutations_rejected_final_query_fails() {
+    let output = run_cli(
+        "confirm.json",
+        &["--confirm-mutations", "cheap products view"],
+        Some("n\nn\n"),
+    );
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("The user rejected this CREATE VIEW statement"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn test_serve_needs_exactly_one_transport() {
+    for args in [&["serve"][..], &["serve", "--port", "0", "--stdio"]] {
+        let output = Command::new(env!("CARGO_BIN_EXE_datafusion-acp"))
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2), "{args:?}");
+        assert!(stderr(&output).contains("--stdio"), "{}", stderr(&output));
+    }
+}
+
+#[test]
+fn test_serve_with_confirm_mutations_stays_in_safe_mode() {
+    // `serve` has nobody to ask, so --confirm-mutations must not lift safe mode.
+    let mut child = Command::new(env!("CARGO_BIN_EXE_datafusion-acp"))
+        .arg("--file")
+        .arg(data_path("products.csv"))
+        .args(["--confirm-mutations", "serve", "--stdio"])
+        .env_remove("ACP_SAFE_MODE")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to start datafusion-acp serve");
+    let mut input = child.stdin.take().unwrap();
+    let mut output = BufReader::new(child.stdout.take().unwrap());
+    let mut request = |message: serde_json::Value| {
+        writeln!(input, "{message}").unwrap();
+        if message.get("id").is_none() {
+            return serde_json::Value::Null;
+        }
+        let mut line = String::new();
+        output.read_line(&mut line).unwrap();
+        serde_json::from_str::<serde_json::Value>(&line).unwrap()
+    };
+
+    request(serde_json::json!({
+        "jsonrpc": "2.0", "id": 1, "method": "initialize",
+        "params": {
+            "protocolVersion": "2025-03-26",
+            "capabilities": {},
+            "clientInfo": {"name": "test", "version": "0"}
+        }
+    }));
+    request(serde_json::json!({"jsonrpc": "2.0", "method": "notifications/initialized"}));
+    let response = request(serde_json::json!({
+        "jsonrpc": "2.0", "id": 2, "method": "tools/call",
+        "params": {"name": "run_sql", "arguments": {"sql": "DROP TABLE products"}}
+    }));
+    let text = response["result"]["content"][0]["text"]
+        .as_str()
+        .unwrap_or_default();
+    assert!(text.contains("DROP TABLE is not allowed"), "{response}");
+    assert_eq!(response["result"]["isError"], true, "{response}");
+
+    drop(input);
+    child.kill().ok();
+    let output = child.wait_with_output().unwrap();
+    assert!(
+        stderr(&output).contains("serve cannot ask before mutations"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn test_invalid_final_query_is_repaired() {
+    let output = run_cli(
+        "repair.json",
+        &["-o", "csv", "--validate-limit", "5", "cheapest product"],
+        None,
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "name\nDoohickey\n");
+    assert!(stderr(&output).contains("after 2 repair attempt(s)"));
+}
+
+#[test]
+fn test_eval_with_confirm_mutations_stays_in_safe_mode() {
+    // Like `serve`, `eval` cannot ask, so the agent's DROP must be rejected.
+    let output = Command::new(env!("CARGO_BIN_EXE_datafusion-acp"))
+        .arg("--agent")
+        .arg(env!("CARGO_BIN_EXE_mock-acp-agent"))
+        .args(["--max-repairs", "1", "--confirm-mutations", "eval"])
+        .arg(data_path("eval/mutation.toml"))
+        .env("MOCK_ACP_SCRIPT", data_path("agent/eval_mutation.json"))
+        .env_remove("ACP_SAFE_MODE")
+        .env_remove("ACP_TIMEOUT")
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    let stderr = stderr(&output);
+    assert!(
+        stderr.contains("eval cannot ask before mutations"),
+        "{stderr}"
+    );
+    assert!(stderr.contains("[FAIL] drop_products"), "{stderr}");
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["passed"], 0);
+    let error = report["results"][0]["error"].as_str().unwrap();
+    assert!(error.contains("DROP TABLE is not allowed"), "{error}");
+}
+
+#[test]
+fn test_eval_reports_accuracy() {
+    let output = Command::new(env!("CARGO_BIN_EXE_datafusion-acp"))
+        .arg("--agent")
+        .arg(env!("CARGO_BIN_EXE_mock-acp-agent"))
+        .arg("eval")
+        .arg(data_path("eval/suite.toml"))
+        .arg("--min-accuracy")
+        .arg("0.8")
+        .env("MOCK_ACP_SCRIPT", data_path("agent/eval.json"))
+        .env_remove("ACP_SAFE_MODE")
+        .env_remove("ACP_TIMEOUT")
+        .output()
+        .unwrap();
+    // Three of the four cases pass, below the required accuracy.
+    assert!(!output.status.success());
+    let stderr = stderr(&output);
+    assert!(stderr.contains("[PASS] count_per_category"), "{stderr}");
+    assert!(stderr.contains("[FAIL] cheapest"), "{stderr}");
+    assert!(stderr.contains("below --min-accuracy 0.8"), "{stderr}");
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["cases"], 4);
+    assert_eq!(report["passed"], 3);
+    assert_eq!(report["accuracy"], 0.75);
+    let results = report["results"].as_array().unwrap();
+    assert_eq!(results[0]["name"], "most_expensive");
+    assert_eq!(results[0]["tool_calls"], 2);
+    assert_eq!(results[0]["run_sql_calls"], 1);
+    assert_eq!(
+        results[3]["sql"],
+        "SELECT name FROM products ORDER BY price LIMIT 1 OFFSET 1"
+    );
+    assert!(results[3]["error"]
+        .as_str()
+        .unwrap()
+        .contains(r#"Expected row ["Doohickey"] is not in the result"#));
+}
+
+#[test]
+fn test_streaming_output_formats() {
+    let output = run_cli("one_shot.json", &["-o", "ndjson", "most expensive"], None);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "{\"name\":\"Gadget\"}\n");
+
+    // stdout is a pipe here, so the Arrow stream is written.
+    let output = run_cli("one_shot.json", &["-o", "arrow", "most expensive"], None);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let reader =
+        datafusion::arrow::ipc::reader::StreamReader::try_new(&output.stdout[..], None).unwrap();
+    let batches = reader.collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(batches.len(), 1);
+    assert_eq!(batches[0].schema().field(0).name(), "name");
+    assert_eq!(batches[0].num_rows(), 1);
+}
+
+#[test]
+fn test_session_is_saved_and_restored() {
+    let dir =
+        std::env::temp_dir().join(format!("datafusion_acp_session_cli_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let session = dir.join("session.json");
+    let session = session.to_str().unwrap();
+
+    let output = run_cli(
+        "session.json",
+        &["-o", "csv", "--no-safe-mode", "--session", session],
+        Some(
+            "CLAUDE cheapest product\n\
+             CREATE TABLE cheap AS SELECT id, name FROM products WHERE price < 10;\n\
+             CREATE VIEW cheap_names AS SELECT name FROM cheap;\n",
+        ),
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(dir.join("session.tables.1").is_dir());
+
+    // The follow-up is answered from the restored conversation.
+    let output = run_cli(
+        "session.json",
+        &["-o", "csv", "--session", session],
+        Some("\\history\nSELECT name FROM cheap_names ORDER BY name;\nCLAUDE and its price?\n"),
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "1. cheapest product\nSELECT name FROM products ORDER BY price LIMIT 1\n\n\
+         name\nDoohickey\nWidget\n\
+         name,price\nDoohickey,4.99\n"
+    );
+    std::fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn test_repl_stop_on_error_skips_the_rest_of_the_line() {
+    let output = run_cli(
+        "one_shot.json",
+        &["-o", "csv", "--no-safe-mode", "--stop-on-error"],
+        Some(
+            "CREATE TABLE kept AS SELECT 1 AS n; SELECT * FROM missing_table; SELECT 2 AS skipped;\n\
+             SELECT n FROM kept;\n",
+        ),
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "n\n1\n");
+    assert!(
+        stderr(&output).contains("missing_table"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn test_saved_template_reruns_without_agent() {
+    let dir = std::env::temp_dir().join(format!("datafusion_acp_templates_{}", std::process::id()));
+    std::fs::remove_dir_all(&dir).ok();
+    let dir_arg = dir.to_str().unwrap();
+
+    let output = run_cli(
+        "template.json",
+        &[
+            "-o",
+            "csv",
+            "--templates-dir",
+            dir_arg,
+            "--save-template",
+            "by_category",
+            "hardware products",
+        ],
+        None,
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "name\nDoohickey\nWidget\n");
+    assert!(
+        stderr(&output).contains("Saved template 'by_category' with parameters category."),
+        "{}",
+        stderr(&output)
+    );
+
+    // The agent script has no turns left: any prompt would fail.
+    let output = run_cli(
+        "no_final_query.json",
+        &[
+            "-o",
+            "csv",
+            "--templates-dir",
+            dir_arg,
+            "run",
+            "by_category",
+            "--param",
+            "category=electronics",
+        ],
+        None,
+    );
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "name\nGadget\n");
+
+    let output = run_cli(
+        "no_final_query.json",
+        &["--templates-dir", dir_arg, "run"],
+        None,
+    );
+    assert_eq!(
+        stdout(&output),
+        "by_category(category: string = \"hardware\")\n    hardware products\n"
+    );
+
+    let output = run_cli(
+        "no_final_query.json",
+        &[
+            "--templates-dir",
+            dir_arg,
+            "run",
+            "by_category",
+            "-p",
+            "region=EU",
+        ],
+        None,
+    );
+    assert!(!output.status.success());
+    assert!(
+        stderr(&output).contains("Unknown parameter 'region'. The template takes: category"),
+        "{}",
+        stderr(&output)
+    );
+    std::fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,388 @@
+//! Scripted ACP agent for the end-to-end tests.
+//!
+//! Speaks ACP over stdio like a real agent, but instead of asking a model it
+//! replays the JSON script named by `MOCK_ACP_SCRIPT`. The script holds one
+//! list of steps per prompt turn:
+//!
+//! ```json
+//! {"turns": [[
+//!     {"message": "Looking at the catalog"},
+//!     {"run_sql": {"sql": "SELECT COUNT(*) AS n FROM products", "expect": "\"n\":3"}},
+//!     {"final_query": {"sql": "SELECT * FROM products", "summary": "All products"}}
+//! ]]}
+//! ```
+//!
+//! Any step whose expectation fails ends the prompt with an error, so the
+//! client under test sees a failed turn.
+//!
+//! Once `turns` runs out, prompts are answered from `by_prompt` instead: the
+//! first turn there whose `expect_prompt` steps all match the prompt is
+//! replayed. Evals use this to answer each question in a fresh session.
+//!
+//! With `MOCK_ACP_LISTEN=host:port` it instead serves one connection on a TCP
+//! socket, printing the bound address on stdout once it is listening.
+
+use std::cell::{OnceCell, RefCell};
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::Duration;
+
+use agent_client_protocol::{self as acp, Client as _};
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::sync::Notify;
+use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+
+const SESSION_ID: &str = "mock-session";
+
+#[derive(Debug, Deserialize)]
+struct Script {
+    #[serde(default)]
+    turns: Vec<Vec<Step>>,
+    #[serde(default)]
+    by_prompt: Vec<Vec<Step>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Step {
+    /// Stream an agent message chunk.
+    Message(String),
+    /// Fail unless the prompt text contains this string.
+    ExpectPrompt(String),
+    /// Call the `run_sql` MCP tool, optionally checking its response.
+    RunSql {
+        sql: String,
+        #[serde(default)]
+        expect: Option<String>,
+    },
+    /// Announce and call the `final_query` MCP tool.
+    FinalQuery {
+        sql: String,
+        #[serde(default)]
+        summary: Option<String>,
+        #[serde(default)]
+        datasources: Option<String>,
+        /// Template parameters, passed through as given.
+        #[serde(default)]
+        parameters: Option<Value>,
+    },
+    /// Ask the client for permission and check which option it picked.
+    RequestPermission {
+        options: Vec<acp::PermissionOptionKind>,
+        expect: acp::PermissionOptionKind,
+    },
+    /// Wait, or stop early when the client cancels the turn.
+    SleepMs(u64),
+    /// Report this token usage in the `_meta` of the prompt response.
+    Usage(Value),
+}
+
+struct MockAgent {
+    turns: RefCell<VecDeque<Vec<Step>>>,
+    by_prompt: Vec<Vec<Step>>,
+    mcp_url: RefCell<Option<String>>,
+    conn: OnceCell<acp::AgentSideConnection>,
+    cancelled: Notify,
+    http: reqwest::Client,
+}
+
+/// Outcome of a replayed turn that did not fail.
+enum TurnEnd {
+    Done(Option<Value>),
+    Cancelled,
+}
+
+impl MockAgent {
+    fn conn(&self) -> Result<&acp::AgentSideConnection> {
+        self.conn
+            .get()
+            .ok_or_else(|| anyhow!("connection not ready"))
+    }
+
+    async fn run_turn(&self, prompt: &str, steps: Vec<Step>) -> Result<TurnEnd> {
+        let mut usage = None;
+        for (index, step) in steps.into_iter().enumerate() {
+            let tool_call_id = format!("tool-{index}");
+            match step {
+                Step::Message(text) => {
+                    self.notify(acp::SessionUpdate::AgentMessageChunk(
+                        acp::ContentChunk::new(text.into()),
+                    ))
+                    .await?;
+                }
+                Step::ExpectPrompt(expected) => {
+                    if !prompt.contains(&expected) {
+                        bail!("prompt does not contain {expected:?}: {prompt}");
+                    }
+                }
+                Step::RunSql { sql, expect } => {
+                    let args = json!({ "sql": sql });
+                    self.announce_tool_call(&tool_call_id, "run_sql", &args)
+                        .await?;
+                    let text = self.call_tool("run_sql", args).await?;
+                    self.complete_tool_call(&tool_call_id).await?;
+                    if let Some(expected) = expect {
+                        if !text.contains(&expected) {
+                            bail!("run_sql response does not contain {expected:?}: {text}");
+                        }
+                    }
+                }
+                Step::FinalQuery {
+                    sql,
+                    summary,
+                    datasources,
+                    parameters,
+                } => {
+                    let mut args = json!({
+                        "sql": sql,
+                        "summary": summary,
+                        "datasources": datasources,
+                    });
+                    if let Some(parameters) = parameters {
+                        args["parameters"] = parameters;
+                    }
+                    self.announce_tool_call(&tool_call_id, "final_query", &args)
+                        .await?;
+                    self.call_tool("final_query", args).await?;
+                    self.complete_tool_call(&tool_call_id).await?;
+                }
+                Step::RequestPermission { options, expect } => {
+                    let options = options
+                        .into_iter()
+                        .map(|kind| {
+                            let id = serde_json::to_value(kind)?
+                                .as_str()
+                                .unwrap_or_default()
+                                .to_string();
+                            Ok(acp::PermissionOption::new(id.clone().into(), id, kind))
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    let tool_call = acp::ToolCallUpdate::new(
+                        tool_call_id,
+                        acp::ToolCallUpdateFields::new().title("mcp__datafusion__run_sql"),
+                    );
+                    let response = self
+                        .conn()?
+                        .request_permission(acp::RequestPermissionRequest::new(
+                            SESSION_ID.into(),
+                            tool_call,
+                            options.clone(),
+                        ))
+                        .await?;
+                    let picked = match response.outcome {
+                        acp::RequestPermissionOutcome::Selected(selected) => options
+                            .iter()
+                            .find(|o| o.option_id == selected.option_id)
+                            .map(|o| o.kind),
+                        _ => None,
+                    };
+                    if picked != Some(expect) {
+                        bail!("expected the client to pick {expect:?}, got {picked:?}");
+                    }
+                }
+                Step::SleepMs(ms) => {
+                    tokio::select! {
+                        _ = tokio::time::sleep(Duration::from_millis(ms)) => {}
+                        _ = self.cancelled.notified() => return Ok(TurnEnd::Cancelled),
+                    }
+                }
+                Step::Usage(reported) => usage = Some(reported),
+            }
+        }
+        Ok(TurnEnd::Done(usage))
+    }
+
+    async fn announce_tool_call(&self, id: &str, tool: &str, args: &Value) -> Result<()> {
+        self.notify(acp::SessionUpdate::ToolCall(
+            acp::ToolCall::new(id.to_string(), format!("mcp__datafusion__{tool}"))
+                .status(acp::ToolCallStatus::InProgress)
+                .raw_input(args.clone()),
+        ))
+        .await
+    }
+
+    async fn complete_tool_call(&self, id: &str) -> Result<()> {
+        self.notify(acp::SessionUpdate::ToolCallUpdate(
+            acp::ToolCallUpdate::new(
+                id.to_string(),
+                acp::ToolCallUpdateFields::new().status(acp::ToolCallStatus::Completed),
+            ),
+        ))
+        .await
+    }
+
+    async fn notify(&self, update: acp::SessionUpdate) -> Result<()> {
+        self.conn()?
+            .session_notification(acp::SessionNotification::new(SESSION_ID, update))
+            .await?;
+        Ok(())
+    }
+
+    /// Call an MCP tool on the client's server and return its text content.
+    async fn call_tool(&self, name: &str, arguments: Value) -> Result<String> {
+        let url = self
+            .mcp_url
+            .borrow()
+            .clone()
+            .ok_or_else(|| anyhow!("no HTTP MCP server in new_session"))?;
+        let body = self
+            .http
+            .post(url)
+            .header("accept", "application/json, text/event-stream")
+            .json(&json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "tools/call",
+                "params": { "name": name, "arguments": arguments },
+            }))
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        let data = body
+            .lines()
+            .find_map(|line| line.strip_prefix("data:"))
+            .ok_or_else(|| anyhow!("no SSE data in MCP response: {body}"))?;
+        let response: Value = serde_json::from_str(data.trim())?;
+        if let Some(error) = response.get("error") {
+            bail!("MCP {name} failed: {error}");
+        }
+        response["result"]["content"][0]["text"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| anyhow!("unexpected MCP response: {response}"))
+    }
+}
+
+#[async_trait::async_trait(?Send)]
+impl acp::Agent for MockAgent {
+    async fn initialize(
+        &self,
+        args: acp::InitializeRequest,
+    ) -> acp::Result<acp::InitializeResponse> {
+        Ok(acp::InitializeResponse::new(args.protocol_version))
+    }
+
+    async fn authenticate(
+        &self,
+        _args: acp::AuthenticateRequest,
+    ) -> acp::Result<acp::AuthenticateResponse> {
+        Ok(acp::AuthenticateResponse::default())
+    }
+
+    async fn new_session(
+        &self,
+        args: acp::NewSessionRequest,
+    ) -> acp::Result<acp::NewSessionResponse> {
+        let url = args
+            .mcp_servers
+            .into_iter()
+            .find_map(|server| match server {
+                acp::McpServer::Http(http) => Some(http.url),
+                _ => None,
+            });
+        *self.mcp_url.borrow_mut() = url;
+        Ok(acp::NewSessionResponse::new(SESSION_ID))
+    }
+
+    async fn prompt(&self, args: acp::PromptRequest) -> acp::Result<acp::PromptResponse> {
+        let prompt = args
+            .prompt
+            .iter()
+            .filter_map(|block| match block {
+                acp::ContentBlock::Text(text) => Some(text.text.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let next = self.turns.borrow_mut().pop_front();
+        let steps = next
+            .or_else(|| {
+                self.by_prompt
+                    .iter()
+                    .find(|steps| {
+                        steps.iter().all(|step| match step {
+                            Step::ExpectPrompt(expected) => prompt.contains(expected),
+                            _ => true,
+                        })
+                    })
+                    .cloned()
+            })
+            .ok_or_else(|| acp::Error::internal_error().data("script has no turns left"))?;
+
+        match self.run_turn(&prompt, steps).await {
+            Ok(TurnEnd::Done(usage)) => {
+                let mut response = acp::PromptResponse::new(acp::StopReason::EndTurn);
+                if let Some(usage) = usage {
+                    let meta = json!({ "usage": usage });
+                    response = response.meta(meta.as_object().cloned().unwrap_or_default());
+                }
+                Ok(response)
+            }
+            Ok(TurnEnd::Cancelled) => Ok(acp::PromptResponse::new(acp::StopReason::Cancelled)),
+            Err(e) => Err(acp::Error::internal_error().data(format!("{e:#}"))),
+        }
+    }
+
+    async fn cancel(&self, _args: acp::CancelNotification) -> acp::Result<()> {
+        self.cancelled.notify_waiters();
+        Ok(())
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> Result<()> {
+    let path = std::env::var("MOCK_ACP_SCRIPT").context("MOCK_ACP_SCRIPT is not set")?;
+    let script: Script = serde_json::from_str(
+        &std::fs::read_to_string(&path).with_context(|| format!("Failed to read '{path}'"))?,
+    )
+    .with_context(|| format!("Invalid mock agent script '{path}'"))?;
+
+    let agent = Rc::new(MockAgent {
+        turns: RefCell::new(script.turns.into()),
+        by_prompt: script.by_prompt,
+        mcp_url: RefCell::new(None),
+        conn: OnceCell::new(),
+        cancelled: Notify::new(),
+        http: reqwest::Client::new(),
+    });
+
+    let local = tokio::task::LocalSet::new();
+    match std::env::var("MOCK_ACP_LISTEN") {
+        Ok(addr) => {
+            let listener = tokio::net::TcpListener::bind(&addr).await?;
+            println!("{}", listener.local_addr()?);
+            let (stream, _) = listener.accept().await?;
+            let (incoming, outgoing) = stream.into_split();
+            local.run_until(serve(agent, outgoing, incoming)).await?;
+        }
+        Err(_) => {
+            local
+                .run_until(serve(agent, tokio::io::stdout(), tokio::io::stdin()))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+async fn serve(
+    agent: Rc<MockAgent>,
+    outgoing: impl tokio::io::AsyncWrite + Unpin + 'static,
+    incoming: impl tokio::io::AsyncRead + Unpin + 'static,
+) -> Result<()> {
+    let (conn, handle_io) = acp::AgentSideConnection::new(
+        agent.clone(),
+        outgoing.compat_write(),
+        incoming.compat(),
+        |fut| {
+            tokio::task::spawn_local(fut);
+        },
+    );
+    let _ = agent.conn.set(conn);
+    handle_io.await?;
+    Ok(())
+}
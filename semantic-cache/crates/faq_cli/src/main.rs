@@ -1,10 +1,11 @@
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
 use faq_core::{
-    build_visualization, cluster_embeddings, decide, downsample_indices, evaluate_cases,
-    load_entries_jsonl, read_cluster_input, render_html_scatter, save_entries_jsonl,
-    CandleEmbeddingProvider, CandleEvaluationRun, Decision, EmbeddingProvider, EvalCase, FaqEntry,
-    HashEmbeddingProvider, MiniLmEmbeddingProvider, OrchestrationStatus, Qwen3EmbeddingProvider,
+    build_visualization, cluster_embeddings, decide_with, downsample_indices, evaluate_cases_with,
+    hnsw_path, load_entries_jsonl, read_cluster_input, render_html_scatter, save_entries_jsonl,
+    BruteForceIndex, CandleEmbeddingProvider, CandleEvaluationRun, Decision, EmbeddingProvider,
+    EvalCase, FaqEntry, HashEmbeddingProvider, HnswIndex, HnswParams, MiniLmEmbeddingProvider,
+    OrchestrationStatus, Qwen3EmbeddingProvider, VectorIndex, DEFAULT_EF_SEARCH,
     DEFAULT_EMBEDDING_DIM, DEFAULT_REQUIRED_PASS_RATE, DEFAULT_THRESHOLD,
 };
 use std::fs::File;
@@ -34,6 +35,9 @@ enum Commands {
         input: PathBuf,
         #[arg(long)]
         output: PathBuf,
+        /// Approximate nearest-neighbour index to write next to the output.
+        #[arg(long, value_enum, default_value_t = AnnKind::Hnsw)]
+        ann: AnnKind,
     },
     Query {
         #[arg(long)]
@@ -42,6 +46,8 @@ enum Commands {
         question: String,
         #[arg(long, default_value_t = DEFAULT_THRESHOLD)]
         threshold: f32,
+        #[command(flatten)]
+        search: QuerySearchArgs,
     },
     Eval {
         #[arg(long)]
@@ -52,6 +58,8 @@ enum Commands {
         threshold: f32,
         #[arg(long, default_value_t = DEFAULT_REQUIRED_PASS_RATE)]
         min_pass_rate: f32,
+        #[command(flatten)]
+        search: SearchArgs,
     },
     /// Cluster questions from a SQuAD v2 parquet file to identify potential FAQs.
     Cluster {
@@ -82,6 +90,60 @@ enum Commands {
     },
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum AnnKind {
+    /// HNSW graph, saved as <output stem>.hnsw.json.
+    Hnsw,
+    /// No approximate index; queries score every entry.
+    None,
+}
+
+// Search options of `query`. Loading the HNSW graph costs more than scoring
+// every entry once, so a single question uses exact search unless asked.
+#[derive(Debug, clap::Args)]
+struct QuerySearchArgs {
+    /// Search the HNSW index next to the index instead of scoring every entry.
+    #[arg(long)]
+    hnsw: bool,
+    /// HNSW candidate list size; higher is slower and closer to exact.
+    #[arg(long, default_value_t = DEFAULT_EF_SEARCH, requires = "hnsw")]
+    ef_search: usize,
+}
+
+#[derive(Debug, clap::Args)]
+struct SearchArgs {
+    /// Score every entry even when an HNSW index sits next to the index.
+    #[arg(long)]
+    exact: bool,
+    /// HNSW candidate list size; higher is slower and closer to exact.
+    #[arg(long, default_value_t = DEFAULT_EF_SEARCH)]
+    ef_search: usize,
+}
+
+/// With `hnsw`, the HNSW index saved next to `index` when there is one and
+/// it matches `entries`; otherwise exact search.
+fn open_vector_index<'a>(
+    index: &Path,
+    entries: &'a [FaqEntry],
+    hnsw: bool,
+    ef_search: usize,
+) -> Result<(Box<dyn VectorIndex + 'a>, &'static str)> {
+    let path = hnsw_path(index);
+    if !hnsw || !path.exists() {
+        return Ok((Box::new(BruteForceIndex::new(entries)), "exact"));
+    }
+    match HnswIndex::load(&path, entries) {
+        Ok(mut hnsw) => {
+            hnsw.set_ef_search(ef_search);
+            Ok((Box::new(hnsw), "hnsw"))
+        }
+        Err(err) => {
+            eprintln!("warning: {err:#}; falling back to exact search");
+            Ok((Box::new(BruteForceIndex::new(entries)), "exact"))
+        }
+    }
+}
+
 #[derive(Debug, serde::Deserialize)]
 struct RawFaq {
     id: String,
@@ -190,7 +252,7 @@ fn run() -> Result<()> {
         .unwrap_or_else(|| "hash".to_string());
 
     match &cli.command {
-        Commands::BuildIndex { input, output } => {
+        Commands::BuildIndex { input, output, ann } => {
             let embedder = make_embedder(&cli)?;
             let raw = read_raw_faq_jsonl(input)?;
             let now = chrono::Utc::now();
@@ -221,20 +283,45 @@ fn run() -> Result<()> {
                 entries.len(),
                 output.display()
             );
+
+            let ann_path = hnsw_path(output);
+            match ann {
+                AnnKind::Hnsw => {
+                    let start = std::time::Instant::now();
+                    let hnsw = HnswIndex::build(&entries, HnswParams::default())?;
+                    hnsw.save(&ann_path)?;
+                    println!(
+                        "ann=hnsw build_time={:.1}ms ann_output={}",
+                        start.elapsed().as_secs_f64() * 1000.0,
+                        ann_path.display()
+                    );
+                }
+                AnnKind::None => {
+                    // A graph left from an earlier build would no longer match.
+                    if ann_path.exists() {
+                        std::fs::remove_file(&ann_path)
+                            .with_context(|| format!("remove {}", ann_path.display()))?;
+                    }
+                }
+            }
         }
         Commands::Query {
             index,
             question,
             threshold,
+            search,
         } => {
             let embedder = make_embedder(&cli)?;
             let entries = load_entries_jsonl(index)?;
+            let (vector_index, search_kind) =
+                open_vector_index(index, &entries, search.hnsw, search.ef_search)?;
             let q = embedder.embed(question)?;
-            let result = decide(&q, &entries, *threshold);
+            let result = decide_with(vector_index.as_ref(), &q, &entries, *threshold);
 
             println!(
-                "model={} decision={:?} score={:.4} entry_id={}",
+                "model={} search={} decision={:?} score={:.4} entry_id={}",
                 model_name,
+                search_kind,
                 result.decision,
                 result.score,
                 result.entry_id.as_deref().unwrap_or("null")
@@ -248,6 +335,7 @@ fn run() -> Result<()> {
             cases,
             threshold,
             min_pass_rate,
+            search,
         } => {
             let run_id = format!("eval-{}", chrono::Utc::now().timestamp_millis());
             let mut run = CandleEvaluationRun::start(
@@ -284,13 +372,22 @@ fn run() -> Result<()> {
             let embedder = make_embedder(&cli)?;
             let entries = load_entries_jsonl(index)?;
             let cases = read_eval_cases_json(cases)?;
-            let summary = evaluate_cases(&embedder, &entries, &cases, *threshold)?;
+            let (vector_index, search_kind) =
+                open_vector_index(index, &entries, !search.exact, search.ef_search)?;
+            let summary = evaluate_cases_with(
+                &embedder,
+                vector_index.as_ref(),
+                &entries,
+                &cases,
+                *threshold,
+            )?;
             run.on_eval_completed(&summary, *min_pass_rate);
 
             println!(
-                "run_id={} model={} status={:?} total={} passed={} failed={} pass_rate={:.4} required={:.4} meets_threshold={}",
+                "run_id={} model={} search={} status={:?} total={} passed={} failed={} pass_rate={:.4} required={:.4} meets_threshold={}",
                 run.run_id,
                 model_name,
+                search_kind,
                 run.status,
                 summary.total,
                 summary.passed,
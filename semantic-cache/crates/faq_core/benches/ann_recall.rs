@@ -0,0 +1,107 @@
+//! Recall and query latency of the HNSW index against exact search.
+//!
+//! ```text
+//! cargo bench -p faq_core --bench ann_recall
+//! ANN_BENCH_SIZES=10000,100000 ANN_BENCH_DIM=768 cargo bench -p faq_core --bench ann_recall
+//! ```
+//!
+//! Entries are synthetic embeddings scattered around topic centres, which is
+//! closer to real FAQ embeddings than uniform noise and harder for the graph.
+//! `load_ms` is what every `faq_cli query` pays before searching: parsing the
+//! saved graph and checking its fingerprint against the entries.
+
+use std::time::Instant;
+
+use faq_core::{
+    recall_at_k, BruteForceIndex, FaqEntry, HnswIndex, HnswParams, SplitMix64, VectorIndex,
+};
+
+const QUERIES: usize = 200;
+const TOPICS: usize = 500;
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+fn main() {
+    let sizes: Vec<usize> = std::env::var("ANN_BENCH_SIZES")
+        .unwrap_or_else(|_| "10000,100000".to_string())
+        .split(',')
+        .filter_map(|s| s.trim().parse().ok())
+        .collect();
+    let dim = env_or("ANN_BENCH_DIM", 384);
+    let ef_values = [16, 32, 64, 128];
+
+    println!(
+        "{:>8} {:>5} {:>9} {:>9} {:>10} {:>5} {:>10} {:>8} {:>9} {:>10}",
+        "entries",
+        "dim",
+        "build_s",
+        "load_ms",
+        "exact_ms",
+        "ef",
+        "hnsw_ms",
+        "speedup",
+        "recall@1",
+        "recall@10"
+    );
+
+    for &n in &sizes {
+        let mut rng = SplitMix64::new(n as u64);
+        let centres: Vec<Vec<f32>> = (0..TOPICS)
+            .map(|_| (0..dim).map(|_| rng.next_gauss()).collect())
+            .collect();
+        let point = |rng: &mut SplitMix64| -> Vec<f32> {
+            let centre = &centres[(rng.next_f64() * TOPICS as f64) as usize % TOPICS];
+            centre.iter().map(|c| c + rng.next_gauss()).collect()
+        };
+        let entries: Vec<FaqEntry> = (0..n)
+            .map(|i| FaqEntry::new(format!("faq-{i}"), "", "", point(&mut rng)))
+            .collect();
+        let queries: Vec<Vec<f32>> = (0..QUERIES).map(|_| point(&mut rng)).collect();
+
+        let start = Instant::now();
+        let mut hnsw = HnswIndex::build(&entries, HnswParams::default()).expect("build");
+        let build_s = start.elapsed().as_secs_f64();
+
+        let path =
+            std::env::temp_dir().join(format!("ann_recall_{}.hnsw.json", std::process::id()));
+        hnsw.save(&path).expect("save");
+        let start = Instant::now();
+        HnswIndex::load(&path, &entries).expect("load");
+        let load_ms = start.elapsed().as_secs_f64() * 1000.0;
+        let _ = std::fs::remove_file(&path);
+
+        let exact = BruteForceIndex::new(&entries);
+        let exact_ms = per_query_ms(&exact, &queries);
+
+        for ef in ef_values {
+            hnsw.set_ef_search(ef);
+            let hnsw_ms = per_query_ms(&hnsw, &queries);
+            println!(
+                "{:>8} {:>5} {:>9.1} {:>9.1} {:>10.3} {:>5} {:>10.3} {:>7.1}x {:>9.3} {:>10.3}",
+                n,
+                dim,
+                build_s,
+                load_ms,
+                exact_ms,
+                ef,
+                hnsw_ms,
+                exact_ms / hnsw_ms,
+                recall_at_k(&exact, &hnsw, &queries, 1),
+                recall_at_k(&exact, &hnsw, &queries, 10),
+            );
+        }
+    }
+}
+
+fn per_query_ms(index: &dyn VectorIndex, queries: &[Vec<f32>]) -> f64 {
+    let start = Instant::now();
+    for query in queries {
+        std::hint::black_box(index.search(query, 10));
+    }
+    start.elapsed().as_secs_f64() * 1000.0 / queries.len() as f64
+}
@@ -1,5 +1,6 @@
 use crate::model::{Decision, FaqEntry};
-use crate::retrieval::decide;
+use crate::retrieval::decide_with;
+use crate::vector_index::{BruteForceIndex, VectorIndex};
 use serde::{Deserialize, Serialize};
 use std::time::Instant;
 
@@ -68,6 +69,27 @@ pub fn evaluate_cases<E>(
     cases: &[EvalCase],
     threshold: f32,
 ) -> anyhow::Result<EvalSummary>
+where
+    E: crate::embed::EmbeddingProvider,
+{
+    evaluate_cases_with(
+        embedder,
+        &BruteForceIndex::new(entries),
+        entries,
+        cases,
+        threshold,
+    )
+}
+
+/// Like [`evaluate_cases`], but retrieving with `index`, which must have
+/// been built from `entries`.
+pub fn evaluate_cases_with<E>(
+    embedder: &E,
+    index: &dyn VectorIndex,
+    entries: &[FaqEntry],
+    cases: &[EvalCase],
+    threshold: f32,
+) -> anyhow::Result<EvalSummary>
 where
     E: crate::embed::EmbeddingProvider,
 {
@@ -76,7 +98,7 @@ where
     for case in cases {
         let start = Instant::now();
         let query_embedding = embedder.embed(&case.question)?;
-        let result = decide(&query_embedding, entries, threshold);
+        let result = decide_with(index, &query_embedding, entries, threshold);
         let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
 
         let passed = CaseExpectation::matches(
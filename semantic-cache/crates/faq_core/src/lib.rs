@@ -8,6 +8,7 @@ pub mod orchestration;
 pub mod qwen3_embed;
 pub mod retrieval;
 pub mod storage;
+pub mod vector_index;
 
 pub use candle_embed::CandleEmbeddingProvider;
 pub use cluster::{
@@ -16,7 +17,9 @@ pub use cluster::{
     ClusterMeta, ClusterSummary, ClusterVisualization, ProjectedPoint, QuestionCluster, SquadRow,
 };
 pub use embed::{EmbeddingProvider, HashEmbeddingProvider};
-pub use eval::{evaluate_cases, CaseExpectation, EvalCase, EvalOutcome, EvalSummary};
+pub use eval::{
+    evaluate_cases, evaluate_cases_with, CaseExpectation, EvalCase, EvalOutcome, EvalSummary,
+};
 pub use minilm_embed::MiniLmEmbeddingProvider;
 pub use model::{Decision, FaqEntry, RetrievalMatch};
 pub use orchestration::{
@@ -24,5 +27,9 @@ pub use orchestration::{
     DEFAULT_MODEL_PATH, DEFAULT_MODEL_REVISION, DEFAULT_REQUIRED_PASS_RATE, DEFAULT_THRESHOLD,
 };
 pub use qwen3_embed::Qwen3EmbeddingProvider;
-pub use retrieval::{cosine_similarity, decide, top_k, top_match};
+pub use retrieval::{cosine_similarity, decide, decide_with, top_k, top_k_with, top_match};
 pub use storage::{load_entries_jsonl, save_entries_jsonl};
+pub use vector_index::{
+    hnsw_path, recall_at_k, BruteForceIndex, HnswIndex, HnswParams, SplitMix64, VectorIndex,
+    DEFAULT_EF_CONSTRUCTION, DEFAULT_EF_SEARCH, DEFAULT_HNSW_M,
+};
@@ -18,6 +18,33 @@ pub struct FaqEntry {
     pub verified: Option<bool>,
 }
 
+impl FaqEntry {
+    /// An entry created now, with no expiry, metadata or provenance.
+    pub fn new(
+        id: impl Into<String>,
+        question: impl Into<String>,
+        answer: impl Into<String>,
+        embedding: Vec<f32>,
+    ) -> Self {
+        let now = Utc::now();
+        Self {
+            id: id.into(),
+            question: question.into(),
+            answer: answer.into(),
+            embedding,
+            created_at: now,
+            updated_at: now,
+            expires_at: None,
+            product: None,
+            locale: None,
+            tags: Vec::new(),
+            version: None,
+            source: None,
+            verified: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Decision {
@@ -1,4 +1,5 @@
 use crate::model::{Decision, FaqEntry, RetrievalMatch};
+use crate::vector_index::{BruteForceIndex, VectorIndex};
 
 pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     if a.is_empty() || b.is_empty() || a.len() != b.len() {
@@ -24,13 +25,22 @@ pub fn top_k<'a>(
     entries: &'a [FaqEntry],
     k: usize,
 ) -> Vec<(&'a FaqEntry, f32)> {
-    let mut scored: Vec<(&FaqEntry, f32)> = entries
-        .iter()
-        .map(|entry| (entry, cosine_similarity(query_embedding, &entry.embedding)))
-        .collect();
+    top_k_with(&BruteForceIndex::new(entries), query_embedding, entries, k)
+}
 
-    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
-    scored.into_iter().take(k).collect()
+/// Like [`top_k`], but searching `index`, which must have been built from
+/// `entries`.
+pub fn top_k_with<'a>(
+    index: &dyn VectorIndex,
+    query_embedding: &[f32],
+    entries: &'a [FaqEntry],
+    k: usize,
+) -> Vec<(&'a FaqEntry, f32)> {
+    index
+        .search(query_embedding, k)
+        .into_iter()
+        .filter_map(|(i, score)| entries.get(i).map(|entry| (entry, score)))
+        .collect()
 }
 
 pub fn top_match<'a>(
@@ -41,7 +51,25 @@ pub fn top_match<'a>(
 }
 
 pub fn decide(query_embedding: &[f32], entries: &[FaqEntry], threshold: f32) -> RetrievalMatch {
-    match top_match(query_embedding, entries) {
+    decide_with(
+        &BruteForceIndex::new(entries),
+        query_embedding,
+        entries,
+        threshold,
+    )
+}
+
+/// Like [`decide`], but finding the best entry with `index`.
+pub fn decide_with(
+    index: &dyn VectorIndex,
+    query_embedding: &[f32],
+    entries: &[FaqEntry],
+    threshold: f32,
+) -> RetrievalMatch {
+    match top_k_with(index, query_embedding, entries, 1)
+        .into_iter()
+        .next()
+    {
         Some((entry, score)) if score >= threshold => RetrievalMatch {
             entry_id: Some(entry.id.clone()),
             answer: Some(entry.answer.clone()),
@@ -67,24 +95,9 @@ pub fn decide(query_embedding: &[f32], entries: &[FaqEntry], threshold: f32) ->
 mod tests {
     use super::*;
     use crate::model::FaqEntry;
-    use chrono::Utc;
 
     fn mk_entry(id: &str, emb: Vec<f32>) -> FaqEntry {
-        FaqEntry {
-            id: id.to_string(),
-            question: String::new(),
-            answer: format!("answer-{id}"),
-            embedding: emb,
-            created_at: Utc::now(),
-            updated_at: Utc::now(),
-            expires_at: None,
-            product: None,
-            locale: None,
-            tags: Vec::new(),
-            version: None,
-            source: None,
-            verified: None,
-        }
+        FaqEntry::new(id, "", format!("answer-{id}"), emb)
     }
 
     #[test]
@@ -0,0 +1,623 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::model::FaqEntry;
+use crate::retrieval::cosine_similarity;
+
+pub const DEFAULT_HNSW_M: usize = 16;
+pub const DEFAULT_EF_CONSTRUCTION: usize = 200;
+pub const DEFAULT_EF_SEARCH: usize = 64;
+
+/// Nearest-neighbour search over the embeddings of a list of FAQ entries.
+///
+/// Results are `(position in the entry list, cosine similarity)`, best first.
+pub trait VectorIndex {
+    fn search(&self, query: &[f32], k: usize) -> Vec<(usize, f32)>;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Exact search: scores every entry. The fallback when no approximate index
+/// is available, and the reference its recall is measured against.
+pub struct BruteForceIndex<'a> {
+    entries: &'a [FaqEntry],
+}
+
+impl<'a> BruteForceIndex<'a> {
+    pub fn new(entries: &'a [FaqEntry]) -> Self {
+        Self { entries }
+    }
+}
+
+impl VectorIndex for BruteForceIndex<'_> {
+    fn search(&self, query: &[f32], k: usize) -> Vec<(usize, f32)> {
+        let mut scored: Vec<(usize, f32)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (i, cosine_similarity(query, &entry.embedding)))
+            .collect();
+
+        // Best first; ties keep entry order.
+        let by_score = |a: &(usize, f32), b: &(usize, f32)| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0));
+        if k == 0 {
+            return Vec::new();
+        }
+        if k < scored.len() {
+            scored.select_nth_unstable_by(k - 1, by_score);
+            scored.truncate(k);
+        }
+        scored.sort_by(by_score);
+        scored
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HnswParams {
+    /// Links a node gets when inserted; nodes keep up to this many on the upper
+    /// layers and twice as many on layer 0.
+    pub m: usize,
+    /// Candidate list size while inserting. Higher builds slower and better.
+    pub ef_construction: usize,
+    /// Candidate list size while searching. Higher is slower and more exact.
+    pub ef_search: usize,
+    /// Seed for the layer assignment, so builds are reproducible.
+    pub seed: u64,
+}
+
+impl Default for HnswParams {
+    fn default() -> Self {
+        Self {
+            m: DEFAULT_HNSW_M,
+            ef_construction: DEFAULT_EF_CONSTRUCTION,
+            ef_search: DEFAULT_EF_SEARCH,
+            seed: 0x5eed,
+        }
+    }
+}
+
+/// Approximate search with a Hierarchical Navigable Small World graph
+/// (Malkov & Yashunin, 2016).
+///
+/// Only the graph is persisted; the vectors come from the entries it was
+/// built from, which [`HnswIndex::load`] checks by fingerprint.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HnswIndex {
+    params: HnswParams,
+    dim: usize,
+    fingerprint: u64,
+    entry_point: Option<u32>,
+    max_level: usize,
+    /// `links[node][level]`: the neighbours of `node` on `level`.
+    links: Vec<Vec<Vec<u32>>>,
+    /// Unit-length embeddings, `dim` floats per entry.
+    #[serde(skip)]
+    vectors: Vec<f32>,
+}
+
+/// A similarity-ordered node id for the search heaps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Scored(f32, u32);
+
+impl Eq for Scored {}
+
+impl PartialOrd for Scored {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Scored {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0).then(other.1.cmp(&self.1))
+    }
+}
+
+/// SplitMix64: a small seeded generator, enough for drawing node levels and
+/// for the synthetic embeddings of tests and benchmarks.
+pub struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_f64(&mut self) -> f64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^= z >> 31;
+        // 53 random bits in (0, 1].
+        ((z >> 11) + 1) as f64 / (1u64 << 53) as f64
+    }
+
+    /// A standard normal sample (Box-Muller).
+    pub fn next_gauss(&mut self) -> f32 {
+        let (u, v) = (self.next_f64(), self.next_f64());
+        ((-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()) as f32
+    }
+}
+
+fn normalized(v: &[f32]) -> Vec<f32> {
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        v.to_vec()
+    } else {
+        v.iter().map(|x| x / norm).collect()
+    }
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    // Eight independent sums, so the compiler can vectorise the loop.
+    let mut lanes = [0.0f32; 8];
+    let (a_chunks, b_chunks) = (a.chunks_exact(8), b.chunks_exact(8));
+    let tail: f32 = a_chunks
+        .remainder()
+        .iter()
+        .zip(b_chunks.remainder())
+        .map(|(x, y)| x * y)
+        .sum();
+    for (x, y) in a_chunks.zip(b_chunks) {
+        for i in 0..8 {
+            lanes[i] += x[i] * y[i];
+        }
+    }
+    lanes.iter().sum::<f32>() + tail
+}
+
+/// FNV-1a over the ids and embeddings, to tell whether a saved graph still
+/// belongs to an entry list.
+fn fingerprint(entries: &[FaqEntry]) -> u64 {
+    let mut h: u64 = 1469598103934665603;
+    let mut feed = |bytes: &[u8]| {
+        for b in bytes {
+            h ^= *b as u64;
+            h = h.wrapping_mul(1099511628211);
+        }
+    };
+    for entry in entries {
+        feed(entry.id.as_bytes());
+        feed(&[0]);
+        for x in &entry.embedding {
+            feed(&x.to_le_bytes());
+        }
+    }
+    h
+}
+
+/// Where the HNSW graph of a JSONL index is kept: `index.hnsw.json` next to
+/// `index.jsonl`.
+pub fn hnsw_path(index: &Path) -> PathBuf {
+    index.with_extension("hnsw.json")
+}
+
+impl HnswIndex {
+    pub fn build(entries: &[FaqEntry], params: HnswParams) -> Result<Self> {
+        if params.m < 2 {
+            bail!("HNSW m must be at least 2, got {}", params.m);
+        }
+        let dim = entries.first().map_or(0, |e| e.embedding.len());
+        let mut index = Self {
+            params,
+            dim,
+            fingerprint: fingerprint(entries),
+            entry_point: None,
+            max_level: 0,
+            links: Vec::with_capacity(entries.len()),
+            vectors: Vec::new(),
+        };
+        index.attach_vectors(entries)?;
+
+        let level_mult = 1.0 / (params.m as f64).ln();
+        let mut rng = SplitMix64::new(params.seed);
+        for node in 0..entries.len() {
+            let level = (-rng.next_f64().ln() * level_mult).floor() as usize;
+            index.insert(node as u32, level);
+        }
+        Ok(index)
+    }
+
+    /// Load the graph saved at `path` for `entries`. Fails if it was built
+    /// from other entries or embeddings.
+    pub fn load(path: &Path, entries: &[FaqEntry]) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("open {}", path.display()))?;
+        let mut index: Self = serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("parse hnsw index {}", path.display()))?;
+        if index.links.len() != entries.len() || index.fingerprint != fingerprint(entries) {
+            bail!(
+                "{} was built from other entries; rebuild it with build-index",
+                path.display()
+            );
+        }
+        index.check_graph().with_context(|| {
+            format!("{} is corrupt; rebuild it with build-index", path.display())
+        })?;
+        index.attach_vectors(entries)?;
+        Ok(index)
+    }
+
+    /// Check that every id the search follows is in range, so a damaged file
+    /// fails to load instead of panicking mid-query.
+    fn check_graph(&self) -> Result<()> {
+        let n = self.links.len();
+        match self.entry_point {
+            None if n == 0 => {}
+            None => bail!("no entry point for {n} nodes"),
+            Some(entry) if entry as usize >= n => {
+                bail!("entry point {entry} is out of range for {n} nodes")
+            }
+            Some(entry) if self.links[entry as usize].len() <= self.max_level => bail!(
+                "entry point {entry} has {} levels, max level is {}",
+                self.links[entry as usize].len(),
+                self.max_level
+            ),
+            Some(_) => {}
+        }
+        for (node, levels) in self.links.iter().enumerate() {
+            if levels.is_empty() {
+                bail!("node {node} has no levels");
+            }
+            for (level, neighbours) in levels.iter().enumerate() {
+                for &neighbour in neighbours {
+                    match self.links.get(neighbour as usize) {
+                        None => {
+                            bail!("node {node} links to {neighbour}, out of range for {n} nodes")
+                        }
+                        Some(their) if their.len() <= level => bail!(
+                            "node {node} links to {neighbour} on level {level}, \
+                             which {neighbour} does not reach"
+                        ),
+                        Some(_) => {}
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let file = File::create(path).with_context(|| format!("create {}", path.display()))?;
+        serde_json::to_writer(BufWriter::new(file), self).context("serialize hnsw index")
+    }
+
+    pub fn params(&self) -> HnswParams {
+        self.params
+    }
+
+    pub fn set_ef_search(&mut self, ef_search: usize) {
+        self.params.ef_search = ef_search.max(1);
+    }
+
+    fn attach_vectors(&mut self, entries: &[FaqEntry]) -> Result<()> {
+        self.vectors = Vec::with_capacity(entries.len() * self.dim);
+        for entry in entries {
+            if entry.embedding.len() != self.dim {
+                bail!(
+                    "entry {} has a {}-dim embedding, expected {}",
+                    entry.id,
+                    entry.embedding.len(),
+                    self.dim
+                );
+            }
+            self.vectors.extend(normalized(&entry.embedding));
+        }
+        Ok(())
+    }
+
+    fn vector(&self, node: u32) -> &[f32] {
+        let start = node as usize * self.dim;
+        &self.vectors[start..start + self.dim]
+    }
+
+    fn max_links(&self, level: usize) -> usize {
+        if level == 0 {
+            self.params.m * 2
+        } else {
+            self.params.m
+        }
+    }
+
+    fn insert(&mut self, node: u32, level: usize) {
+        self.links.push(vec![Vec::new(); level + 1]);
+        let Some(mut entry) = self.entry_point else {
+            self.entry_point = Some(node);
+            self.max_level = level;
+            return;
+        };
+
+        let query = self.vector(node).to_vec();
+        for l in (level + 1..=self.max_level).rev() {
+            entry = self.greedy_closest(&query, entry, l);
+        }
+        for l in (0..=level.min(self.max_level)).rev() {
+            let candidates = self.search_layer(&query, entry, self.params.ef_construction, l);
+            let neighbours = self.select_neighbours(&candidates, self.params.m);
+            for &neighbour in &neighbours {
+                self.links[neighbour as usize][l].push(node);
+                if self.links[neighbour as usize][l].len() > self.max_links(l) {
+                    self.prune(neighbour, l);
+                }
+            }
+            self.links[node as usize][l] = neighbours;
+            entry = candidates[0].1;
+        }
+        if level > self.max_level {
+            self.max_level = level;
+            self.entry_point = Some(node);
+        }
+    }
+
+    /// Cut the links of `node` on `level` back to the limit, keeping diverse
+    /// neighbours.
+    fn prune(&mut self, node: u32, level: usize) {
+        let base = self.vector(node);
+        let mut candidates: Vec<Scored> = self.links[node as usize][level]
+            .iter()
+            .map(|&n| Scored(dot(base, self.vector(n)), n))
+            .collect();
+        candidates.sort_by(|a, b| b.cmp(a));
+        self.links[node as usize][level] =
+            self.select_neighbours(&candidates, self.max_links(level));
+    }
+
+    /// The neighbour selection heuristic of the HNSW paper: take candidates
+    /// best first, skipping any closer to an already chosen neighbour than to
+    /// the base node, then fill up with the skipped ones.
+    fn select_neighbours(&self, candidates: &[Scored], limit: usize) -> Vec<u32> {
+        let mut chosen: Vec<u32> = Vec::with_capacity(limit);
+        let mut skipped = Vec::new();
+        for &Scored(similarity, candidate) in candidates {
+            if chosen.len() == limit {
+                break;
+            }
+            let v = self.vector(candidate);
+            if chosen.iter().all(|&c| dot(v, self.vector(c)) < similarity) {
+                chosen.push(candidate);
+            } else {
+                skipped.push(candidate);
+            }
+        }
+        let room = limit - chosen.len();
+        chosen.extend(skipped.into_iter().take(room));
+        chosen
+    }
+
+    fn greedy_closest(&self, query: &[f32], mut node: u32, level: usize) -> u32 {
+        let mut best = dot(query, self.vector(node));
+        loop {
+            let mut moved = false;
+            for &neighbour in &self.links[node as usize][level] {
+                let similarity = dot(query, self.vector(neighbour));
+                if similarity > best {
+                    best = similarity;
+                    node = neighbour;
+                    moved = true;
+                }
+            }
+            if !moved {
+                return node;
+            }
+        }
+    }
+
+    /// Best-first search on one layer; the `ef` most similar nodes found,
+    /// best first.
+    fn search_layer(&self, query: &[f32], entry: u32, ef: usize, level: usize) -> Vec<Scored> {
+        let mut visited = vec![false; self.links.len()];
+        visited[entry as usize] = true;
+        let first = Scored(dot(query, self.vector(entry)), entry);
+        let mut candidates = BinaryHeap::from([first]);
+        let mut results = BinaryHeap::from([std::cmp::Reverse(first)]);
+
+        while let Some(Scored(similarity, node)) = candidates.pop() {
+            let worst = results.peek().map_or(f32::MIN, |r| r.0 .0);
+            if similarity < worst && results.len() >= ef {
+                break;
+            }
+            for &neighbour in &self.links[node as usize][level] {
+                if std::mem::replace(&mut visited[neighbour as usize], true) {
+                    continue;
+                }
+                let similarity = dot(query, self.vector(neighbour));
+                let worst = results.peek().map_or(f32::MIN, |r| r.0 .0);
+                if results.len() < ef || similarity > worst {
+                    candidates.push(Scored(similarity, neighbour));
+                    results.push(std::cmp::Reverse(Scored(similarity, neighbour)));
+                    if results.len() > ef {
+                        results.pop();
+                    }
+                }
+            }
+        }
+        let mut found: Vec<Scored> = results.into_iter().map(|r| r.0).collect();
+        found.sort_by(|a, b| b.cmp(a));
+        found
+    }
+}
+
+impl VectorIndex for HnswIndex {
+    /// A query of another dimension than the index matches nothing.
+    fn search(&self, query: &[f32], k: usize) -> Vec<(usize, f32)> {
+        let Some(mut entry) = self.entry_point else {
+            return Vec::new();
+        };
+        if k == 0 || query.len() != self.dim {
+            return Vec::new();
+        }
+        let query = normalized(query);
+        for level in (1..=self.max_level).rev() {
+            entry = self.greedy_closest(&query, entry, level);
+        }
+        self.search_layer(&query, entry, self.params.ef_search.max(k), 0)
+            .into_iter()
+            .take(k)
+            .map(|Scored(similarity, node)| (node as usize, similarity))
+            .collect()
+    }
+
+    fn len(&self) -> usize {
+        self.links.len()
+    }
+}
+
+/// The share of the exact top `k` that `approx` also returns, averaged over
+/// `queries`.
+pub fn recall_at_k(
+    exact: &dyn VectorIndex,
+    approx: &dyn VectorIndex,
+    queries: &[Vec<f32>],
+    k: usize,
+) -> f32 {
+    if queries.is_empty() || k == 0 {
+        return 0.0;
+    }
+    let mut found = 0usize;
+    let mut expected = 0usize;
+    for query in queries {
+        let truth: HashSet<usize> = exact.search(query, k).into_iter().map(|(i, _)| i).collect();
+        expected += truth.len();
+        found += approx
+            .search(query, k)
+            .into_iter()
+            .filter(|(i, _)| truth.contains(i))
+            .count();
+    }
+    if expected == 0 {
+        1.0
+    } else {
+        found as f32 / expected as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mk_entry(id: usize, embedding: Vec<f32>) -> FaqEntry {
+        FaqEntry::new(format!("e{id}"), "", "", embedding)
+    }
+
+    /// `n` points around 20 random centres, like embeddings of related
+    /// questions.
+    fn clustered(n: usize, dim: usize, seed: u64) -> Vec<Vec<f32>> {
+        let mut rng = SplitMix64::new(seed);
+        let centres: Vec<Vec<f32>> = (0..20)
+            .map(|_| (0..dim).map(|_| rng.next_gauss()).collect())
+            .collect();
+        (0..n)
+            .map(|i| {
+                centres[i % centres.len()]
+                    .iter()
+                    .map(|c| c + 0.4 * rng.next_gauss())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn brute_force_matches_full_sort() {
+        let entries: Vec<FaqEntry> = clustered(200, 8, 1)
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| mk_entry(i, v))
+            .collect();
+        let query = clustered(1, 8, 2).remove(0);
+        let mut expected: Vec<(usize, f32)> = entries
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (i, cosine_similarity(&query, &e.embedding)))
+            .collect();
+        expected.sort_by(|a, b| b.1.total_cmp(&a.1));
+        expected.truncate(10);
+
+        assert_eq!(BruteForceIndex::new(&entries).search(&query, 10), expected);
+        assert_eq!(
+            BruteForceIndex::new(&entries).search(&query, 500).len(),
+            200
+        );
+    }
+
+    #[test]
+    fn hnsw_recall_against_brute_force() {
+        let entries: Vec<FaqEntry> = clustered(3000, 32, 3)
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| mk_entry(i, v))
+            .collect();
+        let queries = clustered(100, 32, 4);
+        let hnsw = HnswIndex::build(&entries, HnswParams::default()).expect("build");
+        let exact = BruteForceIndex::new(&entries);
+
+        let recall = recall_at_k(&exact, &hnsw, &queries, 10);
+        assert!(recall >= 0.95, "recall@10 = {recall}");
+        // A stored entry finds itself.
+        let (best, score) = hnsw.search(&entries[42].embedding, 1)[0];
+        assert_eq!(best, 42);
+        assert!((score - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn hnsw_roundtrip_and_stale_detection() {
+        let mut entries: Vec<FaqEntry> = clustered(300, 16, 5)
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| mk_entry(i, v))
+            .collect();
+        let index = HnswIndex::build(&entries, HnswParams::default()).expect("build");
+        let path = std::env::temp_dir().join(format!("faq_hnsw_{}.hnsw.json", std::process::id()));
+        index.save(&path).expect("save");
+
+        let loaded = HnswIndex::load(&path, &entries).expect("load");
+        let query = clustered(1, 16, 6).remove(0);
+        assert_eq!(loaded.search(&query, 5), index.search(&query, 5));
+
+        entries[7].embedding[0] += 1.0;
+        let err = HnswIndex::load(&path, &entries).unwrap_err();
+        assert!(
+            err.to_string().contains("built from other entries"),
+            "{err}"
+        );
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn hnsw_load_rejects_a_corrupt_graph() {
+        let entries: Vec<FaqEntry> = clustered(50, 8, 9)
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| mk_entry(i, v))
+            .collect();
+        let mut index = HnswIndex::build(&entries, HnswParams::default()).expect("build");
+        index.links[3][0][0] = 50;
+        let path =
+            std::env::temp_dir().join(format!("faq_hnsw_corrupt_{}.hnsw.json", std::process::id()));
+        index.save(&path).expect("save");
+
+        let err = HnswIndex::load(&path, &entries).unwrap_err();
+        std::fs::remove_file(&path).ok();
+        assert!(format!("{err:#}").contains("out of range"), "{err:#}");
+    }
+
+    #[test]
+    fn hnsw_path_sits_next_to_the_jsonl_index() {
+        assert_eq!(
+            hnsw_path(Path::new("bench/index_hash.jsonl")),
+            PathBuf::from("bench/index_hash.hnsw.json")
+        );
+    }
+}